@@ -1,4 +1,13 @@
-use crate::tokens::Token;
+use crate::{span::Span, tokens::Token, types::Type};
+
+/// Where a variable lives, filled in by the resolver before compiling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+    /// Offset from the base of the current call frame.
+    Local(u32),
+    /// Index into `Vm::globals`.
+    Global(u32),
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue {
@@ -9,14 +18,57 @@ pub enum LiteralValue {
     Nil,
 }
 
+/// One case of an enum, such as `Walking(speed: float)`. `fields` is empty for `Idle`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<(String, Type)>,
+}
+
+/// What a `case` of a `match` compares the value against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// `3`, `-1.5`, `"idle"`, `true`
+    Literal(LiteralValue),
+    /// `Idle` or `State.Walking(speed)`. Each binding names a field of the variant
+    /// in order and gets a local slot; `_` skips a field.
+    Variant {
+        type_name: Option<String>,
+        variant: String,
+        bindings: Vec<(String, Option<Slot>)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    pub body: Vec<StmtNode>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct StmtNode {
     pub stmt: Stmt,
-    pub line: usize,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    /// Where the expression starts, or its operator for binary and logical ones.
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
     Binary {            
         left: Box<Expr>,
         op: Token,
@@ -25,7 +77,11 @@ pub enum Expr {
     Grouping(Box<Expr>),
 
     Literal(LiteralValue),
-    Variable(String),
+    /// `slot` stays `None` for names that are not variables, like the callee of a call.
+    Variable {
+        name: String,
+        slot: Option<Slot>,
+    },
 
     Unary {
         operator: Token,
@@ -34,9 +90,24 @@ pub enum Expr {
 
     Assign {
         name: String,
-        value: Box<Expr>
+        value: Box<Expr>,
+        slot: Option<Slot>,
+    },
+
+    /// `value as type`
+    Cast {
+        value: Box<Expr>,
+        target: Type,
     },
 
+    /// `list[start:end]`, where either bound may be left out.
+    Slice {
+        list: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+
+    /// `and`, `or` and `??`, which only evaluate `right` when `left` does not settle the result.
     Logical {
         left: Box<Expr>,
         operator: Token,
@@ -48,8 +119,18 @@ pub enum Expr {
         paren: Token,
         arguments: Vec<Expr>,
     },
+    /// `fn(x: int) -> int ... end`. `captures` lists the variables of enclosing
+    /// functions that the body uses and where they live, filled in by the resolver.
+    Lambda {
+        params: Vec<(String, Type)>,
+        return_type: Option<Box<Type>>,
+        body: Vec<StmtNode>,
+        captures: Vec<(String, Option<Slot>)>,
+    },
 
     List(Vec<Expr>),
+    /// `{ key: value, ... }`
+    Map(Vec<(Expr, Expr)>),
     Index {
         list: Box<Expr>,
         index: Box<Expr>,
@@ -64,11 +145,31 @@ pub enum Expr {
         object: Box<Expr>,
         field: String,
     },
+    /// `object?.name` reads a field and `object?.name(args)` calls a method, unless
+    /// `object` is nil, which the whole expression then evaluates to.
+    SafeMember {
+        object: Box<Expr>,
+        name: String,
+        args: Option<Vec<Expr>>,
+    },
     FieldSet {
         object: Box<Expr>,
         field: String,
         value: Box<Expr>,
-    }
+    },
+    /// `list[index] = value` where `list` is more than a variable, as in `players[0].bag[1] = x`.
+    IndexSet {
+        list: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    /// `target op= value` where `target` is an element or a field; its indices are
+    /// evaluated once for both the read and the store.
+    CompoundAssign {
+        target: Box<Expr>,
+        op: Token,
+        value: Box<Expr>,
+    },
 
 }
 
@@ -80,8 +181,9 @@ pub enum Stmt {
 
     Let {
         name: String,
-        data_type: Token,
-        value: Expr
+        data_type: Type,
+        value: Expr,
+        slot: Option<Slot>,
     },
     Print(Vec<Expr>),
     Block(Vec<StmtNode>),
@@ -90,19 +192,37 @@ pub enum Stmt {
         then_branch: Box<StmtNode>,
         else_branch: Option<Box<StmtNode>>,
     },
+    /// `label` names the loop for `break label` and `continue label`.
     While {
         condition: Expr,
         body: Box<StmtNode>,
+        label: Option<String>,
     },
+    /// The loop variable gets `slot`; the three local slots after it hold the
+    /// hidden counter, limit and step. Without a `step` the loop counts up by one.
     For {
         var_name: String,
         start_value: Expr,
         end_value: Expr,
+        step: Option<Expr>,
         body: Box<StmtNode>,
+        slot: Option<Slot>,
+        label: Option<String>,
+    },
+    /// `for item in items` or `for key, item in items`. The loop variables start
+    /// at `slot`; the two local slots after them hold the hidden collection and position.
+    ForEach {
+        key_name: Option<String>,
+        var_name: String,
+        iterable: Expr,
+        body: Box<StmtNode>,
+        slot: Option<Slot>,
+        label: Option<String>,
     },
     Func {
         name: String,
-        params: Vec<(String, Token)>,
+        params: Vec<(String, Type)>,
+        return_type: Option<Type>,
         body: Vec<StmtNode>,
     },
 
@@ -116,14 +236,43 @@ pub enum Stmt {
         body: Vec<StmtNode>
     },
 
+    /// `enum State Idle Walking(speed: float) end`
+    Enum {
+        name: String,
+        variants: Vec<Variant>,
+    },
+    /// Runs the first case whose pattern matches `value`, which is kept in the local `slot`.
+    Match {
+        value: Expr,
+        cases: Vec<MatchCase>,
+        slot: Option<Slot>,
+    },
+    /// Runs `body`, and `handler` if it raises a runtime error. The message of the
+    /// error is kept in the local `slot` when the `catch` names it.
+    Try {
+        body: Vec<StmtNode>,
+        error_name: Option<String>,
+        handler: Vec<StmtNode>,
+        slot: Option<Slot>,
+    },
+
     Return {
         keyword: Token,
         value: Option<Expr>,
     },
+    /// Leaves the innermost loop, or the one named by `label`.
+    Break {
+        label: Option<String>,
+    },
+    /// Starts the next iteration of the innermost loop, or the one named by `label`.
+    Continue {
+        label: Option<String>,
+    },
     ListAssign {
         list_name: String,
         indices: Vec<Expr>, 
-        value: Expr 
+        value: Expr,
+        slot: Option<Slot>,
     },
 
     Import {
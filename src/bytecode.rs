@@ -0,0 +1,194 @@
+use crate::{ast::Slot, checker::FnSignature, error_handler::ParseError, span::Span, types::Type, value::Value};
+
+/// One step of a place expression such as `grid[y][x]` or `p.x`.
+/// Index keys are evaluated at runtime and taken from the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    Index,
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Pop,
+    Dup,
+    /// Pushes copies of the top this many values, in the same order.
+    DupMany(u32),
+
+    GetLocal(u32),
+    SetLocal(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    /// Reads `slot` followed by the accesses in `chunk.paths[path]` without copying the root.
+    GetPath(Slot, u32),
+    /// Writes the top of the stack into `slot` followed by `chunk.paths[path]`.
+    SetPath(Slot, u32),
+    GetIndex,
+    GetField(u32),
+
+    /// Errors unless the top of the stack matches `chunk.types[index]`.
+    CheckType(u32),
+    /// Converts the top of the stack to `chunk.types[index]`.
+    Cast(u32),
+    /// Errors unless the top of the stack has the same kind as the value in `slot`.
+    /// The operand names the variable for the error message.
+    CheckAssign(Slot, u32),
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Division rounded down; with `Modulo`, `a == (a // b) * b + a % b`.
+    FloorDivide,
+    /// Remainder with the sign of the divisor.
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Negate,
+    Not,
+    BitNot,
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+
+    Jump(u32),
+    /// Pops a boolean condition and jumps when it is false.
+    JumpIfFalse(u32),
+    /// Short-circuit for `or`: jumps keeping `true` on the stack, otherwise pops it.
+    Or(u32),
+    /// Short-circuit for `and`: jumps keeping `false` on the stack, otherwise pops it.
+    And(u32),
+    /// Errors unless the top of the stack is a boolean.
+    AssertBool,
+    /// Jumps keeping a `nil` on the stack; anything else stays for the code that follows.
+    JumpIfNil(u32),
+    /// `??`: jumps keeping a value that is not `nil`, otherwise pops the `nil`.
+    Coalesce(u32),
+    /// Steps a counting loop whose variable is local `.0`, followed by its
+    /// counter, limit and step. Jumps to `.1` once the counter passes the limit.
+    ForRange(u32, u32),
+    /// Steps a for-each loop whose variables start at local `.0`, followed by the
+    /// collection and position. Jumps to `.1` once every item was visited.
+    /// `.2` is set when the loop binds a key (or index) as well as the item.
+    ForEach(u32, u32, bool),
+
+    /// Calls `Vm::functions[index]` with the given number of arguments.
+    Call(u32, u8),
+    /// Calls the function value sitting below the arguments.
+    CallValue(u8),
+    /// Calls a built-in or host function by the name stored in the constant pool.
+    CallNative(u32, u8),
+    /// Calls a method on a temporary receiver sitting below the arguments.
+    Invoke(u32, u8),
+    /// Calls a method on a place; changes made to the receiver are written back.
+    InvokePath(Slot, u32, u32, u8),
+
+    MakeList(u32),
+    /// Builds a map from this many key/value pairs, keys below their values.
+    MakeMap(u32),
+    /// Slices a list or string; the flags tell whether a start and an end bound
+    /// were pushed after it.
+    Slice(bool, bool),
+    /// Makes a function value of `Vm::functions[index]`, capturing this many values from the stack.
+    MakeClosure(u32, u8),
+    /// Builds an instance of the struct named in the constant pool.
+    MakeStruct(u32, u8),
+    /// Builds the variant named by constant `.1` of the enum named by constant `.0`
+    /// from this many payload values.
+    MakeVariant(u32, u32, u8),
+    /// Pops an enum value and pushes whether it is the variant named in the constant pool.
+    IsVariant(u32),
+    /// Pops an enum value and pushes the payload value at this position.
+    Payload(u8),
+    /// Pops the value of a `match` that no case matched and stops with an error.
+    NoMatch,
+    /// Enters a `try` block: a runtime error until the matching `PopHandler` unwinds
+    /// to here and jumps to the `catch` at the target, with the message pushed.
+    PushHandler(u32),
+    PopHandler,
+
+    Print(u8),
+    Return,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub types: Vec<Type>,
+    pub paths: Vec<Vec<Access>>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> u32 {
+        if let Some(index) = self.constants.iter().position(|c| *c == value) {
+            return index as u32;
+        }
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    pub fn add_type(&mut self, ty: Type) -> u32 {
+        self.types.push(ty);
+        (self.types.len() - 1) as u32
+    }
+
+    pub fn add_path(&mut self, path: Vec<Access>) -> u32 {
+        self.paths.push(path);
+        (self.paths.len() - 1) as u32
+    }
+}
+
+/// A compiled function. Parameters occupy the first local slots
+/// (after `self` for methods), followed by the values a lambda captured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// `name`, `alias::name` in a module, `Type.name` for methods.
+    pub name: String,
+    /// Alias of the module that defined the function.
+    pub module: Option<String>,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Option<Type>,
+    pub is_method: bool,
+    /// Number of values a lambda captures from its surrounding scope.
+    pub captures: usize,
+    pub locals: usize,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    pub fn arity(&self) -> usize {
+        self.params.len() + self.is_method as usize + self.captures
+    }
+
+    pub fn signature(&self) -> FnSignature {
+        FnSignature { params: self.params.clone(), return_type: self.return_type.clone() }
+    }
+
+    /// Verifies a returned value against the declared return type, if any.
+    pub fn check_return(&self, value: &Value, span: &Span) -> Result<(), ParseError> {
+        match &self.return_type {
+            Some(return_type) if !return_type.matches(value) => Err(ParseError::TypeMismatch {
+                expected: return_type.clone(),
+                found: value.clone(),
+                span: span.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
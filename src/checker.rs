@@ -0,0 +1,2250 @@
+use std::{collections::{HashMap, HashSet}, fs};
+
+use crate::{ast::{Expr, ExprKind, LiteralValue, MatchCase, Pattern, Stmt, StmtNode, Variant}, error_handler::ParseError, lexer, native_functions, parser::Parser, span::{SourceFile, Span}, tokens::Token, types::Type};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnSignature {
+    pub params: Vec<(String, Type)>,
+    pub return_type: Option<Type>,
+}
+
+/// Walks the parsed program before it is executed and collects every type error
+/// it can find, instead of stopping at the first one like the interpreter does.
+#[derive(Debug, Clone)]
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Type>>,
+    functions: HashMap<String, FnSignature>,
+    structs: HashMap<String, Vec<(String, Type)>>,
+    enums: HashMap<String, Vec<Variant>>,
+    methods: HashMap<String, HashMap<String, FnSignature>>,
+    natives: HashSet<String>,
+    modules: HashSet<String>,
+    /// Paths of the files already imported, so each is checked once.
+    imported: HashSet<String>,
+    /// Declared return type of each function currently being checked.
+    returns: Vec<Option<Type>>,
+    /// Optional variables known not to be nil in the branch being checked, with
+    /// the index of the scope that holds their narrowed type.
+    narrowed: Vec<(String, usize)>,
+    /// Scopes pushed to narrow the rest of a block after an `if` that leaves it,
+    /// such as `if x == nil return end`. They hold only narrowed types.
+    exit_scopes: Vec<usize>,
+    errors: Vec<ParseError>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            methods: HashMap::new(),
+            natives: HashSet::new(),
+            modules: HashSet::new(),
+            imported: HashSet::new(),
+            returns: Vec::new(),
+            narrowed: Vec::new(),
+            exit_scopes: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn declare_global(&mut self, name: &str, ty: Type) {
+        self.scopes[0].insert(name.to_string(), ty);
+    }
+
+    /// Types of the globals known after `check`, including those it declared.
+    pub fn globals(&self) -> &HashMap<String, Type> {
+        &self.scopes[0]
+    }
+
+    pub fn declare_function(&mut self, name: &str, signature: FnSignature) {
+        self.functions.insert(name.to_string(), signature);
+    }
+
+    pub fn declare_struct(&mut self, name: &str, fields: Vec<(String, Type)>) {
+        self.structs.insert(name.to_string(), fields);
+    }
+
+    pub fn declare_enum(&mut self, name: &str, variants: Vec<Variant>) {
+        self.enums.insert(name.to_string(), variants);
+    }
+
+    pub fn declare_method(&mut self, type_name: &str, name: &str, signature: FnSignature) {
+        self.methods.entry(type_name.to_string()).or_default().insert(name.to_string(), signature);
+    }
+
+    pub fn declare_native(&mut self, name: &str) {
+        self.natives.insert(name.to_string());
+    }
+
+    pub fn declare_module(&mut self, directory: &str, alias: &str) {
+        self.imported.insert(directory.to_string());
+        self.modules.insert(alias.to_string());
+    }
+
+    pub fn check(&mut self, program: &[StmtNode]) -> Result<(), Vec<ParseError>> {
+        self.declare_items(program);
+        self.check_statements(program);
+
+        // Function bodies run later, when every global they may touch already exists.
+        self.check_bodies(program);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn error(&mut self, message: String, span: &Span) {
+        self.errors.push(ParseError::TypeError { message, span: span.clone() });
+    }
+
+    /// Registers functions, structs, impls and imports up front so that they can be
+    /// used before the statement declaring them.
+    fn declare_items(&mut self, program: &[StmtNode]) {
+        for node in program {
+            match &node.stmt {
+                Stmt::Func { name, params, return_type, .. } => {
+                    self.functions.insert(name.clone(), FnSignature { params: params.clone(), return_type: return_type.clone() });
+                }
+                Stmt::Struct { name, body } => {
+                    let fields = body.iter().filter_map(|field| match &field.stmt {
+                        Stmt::Let { name, data_type, .. } => Some((name.clone(), data_type.clone())),
+                        _ => None,
+                    }).collect();
+                    self.structs.insert(name.clone(), fields);
+                }
+                Stmt::Enum { name, variants } => {
+                    self.enums.insert(name.clone(), variants.clone());
+                }
+                Stmt::Impl { name, body } => {
+                    for method in body {
+                        if let Stmt::Func { name: method_name, params, return_type, .. } = &method.stmt {
+                            self.declare_method(name, method_name, FnSignature { params: params.clone(), return_type: return_type.clone() });
+                        }
+                    }
+                }
+                Stmt::Import { directory, identifier } => {
+                    self.modules.insert(identifier.clone());
+                    if self.imported.insert(directory.clone()) {
+                        self.check_module(directory, identifier);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for node in program {
+            match &node.stmt {
+                Stmt::Struct { body, .. } => {
+                    for field in body {
+                        if let Stmt::Let { data_type, .. } = &field.stmt {
+                            self.check_type_exists(data_type, &field.span);
+                        }
+                    }
+                }
+                Stmt::Enum { variants, .. } => {
+                    for (_, field_type) in variants.iter().flat_map(|variant| &variant.fields) {
+                        self.check_type_exists(field_type, &node.span);
+                    }
+                }
+                Stmt::Func { name, params, return_type, .. } => {
+                    self.check_function_name(name, &node.span);
+                    for (_, param_type) in params {
+                        self.check_type_exists(param_type, &node.span);
+                    }
+                    if let Some(return_type) = return_type {
+                        self.check_type_exists(return_type, &node.span);
+                    }
+                }
+                Stmt::Impl { name, .. } if !self.structs.contains_key(name) && !self.enums.contains_key(name) => {
+                    self.error(format!("Cannot implement methods for unknown type '{}'", name), &node.span);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Checks an imported file and registers what it defines under `alias::`. A file
+    /// that cannot be read or parsed is left for the compiler to report.
+    fn check_module(&mut self, directory: &str, alias: &str) {
+        let Ok(source) = fs::read_to_string(directory) else {
+            return;
+        };
+        let Ok(tokens) = lexer::tokenize(&SourceFile::new(directory, &source)) else {
+            return;
+        };
+        let Ok(program) = Parser::new(tokens).parse_program() else {
+            return;
+        };
+
+        // The module sees what is already defined, but none of the importer's locals.
+        let mut module = TypeChecker {
+            scopes: vec![self.scopes[0].clone()],
+            returns: Vec::new(),
+            narrowed: Vec::new(),
+            exit_scopes: Vec::new(),
+            errors: Vec::new(),
+            ..self.clone()
+        };
+        if let Err(mut errors) = module.check(&program) {
+            self.errors.append(&mut errors);
+        }
+        self.imported.extend(module.imported.iter().cloned());
+
+        let defined: HashSet<&str> = program.iter().filter_map(|node| match &node.stmt {
+            Stmt::Struct { name, .. } | Stmt::Enum { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        let qualify = |ty: &Type| qualify(ty, alias, &defined);
+        let qualify_signature = |signature: &FnSignature| FnSignature {
+            params: signature.params.iter().map(|(name, ty)| (name.clone(), qualify(ty))).collect(),
+            return_type: signature.return_type.as_ref().map(qualify),
+        };
+        for node in &program {
+            match &node.stmt {
+                Stmt::Func { name, .. } => {
+                    let signature = qualify_signature(&module.functions[name]);
+                    self.functions.insert(format!("{}::{}", alias, name), signature);
+                }
+                Stmt::Struct { name, .. } => {
+                    let fields = module.structs[name].iter().map(|(field, ty)| (field.clone(), qualify(ty))).collect();
+                    self.structs.insert(format!("{}::{}", alias, name), fields);
+                }
+                Stmt::Enum { name, .. } => {
+                    let variants = module.enums[name].iter().map(|variant| Variant {
+                        name: variant.name.clone(),
+                        fields: variant.fields.iter().map(|(field, ty)| (field.clone(), qualify(ty))).collect(),
+                    }).collect();
+                    self.enums.insert(format!("{}::{}", alias, name), variants);
+                }
+                Stmt::Impl { name, .. } => {
+                    for (method, signature) in module.methods.get(name).into_iter().flatten() {
+                        self.declare_method(&format!("{}::{}", alias, name), method, qualify_signature(signature));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_bodies(&mut self, program: &[StmtNode]) {
+        for node in program {
+            match &node.stmt {
+                Stmt::Func { .. } => self.check_function(node, None),
+                Stmt::Impl { name, body } => {
+                    for method in body {
+                        self.check_function(method, Some(name));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_function(&mut self, node: &StmtNode, self_type: Option<&String>) {
+        let Stmt::Func { name, params, return_type, body } = &node.stmt else {
+            return;
+        };
+
+        // Functions only see globals, not the scope they are declared in.
+        let saved = self.scopes.split_off(1);
+
+        let mut scope: HashMap<String, Type> = params.iter().cloned().collect();
+        if let Some(type_name) = self_type {
+            scope.insert("self".to_string(), Type::Named(type_name.clone()));
+        }
+        self.scopes.push(scope);
+        self.returns.push(return_type.clone());
+        self.check_statements(body);
+        self.returns.pop();
+        self.check_bodies(body);
+
+        self.scopes.truncate(1);
+        self.scopes.extend(saved);
+
+        if let Some(return_type) = return_type
+            && !always_returns(body) {
+            self.error(format!("Function '{}' may finish without returning {}", name, return_type), &node.span);
+        }
+    }
+
+    /// Calls to a built-in name always reach the built-in, so a script function
+    /// with that name could never be called.
+    fn check_function_name(&mut self, name: &str, span: &Span) {
+        if native_functions::is_builtin(name) {
+            self.error(format!("Cannot declare function '{}': the name belongs to a built-in function", name), span);
+        }
+    }
+
+    fn check_type_exists(&mut self, ty: &Type, span: &Span) {
+        match ty {
+            Type::List(inner) => self.check_type_exists(inner, span),
+            Type::Option(inner) => self.check_type_exists(inner, span),
+            Type::Map(key, value) => {
+                if !key.is_key() {
+                    self.error(format!("Map keys must be int, string or bool, found {}", key), span);
+                }
+                self.check_type_exists(key, span);
+                self.check_type_exists(value, span);
+            }
+            Type::Named(name) if !name.contains("::") && !self.structs.contains_key(name) && !self.enums.contains_key(name) => {
+                self.error(format!("Unknown type '{}'", name), span);
+            }
+            Type::Function(params, return_type) => {
+                for param in params {
+                    self.check_type_exists(param, span);
+                }
+                if let Some(return_type) = return_type {
+                    self.check_type_exists(return_type, span);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn check_stmt(&mut self, node: &StmtNode) {
+        let span = &node.span;
+        match &node.stmt {
+            Stmt::Expression(expr) => {
+                self.expr_type(expr);
+            }
+
+            Stmt::Print(exprs) => {
+                for expr in exprs {
+                    self.expr_type(expr);
+                }
+            }
+
+            Stmt::Let { name, data_type, value, .. } => {
+                self.check_type_exists(data_type, span);
+                let value_type = self.expr_type(value);
+                if !data_type.accepts(&value_type) {
+                    self.error(format!("Cannot assign {} to '{}' of type {}", value_type, name, data_type), &value.span);
+                }
+                self.declare(name, data_type.clone());
+            }
+
+            Stmt::ListAssign { list_name, indices, value, .. } => {
+                let mut target = self.lookup(list_name).cloned().unwrap_or(Type::Any);
+                for index in indices {
+                    if target == Type::String {
+                        self.error(format!("Cannot assign to a character of '{}'; strings cannot be changed in place", list_name), span);
+                    }
+                    target = self.element_type(&target, index).unwrap_or_else(|| {
+                        self.error(format!("Cannot index into '{}' of type {}", list_name, target), span);
+                        Type::Any
+                    });
+                }
+                let value_type = self.expr_type(value);
+                if !target.accepts(&value_type) {
+                    self.error(format!("Cannot assign {} to an element of type {}", value_type, target), span);
+                }
+            }
+
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                self.check_statements(statements);
+                self.scopes.pop();
+            }
+
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.check_condition(condition, "if");
+                let (then_scope, else_scope) = self.nil_checks(condition);
+                self.check_narrowed(then_scope, then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_narrowed(else_scope, else_branch);
+                }
+            }
+
+            Stmt::While { condition, body, .. } => {
+                self.forget_reset_in_loop(body);
+                self.check_condition(condition, "while");
+                // The condition is tested again before each run of the body
+                let (scope, _) = self.nil_checks(condition);
+                self.check_narrowed(scope, body);
+            }
+
+            Stmt::For { var_name, start_value, end_value, step, body, .. } => {
+                for bound in [Some(start_value), Some(end_value), step.as_ref()].into_iter().flatten() {
+                    let bound_type = self.expr_type(bound);
+                    if !Type::Int.accepts(&bound_type) {
+                        self.error(format!("For loop bounds must be int, found {}", bound_type), &bound.span);
+                    }
+                }
+                self.scopes.push(HashMap::from([(var_name.clone(), Type::Int)]));
+                self.forget_reset_in_loop(body);
+                self.check_stmt(body);
+                self.scopes.pop();
+            }
+
+            Stmt::ForEach { key_name, var_name, iterable, body, .. } => {
+                let iterable_type = self.expr_type(iterable);
+                let (key_type, item_type) = match iterable_type {
+                    Type::List(element) => (Type::Int, *element),
+                    Type::String => (Type::Int, Type::String),
+                    Type::Map(key, _) if key_name.is_none() => (Type::Any, *key),
+                    Type::Map(key, value) => (*key, *value),
+                    Type::Any => (Type::Any, Type::Any),
+                    other => {
+                        self.error(format!("Cannot iterate over a value of type {}", other), &iterable.span);
+                        (Type::Any, Type::Any)
+                    }
+                };
+                let mut scope = HashMap::from([(var_name.clone(), item_type)]);
+                if let Some(key_name) = key_name {
+                    scope.insert(key_name.clone(), key_type);
+                }
+                self.scopes.push(scope);
+                self.forget_reset_in_loop(body);
+                self.check_stmt(body);
+                self.scopes.pop();
+            }
+
+            Stmt::Return { value, .. } => {
+                let value_type = value.as_ref().map(|value| self.expr_type(value));
+                let Some(Some(return_type)) = self.returns.last().cloned() else {
+                    return;
+                };
+                match value_type {
+                    Some(value_type) if !return_type.accepts(&value_type) => {
+                        self.error(format!("Expected to return {} but found {}", return_type, value_type), span);
+                    }
+                    None => self.error(format!("Missing return value of type {}", return_type), span),
+                    _ => {}
+                }
+            }
+
+            Stmt::Match { value, cases, .. } => self.check_match(value, cases, span),
+
+            // The caught error is its message
+            Stmt::Try { body, error_name, handler, .. } => {
+                self.scopes.push(HashMap::new());
+                self.check_statements(body);
+                self.scopes.pop();
+
+                let scope = error_name.iter().map(|name| (name.clone(), Type::String)).collect();
+                self.scopes.push(scope);
+                self.check_statements(handler);
+                self.scopes.pop();
+            }
+
+            Stmt::Enum { .. } | Stmt::Struct { .. } | Stmt::Impl { .. } | Stmt::Import { .. } | Stmt::Break { .. } | Stmt::Continue { .. } => {}
+
+            Stmt::Func { name, params, return_type, .. } => {
+                // Nested declarations are registered when they are reached.
+                self.check_function_name(name, span);
+                self.functions.insert(name.clone(), FnSignature { params: params.clone(), return_type: return_type.clone() });
+            }
+        }
+    }
+
+    /// Checks each case against the type of `value` and that together they cover
+    /// every value it can hold: all variants of an enum, both bools, or `case _`.
+    fn check_match(&mut self, value: &Expr, cases: &[MatchCase], span: &Span) {
+        let value_type = self.expr_type(value);
+        let variants = match &value_type {
+            Type::Named(name) => self.enums.get(name).cloned(),
+            _ => None,
+        };
+
+        let mut covered: Vec<String> = Vec::new();
+        let mut wildcard = false;
+        for case in cases {
+            if wildcard {
+                self.error("Unreachable case: an earlier `case _` matches everything".to_string(), &case.span);
+            }
+            let mut scope = HashMap::new();
+            match &case.pattern {
+                Pattern::Wildcard => {
+                    let exhausted = match (&variants, &value_type) {
+                        (Some(variants), Type::Named(_)) => variants.iter().all(|variant| covered.contains(&variant.name)),
+                        (_, Type::Bool) => ["true", "false"].iter().all(|b| covered.iter().any(|covered| covered == b)),
+                        _ => false,
+                    };
+                    if exhausted {
+                        self.error("Unreachable case: the cases above already match every value".to_string(), &case.span);
+                    }
+                    wildcard = true;
+                }
+                Pattern::Literal(literal) => {
+                    let literal_type = match literal {
+                        LiteralValue::Int(_) => Type::Int,
+                        LiteralValue::Float(_) => Type::Float,
+                        LiteralValue::Str(_) => Type::String,
+                        LiteralValue::Bool(_) => Type::Bool,
+                        LiteralValue::Nil => Type::Nil,
+                    };
+                    let numeric = |ty: &Type| matches!(ty, Type::Int | Type::Float);
+                    let comparable = value_type.accepts(&literal_type) || (numeric(&value_type) && numeric(&literal_type));
+                    if !comparable {
+                        self.error(format!("Cannot match {} against a {} case", value_type, literal_type), &case.span);
+                    }
+                    if let LiteralValue::Bool(b) = literal {
+                        covered.push(b.to_string());
+                    }
+                }
+                Pattern::Variant { type_name, variant, bindings } => {
+                    let fields = match (&variants, &value_type) {
+                        (Some(variants), Type::Named(enum_name)) => {
+                            if let Some(type_name) = type_name
+                                && type_name != enum_name {
+                                self.error(format!("Expected a case of {}, found {}.{}", enum_name, type_name, variant), &case.span);
+                            }
+                            match variants.iter().find(|v| v.name == *variant) {
+                                Some(found) => {
+                                    if covered.contains(variant) {
+                                        self.error(format!("Unreachable case: {} is already matched", variant), &case.span);
+                                    }
+                                    covered.push(variant.clone());
+                                    found.fields.iter().map(|(_, ty)| ty.clone()).collect()
+                                }
+                                None => {
+                                    self.error(format!("Enum '{}' has no variant '{}'", enum_name, variant), &case.span);
+                                    vec![Type::Any; bindings.len()]
+                                }
+                            }
+                        }
+                        (_, Type::Any) => vec![Type::Any; bindings.len()],
+                        // Enums coming from imported modules are not known statically.
+                        (_, Type::Named(name)) if !self.structs.contains_key(name) => vec![Type::Any; bindings.len()],
+                        (_, other) => {
+                            self.error(format!("Cannot match {} against variant '{}'", other, variant), &case.span);
+                            vec![Type::Any; bindings.len()]
+                        }
+                    };
+                    if fields.len() != bindings.len() {
+                        self.error(format!("Variant '{}' holds {} values but the case binds {}", variant, fields.len(), bindings.len()), &case.span);
+                    }
+                    for ((name, _), ty) in bindings.iter().zip(fields) {
+                        if name != "_" {
+                            scope.insert(name.clone(), ty);
+                        }
+                    }
+                }
+            }
+            self.scopes.push(scope);
+            self.check_statements(&case.body);
+            self.scopes.pop();
+        }
+
+        if wildcard {
+            return;
+        }
+        match (&variants, &value_type) {
+            (Some(variants), Type::Named(enum_name)) => {
+                let missing: Vec<&str> = variants.iter()
+                    .filter(|variant| !covered.contains(&variant.name))
+                    .map(|variant| variant.name.as_str())
+                    .collect();
+                if !missing.is_empty() {
+                    self.error(format!("match on {} does not cover {}", enum_name, missing.join(", ")), span);
+                }
+            }
+            (_, Type::Bool) => {
+                for b in ["true", "false"] {
+                    if !covered.iter().any(|covered| covered == b) {
+                        self.error(format!("match on bool does not cover {}", b), span);
+                    }
+                }
+            }
+            (_, Type::Any) => {}
+            (_, Type::Named(name)) if !self.structs.contains_key(name) => {}
+            (_, other) => self.error(format!("match on {} needs a `case _` to cover every value", other), span),
+        }
+    }
+
+    /// Optional variables that `condition` proves are not nil when it holds
+    /// (`x != nil`, also joined with `and`) and when it fails (`x == nil`, also
+    /// joined with `or`).
+    fn nil_checks(&self, condition: &Expr) -> (HashMap<String, Type>, HashMap<String, Type>) {
+        let mut when_true = HashMap::new();
+        let mut when_false = HashMap::new();
+        match &condition.kind {
+            ExprKind::Grouping(inner) => return self.nil_checks(inner),
+            ExprKind::Logical { left, operator: Token::And, right } => {
+                when_true = self.nil_checks(left).0;
+                when_true.extend(self.nil_checks(right).0);
+            }
+            ExprKind::Logical { left, operator: Token::Or, right } => {
+                when_false = self.nil_checks(left).1;
+                when_false.extend(self.nil_checks(right).1);
+            }
+            ExprKind::Binary { left, op: op @ (Token::Equals | Token::NotEquals), right } => {
+                let variable = match (&left.kind, &right.kind) {
+                    (ExprKind::Variable { name, .. }, ExprKind::Literal(LiteralValue::Nil))
+                    | (ExprKind::Literal(LiteralValue::Nil), ExprKind::Variable { name, .. }) => Some(name),
+                    _ => None,
+                };
+                if let Some(name) = variable
+                    && let Some(Type::Option(inner)) = self.lookup(name) {
+                    let scope = if *op == Token::NotEquals { &mut when_true } else { &mut when_false };
+                    scope.insert(name.clone(), (**inner).clone());
+                }
+            }
+            _ => {}
+        }
+        (when_true, when_false)
+    }
+
+    /// Checks a branch in which the variables of `scope` hold a value rather than nil.
+    fn check_narrowed(&mut self, scope: HashMap<String, Type>, branch: &StmtNode) {
+        let depth = self.scopes.len();
+        self.narrowed.extend(scope.keys().map(|name| (name.clone(), depth)));
+        self.scopes.push(scope);
+        self.check_stmt(branch);
+        self.scopes.pop();
+        self.narrowed.retain(|(_, at)| *at < depth);
+    }
+
+    /// The type of the right operand of `and` or `or`, which only runs when the
+    /// left one leaves the variables of `scope` holding a value.
+    fn narrowed_expr_type(&mut self, scope: HashMap<String, Type>, expr: &Expr) -> Type {
+        let depth = self.scopes.len();
+        self.narrowed.extend(scope.keys().map(|name| (name.clone(), depth)));
+        self.scopes.push(scope);
+        let ty = self.expr_type(expr);
+        self.scopes.pop();
+        self.narrowed.retain(|(_, at)| *at < depth);
+        ty
+    }
+
+    /// Checks the statements of a block. After an `if` that leaves the block
+    /// without an `else`, the rest of it runs only when the condition failed.
+    fn check_statements(&mut self, statements: &[StmtNode]) {
+        let depth = self.scopes.len();
+        for node in statements {
+            self.check_stmt(node);
+            if let Stmt::If { condition, then_branch, else_branch: None } = &node.stmt
+                && always_leaves(std::slice::from_ref(then_branch)) {
+                let (_, scope) = self.nil_checks(condition);
+                if !scope.is_empty() {
+                    self.exit_scopes.push(self.scopes.len());
+                    self.narrowed.extend(scope.keys().map(|name| (name.clone(), self.scopes.len())));
+                    self.scopes.push(scope);
+                }
+            }
+        }
+        self.scopes.truncate(depth);
+        self.narrowed.retain(|(_, at)| *at < depth);
+        self.exit_scopes.retain(|at| *at < depth);
+    }
+
+    /// Declares a variable in the innermost block, below any scope that only
+    /// narrows the rest of it. The new variable is not narrowed.
+    fn declare(&mut self, name: &str, ty: Type) {
+        let at = (0..self.scopes.len()).rev().find(|at| !self.exit_scopes.contains(at)).unwrap_or(0);
+        for above in at + 1..self.scopes.len() {
+            self.scopes[above].remove(name);
+        }
+        self.narrowed.retain(|(narrowed, scope)| !(narrowed == name && *scope > at));
+        self.scopes[at].insert(name.to_string(), ty);
+    }
+
+    /// Scopes narrowing the variable `name` refers to, innermost first, and
+    /// whether that variable is a global.
+    fn narrowings(&self, name: &str) -> (Vec<usize>, bool) {
+        let mut found = Vec::new();
+        for at in (0..self.scopes.len()).rev() {
+            if !self.scopes[at].contains_key(name) {
+                continue;
+            }
+            if self.narrowed.iter().any(|(narrowed, scope)| narrowed == name && *scope == at) {
+                found.push(at);
+            } else {
+                return (found, at == 0);
+            }
+        }
+        (found, false)
+    }
+
+    /// Stops treating `name` as not nil, e.g. once it is assigned again.
+    fn forget_narrowing(&mut self, name: &str) {
+        for at in self.narrowings(name).0 {
+            self.scopes[at].remove(name);
+            self.narrowed.retain(|(narrowed, scope)| !(narrowed == name && *scope == at));
+        }
+    }
+
+    /// A call may run code that sets any global back to nil.
+    fn forget_narrowed_globals(&mut self) {
+        let names: Vec<String> = self.narrowed.iter().map(|(name, _)| name.clone()).collect();
+        for name in names {
+            if self.narrowings(&name).1 {
+                self.forget_narrowing(&name);
+            }
+        }
+    }
+
+    /// A loop body runs again after its own assignments and calls, so whatever
+    /// they un-narrow is checked as possibly nil from the start.
+    fn forget_reset_in_loop(&mut self, body: &StmtNode) {
+        if self.narrowed.is_empty() {
+            return;
+        }
+        let mut names = HashSet::new();
+        let mut calls = false;
+        self.stmt_resets(body, &mut names, &mut calls);
+        for name in names {
+            self.forget_narrowing(&name);
+        }
+        if calls {
+            self.forget_narrowed_globals();
+        }
+    }
+
+    /// Collects the variables that `node` assigns, and whether it calls script
+    /// code that may assign globals.
+    fn stmt_resets(&self, node: &StmtNode, names: &mut HashSet<String>, calls: &mut bool) {
+        let (exprs, bodies): (Vec<&Expr>, Vec<&StmtNode>) = match &node.stmt {
+            Stmt::Expression(value) | Stmt::Let { value, .. } => (vec![value], vec![]),
+            Stmt::Print(values) => (values.iter().collect(), vec![]),
+            Stmt::Return { value, .. } => (value.iter().collect(), vec![]),
+            Stmt::ListAssign { indices, value, .. } => (indices.iter().chain([value]).collect(), vec![]),
+            Stmt::Block(body) => (vec![], body.iter().collect()),
+            Stmt::If { condition, then_branch, else_branch } => {
+                (vec![condition], [then_branch].into_iter().chain(else_branch).map(|branch| &**branch).collect())
+            }
+            Stmt::While { condition, body, .. } => (vec![condition], vec![body]),
+            Stmt::For { start_value, end_value, step, body, .. } => {
+                ([start_value, end_value].into_iter().chain(step).collect(), vec![body])
+            }
+            Stmt::ForEach { iterable, body, .. } => (vec![iterable], vec![body]),
+            Stmt::Match { value, cases, .. } => (vec![value], cases.iter().flat_map(|case| &case.body).collect()),
+            Stmt::Try { body, handler, .. } => (vec![], body.iter().chain(handler).collect()),
+            // Declarations run nothing where they stand
+            Stmt::Func { .. } | Stmt::Struct { .. } | Stmt::Impl { .. } | Stmt::Enum { .. } | Stmt::Import { .. }
+            | Stmt::Break { .. } | Stmt::Continue { .. } => (vec![], vec![]),
+        };
+        for expr in exprs {
+            self.expr_resets(expr, names, calls);
+        }
+        for body in bodies {
+            self.stmt_resets(body, names, calls);
+        }
+    }
+
+    fn expr_resets(&self, expr: &Expr, names: &mut HashSet<String>, calls: &mut bool) {
+        let inner: Vec<&Expr> = match &expr.kind {
+            ExprKind::Assign { name, value, .. } => {
+                names.insert(name.clone());
+                vec![value]
+            }
+            ExprKind::Call { callee, arguments, .. } => {
+                // Struct constructors, conversions and built-in functions run no script code
+                *calls |= match &callee.kind {
+                    ExprKind::Variable { name, .. } => self.functions.contains_key(name) || self.lookup(name).is_some(),
+                    _ => true,
+                };
+                [&**callee].into_iter().chain(arguments).collect()
+            }
+            ExprKind::MethodCall { object, method, args } => {
+                *calls |= self.may_run_script(object, method);
+                [&**object].into_iter().chain(args).collect()
+            }
+            ExprKind::SafeMember { object, name, args } => {
+                *calls |= args.is_some() && self.may_run_script(object, name);
+                [&**object].into_iter().chain(args.iter().flatten()).collect()
+            }
+            // The body runs when the lambda is called, which counts as a call
+            ExprKind::Lambda { .. } | ExprKind::Literal(_) | ExprKind::Variable { .. } => vec![],
+            ExprKind::Grouping(inner) | ExprKind::Unary { right: inner, .. } | ExprKind::Cast { value: inner, .. }
+            | ExprKind::FieldGet { object: inner, .. } => vec![inner],
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. }
+            | ExprKind::Index { list: left, index: right } | ExprKind::FieldSet { object: left, value: right, .. }
+            | ExprKind::CompoundAssign { target: left, value: right, .. } => vec![left, right],
+            ExprKind::IndexSet { list, index, value } => vec![list, index, value],
+            ExprKind::Slice { list, start, end } => [list].into_iter().chain(start).chain(end).map(|expr| &**expr).collect(),
+            ExprKind::List(elements) => elements.iter().collect(),
+            ExprKind::Map(entries) => entries.iter().flat_map(|(key, value)| [key, value]).collect(),
+        };
+        for expr in inner {
+            self.expr_resets(expr, names, calls);
+        }
+    }
+
+    /// Whether calling `method` on `object` may run script code: a method of a
+    /// struct, a function of a module, a function held in a field, or a list
+    /// method that takes a callback. Anything else is built in.
+    fn may_run_script(&self, object: &Expr, method: &str) -> bool {
+        matches!(method, "map" | "filter" | "reduce" | "any" | "all" | "find" | "sort_by")
+            || self.methods.values().any(|methods| methods.contains_key(method))
+            || self.structs.values().any(|fields| fields.iter().any(|(field, _)| field == method))
+            || matches!(&object.kind, ExprKind::Variable { name, .. } if self.modules.contains(name))
+    }
+
+    fn check_condition(&mut self, condition: &Expr, keyword: &str) {
+        let condition_type = self.expr_type(condition);
+        if !Type::Bool.accepts(&condition_type) {
+            self.error(format!("'{}' condition must be bool, found {}", keyword, condition_type), &condition.span);
+        }
+    }
+
+    fn check_index(&mut self, index: &Expr) {
+        let index_type = self.expr_type(index);
+        if !Type::Int.accepts(&index_type) {
+            self.error(format!("List index must be int, found {}", index_type), &index.span);
+        }
+    }
+
+    fn check_key(&mut self, key_type: &Type, key: &Expr) {
+        let found = self.expr_type(key);
+        if !key_type.accepts(&found) {
+            self.error(format!("Map key must be {}, found {}", key_type, found), &key.span);
+        }
+    }
+
+    /// The type of `container[index]`, or `None` if `container` cannot be indexed.
+    fn element_type(&mut self, container: &Type, index: &Expr) -> Option<Type> {
+        match container {
+            Type::List(inner) => {
+                self.check_index(index);
+                Some((**inner).clone())
+            }
+            Type::Map(key, value) => {
+                self.check_key(key, index);
+                Some((**value).clone())
+            }
+            Type::String => {
+                self.check_index(index);
+                Some(Type::String)
+            }
+            other => {
+                self.expr_type(index);
+                (*other == Type::Any).then_some(Type::Any)
+            }
+        }
+    }
+
+    /// Narrows `current` to the first type found among a literal's elements and
+    /// reports any element that does not fit it. `nil` next to a `T` makes the
+    /// elements `option<T>`.
+    fn unify(&mut self, current: &mut Type, found: Type, what: &str, span: &Span) {
+        let unified = match (current.clone(), found) {
+            (Type::Any, found) => found,
+            (Type::Nil, Type::Nil) => Type::Nil,
+            (Type::Nil, Type::Option(inner)) => Type::Option(inner),
+            (Type::Nil, found) => Type::Option(Box::new(found)),
+            (Type::Option(inner), Type::Nil) => Type::Option(inner),
+            (Type::Option(inner), found) if inner.accepts(&found) => Type::Option(inner),
+            (ty, Type::Nil) => Type::Option(Box::new(ty)),
+            (ty, Type::Option(inner)) if ty.accepts(&inner) => Type::Option(inner),
+            (ty, found) if ty.accepts(&found) => ty,
+            (ty, found) => {
+                self.error(format!("{} must share one type, found {} and {}", what, ty, found), span);
+                ty
+            }
+        };
+        *current = unified;
+    }
+
+    /// Checks each argument against its parameter and returns the argument types.
+    fn check_args(&mut self, name: &str, params: &[(String, Type)], args: &[Expr], span: &Span) -> Vec<Type> {
+        if params.len() != args.len() {
+            self.error(format!("'{}' expects {} args but got {}", name, params.len(), args.len()), span);
+        }
+        let mut arg_types = Vec::with_capacity(args.len());
+        for ((param_name, param_type), arg) in params.iter().zip(args) {
+            let arg_type = self.expr_type(arg);
+            if !param_type.accepts(&arg_type) {
+                self.error(format!("Argument '{}' of '{}' expects {} but got {}", param_name, name, param_type, arg_type), &arg.span);
+            }
+            arg_types.push(arg_type);
+        }
+        for arg in args.iter().skip(params.len()) {
+            arg_types.push(self.expr_type(arg));
+        }
+        arg_types
+    }
+
+    fn expr_type(&mut self, expr: &Expr) -> Type {
+        let span = &expr.span;
+        match &expr.kind {
+            ExprKind::Literal(lit) => match lit {
+                LiteralValue::Int(_) => Type::Int,
+                LiteralValue::Float(_) => Type::Float,
+                LiteralValue::Str(_) => Type::String,
+                LiteralValue::Bool(_) => Type::Bool,
+                LiteralValue::Nil => Type::Nil,
+            },
+
+            ExprKind::Grouping(inner) => self.expr_type(inner),
+
+            ExprKind::Variable { name, .. } => match (self.lookup(name), self.functions.get(name)) {
+                (Some(var_type), _) => var_type.clone(),
+                (None, Some(signature)) => function_type(signature),
+                (None, None) => match conversion_target(name) {
+                    Some(target) => Type::Function(vec![Type::Any], Some(Box::new(target))),
+                    // Host functions declare no types
+                    None => Type::Any,
+                },
+            },
+
+            ExprKind::Assign { name, value, .. } => {
+                let value_type = self.expr_type(value);
+                // A variable known not to be nil may still be set back to nil
+                let narrowed = !self.narrowings(name).0.is_empty();
+                let declared = self.lookup(name).cloned().map(|ty| match narrowed {
+                    true => Type::Option(Box::new(ty)),
+                    false => ty,
+                });
+                if let Some(var_type) = declared
+                    && !var_type.accepts(&value_type) {
+                    self.error(format!("Cannot assign {} to '{}' of type {}", value_type, name, var_type), span);
+                }
+                self.forget_narrowing(name);
+                value_type
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                let left = self.expr_type(left);
+                let right = self.expr_type(right);
+                self.binary_type(&left, op, &right, span)
+            }
+
+            ExprKind::Cast { value, target } => {
+                let found = self.expr_type(value);
+                self.cast_type(&found, target.clone(), span)
+            }
+
+            ExprKind::Unary { operator, right } => {
+                let right = self.expr_type(right);
+                match operator {
+                    Token::Minus if right.is_numeric() => right,
+                    Token::Bang if Type::Bool.accepts(&right) => Type::Bool,
+                    Token::BitNot if Type::Int.accepts(&right) => Type::Int,
+                    _ => {
+                        self.error(format!("Operator '{}' cannot be applied to {}", op_symbol(operator), right), span);
+                        Type::Any
+                    }
+                }
+            }
+
+            ExprKind::Logical { left, operator: Token::NilCoalesce, right } => {
+                let left_type = self.expr_type(left);
+                let right_type = self.expr_type(right);
+                match left_type {
+                    Type::Option(inner) => {
+                        if !inner.accepts(&right_type) && !Type::Option(inner.clone()).accepts(&right_type) {
+                            self.error(format!("'??' needs a default of type {}, found {}", inner, right_type), span);
+                        }
+                        // The default may itself be nil
+                        match right_type {
+                            Type::Option(_) | Type::Nil => Type::Option(inner),
+                            _ => *inner,
+                        }
+                    }
+                    Type::Nil | Type::Any => right_type,
+                    other => {
+                        self.error(format!("Left side of '??' is {}, which is never nil", other), span);
+                        other
+                    }
+                }
+            }
+
+            ExprKind::SafeMember { object, name, args } => {
+                let object_type = match self.expr_type(object) {
+                    Type::Option(inner) => *inner,
+                    other => other,
+                };
+                let member_type = match args {
+                    None => self.field_type(&object_type, name, span),
+                    Some(args) => self.method_type(&object_type, name, args, span),
+                };
+                match member_type {
+                    Type::Option(_) | Type::Nil | Type::Any => member_type,
+                    other => Type::Option(Box::new(other)),
+                }
+            }
+
+            ExprKind::Logical { left, operator, right } => {
+                let left_type = self.expr_type(left);
+                // `x != nil and x > 0`, `x == nil or x > 0`
+                let (when_true, when_false) = self.nil_checks(left);
+                let scope = if *operator == Token::And { when_true } else { when_false };
+                let right_type = self.narrowed_expr_type(scope, right);
+                for side_type in [left_type, right_type] {
+                    if !Type::Bool.accepts(&side_type) {
+                        self.error(format!("'{}' operands must be bool, found {}", op_symbol(operator), side_type), span);
+                    }
+                }
+                Type::Bool
+            }
+
+            ExprKind::List(elements) => {
+                let mut element_type = Type::Any;
+                for element in elements {
+                    let found = self.expr_type(element);
+                    self.unify(&mut element_type, found, "List elements", span);
+                }
+                Type::List(Box::new(element_type))
+            }
+
+            ExprKind::Map(entries) => {
+                let (mut key_type, mut value_type) = (Type::Any, Type::Any);
+                for (key, value) in entries {
+                    let found = self.expr_type(key);
+                    if !found.is_key() {
+                        self.error(format!("Map keys must be int, string or bool, found {}", found), &key.span);
+                    }
+                    self.unify(&mut key_type, found, "Map keys", span);
+                    let found = self.expr_type(value);
+                    self.unify(&mut value_type, found, "Map values", span);
+                }
+                Type::Map(Box::new(key_type), Box::new(value_type))
+            }
+
+            ExprKind::Slice { list, start, end } => {
+                let list_type = self.expr_type(list);
+                for bound in [start, end].into_iter().flatten() {
+                    self.check_index(bound);
+                }
+                match list_type {
+                    Type::List(_) | Type::String | Type::Any => list_type,
+                    other => {
+                        self.error(format!("Cannot slice a value of type {}", other), span);
+                        Type::Any
+                    }
+                }
+            }
+
+            ExprKind::Index { list, index } => {
+                let list_type = self.expr_type(list);
+                self.element_type(&list_type, index).unwrap_or_else(|| {
+                    self.error(format!("Cannot index into a value of type {}", list_type), span);
+                    Type::Any
+                })
+            }
+
+            ExprKind::Call { callee, arguments, .. } => match &callee.kind {
+                ExprKind::Variable { name, .. } => match self.function_variable(name) {
+                    Some(callee_type) => self.value_call_type(name, &callee_type, arguments, span),
+                    None => self.call_type(name, arguments, span),
+                },
+                _ => {
+                    let callee_type = self.expr_type(callee);
+                    self.value_call_type("function value", &callee_type, arguments, span)
+                }
+            },
+
+            ExprKind::Lambda { params, return_type, body, .. } => {
+                let return_type = return_type.as_deref().cloned();
+                for (_, param_type) in params {
+                    self.check_type_exists(param_type, span);
+                }
+                if let Some(return_type) = &return_type {
+                    self.check_type_exists(return_type, span);
+                }
+
+                // The lambda may run after a narrowed variable was set back to nil.
+                let hidden: Vec<(String, usize, Type)> = self.narrowed.iter()
+                    .filter_map(|(name, at)| self.scopes[*at].get(name).map(|ty| (name.clone(), *at, ty.clone())))
+                    .collect();
+                for (name, at, _) in &hidden {
+                    self.scopes[*at].remove(name);
+                }
+
+                // Unlike functions, lambdas see the scopes around them.
+                self.scopes.push(params.iter().cloned().collect());
+                self.returns.push(return_type.clone());
+                self.check_statements(body);
+                self.returns.pop();
+                self.check_bodies(body);
+                self.scopes.pop();
+                for (name, at, ty) in hidden {
+                    self.scopes[at].insert(name, ty);
+                }
+
+                if let Some(return_type) = &return_type
+                    && !always_returns(body) {
+                    self.error(format!("Lambda may finish without returning {}", return_type), span);
+                }
+                function_type(&FnSignature { params: params.clone(), return_type })
+            }
+
+            ExprKind::MethodCall { object, method, args } => {
+                if let Some(enum_name) = self.enum_name(object) {
+                    return self.variant_type(&enum_name, method, args, span);
+                }
+                if let ExprKind::Variable { name: alias, .. } = &object.kind
+                    && self.modules.contains(alias) && self.lookup(alias).is_none() {
+                    // Modules registered by the host have no source to check.
+                    let Some(signature) = self.functions.get(&format!("{}::{}", alias, method)).cloned() else {
+                        for arg in args {
+                            self.expr_type(arg);
+                        }
+                        return Type::Any;
+                    };
+                    self.check_args(method, &signature.params, args, span);
+                    self.forget_narrowed_globals();
+                    return signature.return_type.unwrap_or(Type::Any);
+                }
+
+                let object_type = self.expr_type(object);
+                self.method_type(&object_type, method, args, span)
+            }
+
+            ExprKind::FieldGet { object, field } => {
+                if let Some(enum_name) = self.enum_name(object) {
+                    return self.variant_type(&enum_name, field, &[], span);
+                }
+                let object_type = self.expr_type(object);
+                self.field_type(&object_type, field, span)
+            }
+
+            ExprKind::FieldSet { object, field, value } => {
+                let object_type = self.expr_type(object);
+                let field_type = self.field_type(&object_type, field, span);
+                let value_type = self.expr_type(value);
+                if !field_type.accepts(&value_type) {
+                    self.error(format!("Cannot assign {} to field '{}' of type {}", value_type, field, field_type), span);
+                }
+                field_type
+            }
+
+            ExprKind::IndexSet { list, index, value } => {
+                let list_type = self.expr_type(list);
+                if list_type == Type::String {
+                    self.error("Cannot assign to a character; strings cannot be changed in place".to_string(), span);
+                }
+                let element_type = self.element_type(&list_type, index).unwrap_or_else(|| {
+                    self.error(format!("Cannot index into a value of type {}", list_type), span);
+                    Type::Any
+                });
+                let value_type = self.expr_type(value);
+                if !element_type.accepts(&value_type) {
+                    self.error(format!("Cannot assign {} to an element of type {}", value_type, element_type), span);
+                }
+                element_type
+            }
+
+            ExprKind::CompoundAssign { target, op, value } => {
+                let (target_type, place) = match &target.kind {
+                    ExprKind::Index { list, index } => {
+                        let list_type = self.expr_type(list);
+                        if list_type == Type::String {
+                            self.error("Cannot assign to a character; strings cannot be changed in place".to_string(), span);
+                        }
+                        let element_type = self.element_type(&list_type, index).unwrap_or_else(|| {
+                            self.error(format!("Cannot index into a value of type {}", list_type), span);
+                            Type::Any
+                        });
+                        (element_type, "an element".to_string())
+                    }
+                    ExprKind::FieldGet { field, .. } => (self.expr_type(target), format!("field '{}'", field)),
+                    _ => (self.expr_type(target), "the target".to_string()),
+                };
+                let value_type = self.expr_type(value);
+                let result = self.binary_type(&target_type, op, &value_type, span);
+                if !target_type.accepts(&result) {
+                    self.error(format!("Cannot assign {} to {} of type {}", result, place, target_type), span);
+                }
+                target_type
+            }
+        }
+    }
+
+    fn binary_type(&mut self, left: &Type, op: &Token, right: &Type, span: &Span) -> Type {
+        let result = match op {
+            Token::Equals | Token::NotEquals => Some(Type::Bool),
+            Token::Greater | Token::Lesser | Token::GreaterEquals | Token::LesserEquals => {
+                (left.is_numeric() && right.is_numeric()).then_some(Type::Bool)
+            }
+            Token::Plus | Token::Minus | Token::Multiply | Token::Divide
+            | Token::FloorDivide | Token::Modulo | Token::Power => match (left, right) {
+                (Type::Any, other) | (other, Type::Any) => Some(other.clone()),
+                (Type::Int, Type::Int) => Some(Type::Int),
+                (Type::Float, Type::Float) | (Type::Int, Type::Float) | (Type::Float, Type::Int) => Some(Type::Float),
+                (Type::String, Type::String) if *op == Token::Plus => Some(Type::String),
+                _ => None,
+            },
+            Token::BitAnd | Token::BitOr | Token::BitXor | Token::ShiftLeft | Token::ShiftRight => {
+                (Type::Int.accepts(left) && Type::Int.accepts(right)).then_some(Type::Int)
+            }
+            _ => None,
+        };
+
+        result.unwrap_or_else(|| {
+            let hint = match (left, right) {
+                (Type::Option(_) | Type::Nil, _) | (_, Type::Option(_) | Type::Nil) => "; give a default with ?? first",
+                _ => "",
+            };
+            self.error(format!("Operator '{}' cannot be applied to {} and {}{}", op_symbol(op), left, right, hint), span);
+            Type::Any
+        })
+    }
+
+    /// The type of `value as target`; strings are only checked when converted at runtime.
+    fn cast_type(&mut self, found: &Type, target: Type, span: &Span) -> Type {
+        let allowed = match (&target, found) {
+            (_, Type::Any) | (Type::String, _) => true,
+            (Type::Int, found) => matches!(found, Type::Int | Type::Float | Type::Bool | Type::String),
+            (Type::Float, found) => matches!(found, Type::Int | Type::Float | Type::String),
+            _ => false,
+        };
+        if !allowed {
+            self.error(format!("Cannot convert {} to {}", found, target), span);
+        }
+        target
+    }
+
+    /// The type of a variable that a call goes through. Locals shadow functions and
+    /// structs of the same name, globals do not.
+    fn function_variable(&self, name: &str) -> Option<Type> {
+        let (globals, locals) = self.scopes.split_first()?;
+        let local = locals.iter().rev().find_map(|scope| scope.get(name));
+        let global = globals.get(name).filter(|_| {
+            !self.functions.contains_key(name) && !self.structs.contains_key(name)
+                && !self.natives.contains(name) && !native_functions::is_builtin(name)
+        });
+        local.or(global).cloned()
+    }
+
+    /// Checks a call on a function value, such as a parameter of type `fn(int) -> int`.
+    fn value_call_type(&mut self, name: &str, callee_type: &Type, args: &[Expr], span: &Span) -> Type {
+        match callee_type {
+            Type::Function(params, return_type) => {
+                let params: Vec<(String, Type)> = params.iter().enumerate()
+                    .map(|(i, ty)| (format!("#{}", i + 1), ty.clone()))
+                    .collect();
+                self.check_args(name, &params, args, span);
+                self.forget_narrowed_globals();
+                return_type.as_deref().cloned().unwrap_or(Type::Any)
+            }
+            Type::Any => {
+                for arg in args {
+                    self.expr_type(arg);
+                }
+                self.forget_narrowed_globals();
+                Type::Any
+            }
+            other => {
+                self.error(format!("Cannot call '{}' of type {}", name, other), span);
+                Type::Any
+            }
+        }
+    }
+
+    fn call_type(&mut self, name: &str, args: &[Expr], span: &Span) -> Type {
+        let struct_name = if self.structs.contains_key(name) {
+            Some(name.to_string())
+        } else {
+            name.split_once("::")
+                .map(|(_, type_name)| type_name.to_string())
+                .filter(|type_name| self.structs.contains_key(type_name))
+        };
+        if let Some(struct_name) = struct_name {
+            let fields = self.structs[&struct_name].clone();
+            self.check_args(&struct_name, &fields, args, span);
+            return Type::Named(name.to_string());
+        }
+
+        if let Some(signature) = self.functions.get(name).cloned() {
+            self.check_args(name, &signature.params, args, span);
+            self.forget_narrowed_globals();
+            return signature.return_type.unwrap_or(Type::Any);
+        }
+
+        if let Some(target) = conversion_target(name) {
+            return match args {
+                [value] => {
+                    let found = self.expr_type(value);
+                    self.cast_type(&found, target, span)
+                }
+                _ => {
+                    self.error(format!("{}() takes exactly one argument, found {}", name, args.len()), span);
+                    target
+                }
+            };
+        }
+
+        for arg in args {
+            self.expr_type(arg);
+        }
+
+        match name {
+            "input" => Type::String,
+            "clear" => Type::Bool,
+            // Never returns, so it may stand in for a value of any type
+            "error" => {
+                if args.len() != 1 {
+                    self.error(format!("error() takes exactly one argument, found {}", args.len()), span);
+                }
+                Type::Any
+            }
+            _ if self.natives.contains(name) || name.contains("::") => Type::Any,
+            _ => {
+                self.error(format!("Undefined function '{}'", name), span);
+                Type::Any
+            }
+        }
+    }
+
+    /// The enum named by `State` in `State.Idle`, unless a variable has that name.
+    fn enum_name(&self, object: &Expr) -> Option<String> {
+        match &object.kind {
+            ExprKind::Variable { name, .. } if self.enums.contains_key(name) && self.lookup(name).is_none() => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// Checks the payload of `State.Walking(1.5)` against the fields of the variant.
+    fn variant_type(&mut self, enum_name: &str, variant: &str, args: &[Expr], span: &Span) -> Type {
+        let fields = self.enums[enum_name].iter().find(|v| v.name == variant).map(|v| v.fields.clone());
+        match fields {
+            Some(fields) => {
+                self.check_args(&format!("{}.{}", enum_name, variant), &fields, args, span);
+            }
+            None => {
+                self.error(format!("Enum '{}' has no variant '{}'", enum_name, variant), span);
+                for arg in args {
+                    self.expr_type(arg);
+                }
+            }
+        }
+        Type::Named(enum_name.to_string())
+    }
+
+    fn method_type(&mut self, object_type: &Type, method: &str, args: &[Expr], span: &Span) -> Type {
+        match object_type {
+            Type::Named(type_name) => {
+                let signature = self.methods.get(type_name).and_then(|methods| methods.get(method)).cloned();
+                let field_type = self.structs.get(type_name)
+                    .and_then(|fields| fields.iter().find(|(field, _)| field == method))
+                    .map(|(_, field_type)| field_type.clone());
+                match (signature, field_type) {
+                    (Some(signature), _) => {
+                        self.check_args(method, &signature.params, args, span);
+                        self.forget_narrowed_globals();
+                        signature.return_type.unwrap_or(Type::Any)
+                    }
+                    // A field holding a function is called like a method: button.on_click()
+                    (None, Some(field_type)) => self.value_call_type(method, &field_type, args, span),
+                    (None, None) => {
+                        // Types coming from imported modules are not known statically.
+                        if self.structs.contains_key(type_name) {
+                            self.error(format!("Struct '{}' has no method '{}'", type_name, method), span);
+                        } else if self.enums.contains_key(type_name) {
+                            self.error(format!("Enum '{}' has no method '{}'", type_name, method), span);
+                        }
+                        for arg in args {
+                            self.expr_type(arg);
+                        }
+                        self.forget_narrowed_globals();
+                        Type::Any
+                    }
+                }
+            }
+
+            Type::List(element_type) => {
+                let element = (**element_type).clone();
+                let (params, result): (&[(&str, Type)], Type) = match method {
+                    "push" => (&[("value", element)], Type::Nil),
+                    "insert" => (&[("at", Type::Int), ("value", element)], Type::Nil),
+                    "pop" | "first" | "last" => (&[], element),
+                    "remove" => (&[("at", Type::Int)], element),
+                    "len" => (&[], Type::Int),
+                    "contains" => (&[("value", element)], Type::Bool),
+                    "index_of" => (&[("value", element)], Type::Int),
+                    "reverse" | "clear" => (&[], Type::Nil),
+                    "sort" => {
+                        if !matches!(element, Type::Int | Type::Float | Type::String | Type::Bool | Type::Any) {
+                            self.error(format!("Cannot sort a list of {}", element), span);
+                        }
+                        (&[], Type::Nil)
+                    }
+                    "slice" => (&[("start", Type::Int), ("end", Type::Int)], object_type.clone()),
+                    "extend" => (&[("other", object_type.clone())], Type::Nil),
+                    "join" => (&[("separator", Type::String)], Type::String),
+                    "map" => (&[("f", Type::Function(vec![element], None))], Type::Any),
+                    "filter" => (&[("f", predicate(vec![element]))], object_type.clone()),
+                    "reduce" => (&[("initial", Type::Any), ("f", Type::Function(vec![Type::Any, element], None))], Type::Any),
+                    "any" | "all" => (&[("f", predicate(vec![element]))], Type::Bool),
+                    "find" => (&[("f", predicate(vec![element.clone()]))], Type::Option(Box::new(element))),
+                    "sort_by" => (&[("before", predicate(vec![element.clone(), element]))], Type::Nil),
+                    "enumerate" => (&[], Type::List(Box::new(Type::List(Box::new(pair_type(&Type::Int, &element)))))),
+                    "zip" => (&[("other", Type::List(Box::new(Type::Any)))], Type::Any),
+                    _ => {
+                        self.error(format!("Unknown list method '{}'", method), span);
+                        for arg in args {
+                            self.expr_type(arg);
+                        }
+                        return Type::Any;
+                    }
+                };
+                let arg_types = self.check_method(method, params, args, span);
+                // Callbacks may set globals back to nil
+                if matches!(method, "map" | "filter" | "reduce" | "any" | "all" | "find" | "sort_by") {
+                    self.forget_narrowed_globals();
+                }
+
+                // What `map` and `reduce` produce depends on the function they are given.
+                let returned = |at: usize| match arg_types.get(at) {
+                    Some(Type::Function(_, Some(return_type))) => (**return_type).clone(),
+                    _ => Type::Any,
+                };
+                match method {
+                    "map" => Type::List(Box::new(returned(0))),
+                    "zip" => {
+                        let other = match arg_types.first() {
+                            Some(Type::List(other)) => (**other).clone(),
+                            _ => Type::Any,
+                        };
+                        Type::List(Box::new(Type::List(Box::new(pair_type(element_type, &other)))))
+                    }
+                    "reduce" => {
+                        let total = arg_types.first().cloned().unwrap_or(Type::Any);
+                        let step = returned(1);
+                        if !total.accepts(&step) {
+                            self.error(format!("'reduce' starts from {} but its function returns {}", total, step), span);
+                        }
+                        total
+                    }
+                    _ => result,
+                }
+            }
+
+            Type::Map(key_type, value_type) => match method {
+                "keys" => {
+                    self.check_args(method, &[], args, span);
+                    Type::List(key_type.clone())
+                }
+                "values" => {
+                    self.check_args(method, &[], args, span);
+                    Type::List(value_type.clone())
+                }
+                "has" => {
+                    let params = [("key".to_string(), (**key_type).clone())];
+                    self.check_args(method, &params, args, span);
+                    Type::Bool
+                }
+                "remove" => {
+                    let params = [("key".to_string(), (**key_type).clone())];
+                    self.check_args(method, &params, args, span);
+                    (**value_type).clone()
+                }
+                "len" => {
+                    self.check_args(method, &[], args, span);
+                    Type::Int
+                }
+                _ => {
+                    self.error(format!("Unknown map method '{}'", method), span);
+                    Type::Any
+                }
+            },
+
+            Type::String => {
+                let (params, result): (&[(&str, Type)], Type) = match method {
+                    "len" => (&[], Type::Int),
+                    "upper" | "lower" | "trim" => (&[], Type::String),
+                    "split" => (&[("separator", Type::String)], Type::List(Box::new(Type::String))),
+                    "contains" => (&[("text", Type::String)], Type::Bool),
+                    "starts_with" | "ends_with" => (&[("prefix", Type::String)], Type::Bool),
+                    "replace" => (&[("from", Type::String), ("to", Type::String)], Type::String),
+                    "find" => (&[("text", Type::String)], Type::Int),
+                    "substring" => (&[("start", Type::Int), ("end", Type::Int)], Type::String),
+                    "chars" => (&[], Type::List(Box::new(Type::String))),
+                    "repeat" => (&[("count", Type::Int)], Type::String),
+                    _ => {
+                        self.error(format!("Unknown string method '{}'", method), span);
+                        for arg in args {
+                            self.expr_type(arg);
+                        }
+                        return Type::Any;
+                    }
+                };
+                self.check_method(method, params, args, span);
+                result
+            }
+
+            Type::Any => {
+                for arg in args {
+                    self.expr_type(arg);
+                }
+                self.forget_narrowed_globals();
+                Type::Any
+            }
+
+            Type::Option(_) | Type::Nil => {
+                self.error(format!("Cannot call '{}' on {}, which may be nil; use ?. or check for nil first", method, object_type), span);
+                for arg in args {
+                    self.expr_type(arg);
+                }
+                Type::Any
+            }
+
+            other => {
+                self.error(format!("Type {} has no method '{}'", other, method), span);
+                Type::Any
+            }
+        }
+    }
+
+    fn check_method(&mut self, method: &str, params: &[(&str, Type)], args: &[Expr], span: &Span) -> Vec<Type> {
+        let params: Vec<(String, Type)> = params.iter().map(|(name, ty)| (name.to_string(), ty.clone())).collect();
+        self.check_args(method, &params, args, span)
+    }
+
+    fn field_type(&mut self, object_type: &Type, field: &str, span: &Span) -> Type {
+        match object_type {
+            Type::Named(type_name) => {
+                let field_type = self.structs.get(type_name)
+                    .and_then(|fields| fields.iter().find(|(name, _)| name == field))
+                    .map(|(_, ty)| ty.clone());
+                match field_type {
+                    Some(ty) => ty,
+                    None if self.structs.contains_key(type_name) => {
+                        self.error(format!("Struct '{}' has no field '{}'", type_name, field), span);
+                        Type::Any
+                    }
+                    None if self.enums.contains_key(type_name) => {
+                        self.error(format!("Cannot read '{}' of enum '{}'; use match to get at its values", field, type_name), span);
+                        Type::Any
+                    }
+                    // Types coming from imported modules are not known statically.
+                    None => Type::Any,
+                }
+            }
+            Type::Any => Type::Any,
+            Type::Option(_) | Type::Nil => {
+                self.error(format!("Cannot read '{}' of {}, which may be nil; use ?. or check for nil first", field, object_type), span);
+                Type::Any
+            }
+            other => {
+                self.error(format!("Field access on non-struct value of type {}", other), span);
+                Type::Any
+            }
+        }
+    }
+}
+
+/// True if every path through `body` ends in a `return`.
+fn always_returns(body: &[StmtNode]) -> bool {
+    body.iter().any(|node| match &node.stmt {
+        Stmt::Return { .. } => true,
+        Stmt::Block(statements) => always_returns(statements),
+        Stmt::If { then_branch, else_branch: Some(else_branch), .. } => {
+            always_returns(std::slice::from_ref(then_branch)) && always_returns(std::slice::from_ref(else_branch))
+        }
+        // A match that does not cover every value is reported on its own
+        Stmt::Match { cases, .. } => !cases.is_empty() && cases.iter().all(|case| always_returns(&case.body)),
+        Stmt::Try { body, handler, .. } => always_returns(body) && always_returns(handler),
+        // `while true` never ends unless something breaks out of it
+        Stmt::While { condition: Expr { kind: ExprKind::Literal(LiteralValue::Bool(true)), .. }, body, .. } => {
+            !breaks_out(std::slice::from_ref(body), &[])
+        }
+        // `error(...)` never comes back
+        Stmt::Expression(Expr { kind: ExprKind::Call { callee, .. }, .. }) => {
+            matches!(&callee.kind, ExprKind::Variable { name, .. } if name == "error")
+        }
+        _ => false,
+    })
+}
+
+/// True if `body` never carries on to the statement after it: it returns, raises
+/// an error, or breaks out of or continues a loop.
+fn always_leaves(body: &[StmtNode]) -> bool {
+    always_returns(body) || body.iter().any(|node| match &node.stmt {
+        Stmt::Break { .. } | Stmt::Continue { .. } => true,
+        Stmt::Block(statements) => always_leaves(statements),
+        Stmt::If { then_branch, else_branch: Some(else_branch), .. } => {
+            always_leaves(std::slice::from_ref(then_branch)) && always_leaves(std::slice::from_ref(else_branch))
+        }
+        _ => false,
+    })
+}
+
+/// `ty` as seen from outside a module, with the types `defined` there prefixed by `alias::`.
+fn qualify(ty: &Type, alias: &str, defined: &HashSet<&str>) -> Type {
+    let qualify = |ty: &Type| qualify(ty, alias, defined);
+    match ty {
+        Type::Named(name) if defined.contains(name.as_str()) => Type::Named(format!("{}::{}", alias, name)),
+        Type::List(inner) => Type::List(Box::new(qualify(inner))),
+        Type::Option(inner) => Type::Option(Box::new(qualify(inner))),
+        Type::Map(key, value) => Type::Map(Box::new(qualify(key)), Box::new(qualify(value))),
+        Type::Function(params, return_type) => Type::Function(
+            params.iter().map(qualify).collect(),
+            return_type.as_deref().map(|ty| Box::new(qualify(ty))),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// True if a `break` in `body` leaves the loop that `body` belongs to. `inner`
+/// holds the labels of the loops in between, whose `break`s stay inside.
+fn breaks_out(body: &[StmtNode], inner: &[Option<&str>]) -> bool {
+    body.iter().any(|node| match &node.stmt {
+        Stmt::Break { label: None } => inner.is_empty(),
+        Stmt::Break { label: Some(label) } => !inner.contains(&Some(label.as_str())),
+        Stmt::Block(statements) => breaks_out(statements, inner),
+        Stmt::If { then_branch, else_branch, .. } => {
+            breaks_out(std::slice::from_ref(then_branch), inner)
+                || else_branch.as_ref().is_some_and(|branch| breaks_out(std::slice::from_ref(branch), inner))
+        }
+        Stmt::Match { cases, .. } => cases.iter().any(|case| breaks_out(&case.body, inner)),
+        Stmt::Try { body, handler, .. } => breaks_out(body, inner) || breaks_out(handler, inner),
+        Stmt::While { body, label, .. } | Stmt::For { body, label, .. } | Stmt::ForEach { body, label, .. } => {
+            let mut nested = inner.to_vec();
+            nested.push(label.as_deref());
+            breaks_out(std::slice::from_ref(body), &nested)
+        }
+        _ => false,
+    })
+}
+
+/// The type of a callback that answers a question about its arguments.
+fn predicate(params: Vec<Type>) -> Type {
+    Type::Function(params, Some(Box::new(Type::Bool)))
+}
+
+/// The element type of the `[a, b]` pairs built by `enumerate` and `zip`: the
+/// type both items share, or `any` when they differ.
+fn pair_type(first: &Type, second: &Type) -> Type {
+    match (first, second) {
+        (Type::Any, _) | (_, Type::Any) => Type::Any,
+        _ if first.accepts(second) => first.clone(),
+        _ if second.accepts(first) => second.clone(),
+        _ => Type::Any,
+    }
+}
+
+/// The type of a named function used as a value.
+fn function_type(signature: &FnSignature) -> Type {
+    Type::Function(
+        signature.params.iter().map(|(_, ty)| ty.clone()).collect(),
+        signature.return_type.clone().map(Box::new),
+    )
+}
+
+/// The type produced by the `int`, `float` and `string` conversion builtins.
+fn conversion_target(name: &str) -> Option<Type> {
+    match name {
+        "int" => Some(Type::Int),
+        "float" => Some(Type::Float),
+        "string" => Some(Type::String),
+        _ => None,
+    }
+}
+
+fn op_symbol(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Multiply => "*",
+        Token::Divide => "/",
+        Token::FloorDivide => "//",
+        Token::Modulo => "%",
+        Token::Power => "**",
+        Token::BitAnd => "&",
+        Token::BitOr => "|",
+        Token::BitXor => "^",
+        Token::BitNot => "~",
+        Token::ShiftLeft => "<<",
+        Token::ShiftRight => ">>",
+        Token::Equals => "==",
+        Token::NotEquals => "!=",
+        Token::Greater => ">",
+        Token::Lesser => "<",
+        Token::GreaterEquals => ">=",
+        Token::LesserEquals => "<=",
+        Token::And => "and",
+        Token::Or => "or",
+        Token::Bang => "!",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WolfEngine;
+
+    #[test]
+    fn reports_every_error_before_running() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let started: bool = true
+            let hp: int = "full"
+            let speed: float = 1.5 + "2"
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign string to 'hp' of type int"));
+        assert!(err.contains("Operator '+' cannot be applied to float and string"));
+        assert_eq!(engine.get_bool("started"), None);
+    }
+
+    #[test]
+    fn checks_function_params_and_list_elements() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            fn heal(amount: int)
+                print amount
+            end
+
+            let names: list<string> = ["wolf", 3]
+            heal("a lot")
+            names.push(false)
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("List elements must share one type"));
+        assert!(err.contains("Argument 'amount' of 'heal' expects int but got string"));
+        assert!(err.contains("Argument 'value' of 'push' expects string but got bool"));
+    }
+
+    #[test]
+    fn checks_map_keys_and_values() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let stock: map<string, int> = { "arrows": 20, "potions": "three" }
+            let ages: map<float, int> = {}
+            stock[1] = 5
+            stock["rope"] = "long"
+            let gone: string = stock.remove("arrows")
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Map values must share one type, found int and string"));
+        assert!(err.contains("Map keys must be int, string or bool, found float"));
+        assert!(err.contains("Map key must be string, found int"));
+        assert!(err.contains("Cannot assign string to an element of type int"));
+        assert!(err.contains("Cannot assign int to 'gone' of type string"));
+    }
+
+    #[test]
+    fn checks_for_each_loops() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let stock: map<string, int> = { "arrows": 20 }
+            for item, count in stock
+                let label: int = item
+            end
+            for ch in "wolf"
+                let n: int = ch
+            end
+            for x in 42
+            end
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign string to 'label' of type int"));
+        assert!(err.contains("Cannot assign string to 'n' of type int"));
+        assert!(err.contains("Cannot iterate over a value of type int"));
+    }
+
+    #[test]
+    fn checks_new_operators() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let flags: int = 1.5 | 2
+            let inverted: bool = ~true
+            let rest: float = 7.5 % "2"
+            let name: string = "wolf"
+            name -= "f"
+            let counts: list<int> = [1]
+            counts[0] += 1.5
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Operator '|' cannot be applied to float and int"));
+        assert!(err.contains("Operator '~' cannot be applied to bool"));
+        assert!(err.contains("Operator '%' cannot be applied to float and string"));
+        assert!(err.contains("Operator '-' cannot be applied to string and string"));
+        assert!(err.contains("Cannot assign float to an element of type int"));
+    }
+
+    #[test]
+    fn checks_numeric_promotion_and_casts() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let speed: float = 1.5 * 2
+            let steps: int = speed
+            let whole: int = speed as int
+            let items: list<int> = [1, 2]
+            let count: float = items as float
+            let text: string = items as string
+            let parsed: int = int("4", "2")
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign float to 'steps' of type int"));
+        assert!(err.contains("Cannot convert list<int> to float"));
+        assert!(err.contains("int() takes exactly one argument, found 2"));
+        assert_eq!(err.matches("Type Error").count(), 3);
+    }
+
+    #[test]
+    fn checks_string_methods() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let name: string = "wolf"
+            let parts: list<string> = name.split(",")
+            let size: string = name.len()
+            let shouted: string = name.shout()
+            let piece: string = name.substring("1", 2)
+            name[0] = "W"
+            let first: string = name[0]
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign int to 'size' of type string"));
+        assert!(err.contains("Unknown string method 'shout'"));
+        assert!(err.contains("Argument 'start' of 'substring' expects int but got string"));
+        assert!(err.contains("Cannot assign to a character of 'name'; strings cannot be changed in place"));
+        assert_eq!(err.matches("Type Error").count(), 4);
+    }
+
+    #[test]
+    fn checks_list_methods_and_slices() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let grid: list<list<int>> = [[1]]
+            grid.sort()
+            let row: list<int> = grid.first()
+            let one: int = grid.slice(0, 1)
+            grid.extend([2])
+            let n: int = 3
+            let part: int = n[1:]
+            let words: list<string> = ["a"]
+            let at: int = words.index_of("a")
+            let piece: list<string> = words["a":]
+            let pushed: string = words.push("b")
+            let cleared: list<string> = words.clear()
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot sort a list of list<int>"));
+        assert!(err.contains("Cannot assign list<list<int>> to 'one' of type int"));
+        assert!(err.contains("Argument 'other' of 'extend' expects list<list<int>> but got list<int>"));
+        assert!(err.contains("Cannot slice a value of type int"));
+        assert!(err.contains("List index must be int, found string"));
+        assert!(err.contains("Cannot assign nil to 'pushed' of type string"));
+        assert!(err.contains("Cannot assign nil to 'cleared' of type list<string>"));
+        assert_eq!(err.matches("Type Error").count(), 7);
+    }
+
+    #[test]
+    fn checks_nested_assignments() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            struct Bag
+                items: list<string>
+            end
+            let bags: list<Bag> = [Bag(["axe"])]
+            bags[0].items[0] = 3
+            bags[0].items = "none"
+            bags[0].missing = 1
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign int to an element of type string"));
+        assert!(err.contains("Cannot assign string to field 'items' of type list<string>"));
+        assert!(err.contains("Struct 'Bag' has no field 'missing'"));
+    }
+
+    #[test]
+    fn checks_function_values() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            fn apply(f: fn(int) -> int, x: int) -> int
+                return f(x)
+            end
+            let shout: fn(string) -> string = fn(text: string) -> string return text.upper() end
+            let count: int = 3
+            let wrong: int = apply(shout, 1)
+            let called: int = count(2)
+            let unfinished: fn(int) -> int = fn(x: int) -> int
+                if x > 0
+                    return 1
+                end
+            end
+            let loud: int = shout("a")
+            shout(1)
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Argument 'f' of 'apply' expects fn(int) -> int but got fn(string) -> string"));
+        assert!(err.contains("Cannot call 'count' of type int"));
+        assert!(err.contains("Lambda may finish without returning int"));
+        assert!(err.contains("Cannot assign string to 'loud' of type int"));
+        assert!(err.contains("Argument '#1' of 'shout' expects string but got int"));
+    }
+
+    #[test]
+    fn checks_higher_order_list_methods() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let xs: list<int> = [1, 2, 3]
+            let names: list<string> = xs.map(fn(x: int) -> int return x * 2 end)
+            let kept: list<int> = xs.filter(fn(x: int) -> int return x end)
+            let total: string = xs.reduce("", fn(sum: int, x: int) -> int return sum + x end)
+            xs.sort_by(fn(a: string, b: string) -> bool return a < b end)
+            let first: string = xs.find(fn(x: int) -> bool return x > 1 end)
+            let sorted: list<int> = xs.sort_by(fn(a: int, b: int) -> bool return a < b end)
+            let numbered: list<list<string>> = xs.enumerate()
+            let pairs: list<list<string>> = xs.zip([4, 5])
+            let words: list<string> = ["a"]
+            let mixed: list<list<string>> = words.zip(xs)
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign list<int> to 'names' of type list<string>"));
+        assert!(err.contains("Argument 'f' of 'filter' expects fn(int) -> bool but got fn(int) -> int"));
+        assert!(err.contains("'reduce' starts from string but its function returns int"));
+        assert!(err.contains("Argument 'before' of 'sort_by' expects fn(int, int) -> bool but got fn(string, string) -> bool"));
+        assert!(err.contains("Cannot assign option<int> to 'first' of type string"));
+        assert!(err.contains("Cannot assign nil to 'sorted' of type list<int>"));
+        assert!(err.contains("Cannot assign list<list<int>> to 'numbered' of type list<list<string>>"));
+        assert!(err.contains("Cannot assign list<list<int>> to 'pairs' of type list<list<string>>"));
+        // Pairs of a string and an int have no common element type
+        assert!(!err.contains("'mixed'"));
+    }
+
+    #[test]
+    fn checks_options() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            struct Unit
+                name: string
+            end
+            let a: int = nil
+            let b: option<int> = 3
+            let c: int = b + 1
+            let p: option<Unit> = nil
+            print p.name
+            p.greet()
+            let e: int = b ?? "zero"
+            let f: int = 4 ?? 1
+            let g: string = p?.name
+            let slots: list<option<int>> = [nil, 1, nil]
+            let more: list<option<int>> = [2, nil]
+            if b != nil and p != nil
+                let h: int = b + 1
+                print p.name
+                b = nil
+            end
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign nil to 'a' of type int"));
+        assert!(err.contains("Operator '+' cannot be applied to option<int> and int; give a default with ?? first"));
+        assert!(err.contains("Cannot read 'name' of option<Unit>, which may be nil; use ?. or check for nil first"));
+        assert!(err.contains("Cannot call 'greet' on option<Unit>, which may be nil"));
+        assert!(err.contains("'??' needs a default of type int, found string"));
+        assert!(err.contains("Left side of '??' is int, which is never nil"));
+        assert!(err.contains("Cannot assign option<string> to 'g' of type string"));
+        assert!(!err.contains("List elements must share one type"));
+        // Inside the `if`, b and p are known to hold values
+        assert_eq!(err.matches("Type Error").count(), 7);
+    }
+
+    #[test]
+    fn assignments_end_nil_narrowing() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let o: option<int> = 3
+            if o != nil
+                print o + 1
+                o = nil
+                print o + 2
+            end
+            if o != nil
+                while true
+                    print o + 3
+                    o = nil
+                end
+            end
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Operator '+' cannot be applied to option<int> and int"));
+        assert_eq!(err.matches("Type Error").count(), 2);
+    }
+
+    #[test]
+    fn calls_end_nil_narrowing_of_globals() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let o: option<int> = 3
+            fn reset()
+                o = nil
+            end
+            if o != nil
+                print o + 1
+                reset()
+                print o + 2
+            end
+            fn local(p: option<int>)
+                if p != nil
+                    reset()
+                    print p + 3
+                end
+            end
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Operator '+' cannot be applied to option<int> and int"));
+        assert_eq!(err.matches("Type Error").count(), 1);
+    }
+
+    #[test]
+    fn narrows_in_conditions_loops_and_after_early_exits() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn positive(x: option<int>) -> bool
+                return x != nil and x > 0
+            end
+            fn small(x: option<int>) -> bool
+                return x == nil or x < 10
+            end
+            fn step(n: int) -> option<int>
+                if n > 3
+                    return nil
+                end
+                return n + 1
+            end
+            fn total(start: option<int>) -> int
+                let n: option<int> = start
+                let sum: int = 0
+                while n != nil
+                    sum += n
+                    n = step(n)
+                end
+                return sum
+            end
+            fn first(values: list<option<int>>) -> int
+                for value in values
+                    if value == nil
+                        continue
+                    end
+                    return value
+                end
+                return 0
+            end
+            let g: option<int> = 5
+            if g == nil
+                error("missing")
+            end
+            let h: int = g + total(1) + first([nil, 7])
+            let checks: bool = positive(2) and !positive(nil) and small(nil) and !small(12)
+        "#).unwrap();
+        assert_eq!(engine.get_int("h"), Some(5 + 10 + 7));
+        assert_eq!(engine.get_bool("checks"), Some(true));
+
+        let err = engine.run(r#"
+            let y: option<int> = 1
+            if y == nil
+                print "none"
+            end
+            print y + 1
+            let z: option<int> = 2
+            print z != nil or z > 0
+            if z == nil
+                return
+            end
+            z = nil
+            print z + 3
+        "#).unwrap_err().to_string();
+        assert!(err.contains("Operator '+' cannot be applied to option<int> and int"));
+        assert!(err.contains("Operator '>' cannot be applied to option<int> and int"));
+        assert_eq!(err.matches("Type Error").count(), 3);
+
+        // Each loop is looked at once, however deeply they nest
+        let depth = 40;
+        let nested = format!(
+            "let o: option<int> = 1\n{}print o + 1\n{}",
+            "while o != nil\n".repeat(depth),
+            "o = nil\nend\n".repeat(depth),
+        );
+        engine.run(&nested).unwrap();
+    }
+
+    #[test]
+    fn lambdas_do_not_see_nil_narrowing() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            let o: option<int> = 3
+            if o != nil
+                let next: fn() -> int = fn() -> int return o + 1 end
+                print o + 2
+            end
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Operator '+' cannot be applied to option<int> and int"));
+        assert_eq!(err.matches("Type Error").count(), 1);
+    }
+
+    #[test]
+    fn checks_enums_and_match() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            enum State
+                Idle
+                Walking(speed: float)
+            end
+            let s: State = State.Walking("fast")
+            let t: State = State.Run
+            match s
+                case Idle
+                    print "idle"
+            end
+            match s
+                case Walking(a, b)
+                    let speed: string = a
+                case _
+                case Idle
+            end
+            match 3
+                case "three"
+            end
+            match true
+                case true
+            end
+            match s
+                case Idle
+                case Walking(speed)
+                case _
+            end
+            match false
+                case false
+                case true
+                case _
+            end
+            print s.speed
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Argument 'speed' of 'State.Walking' expects float but got string"));
+        assert!(err.contains("Enum 'State' has no variant 'Run'"));
+        assert!(err.contains("match on State does not cover Walking"));
+        assert!(err.contains("Variant 'Walking' holds 1 values but the case binds 2"));
+        assert!(err.contains("Cannot assign float to 'speed' of type string"));
+        assert!(err.contains("Unreachable case: an earlier `case _` matches everything"));
+        assert!(err.contains("Cannot match int against a string case"));
+        assert!(err.contains("match on int needs a `case _` to cover every value"));
+        assert!(err.contains("match on bool does not cover false"));
+        assert_eq!(err.matches("Unreachable case: the cases above already match every value").count(), 2);
+        assert!(err.contains("Cannot read 'speed' of enum 'State'; use match to get at its values"));
+    }
+
+    #[test]
+    fn checks_try_catch() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            fn first(text: string) -> int
+                try
+                    return int(text)
+                catch err
+                    let code: int = err
+                    return 0
+                end
+            end
+
+            fn fail() -> int
+                error("always fails")
+            end
+
+            fn partial() -> int
+                try
+                    return 1
+                catch
+                    print "no return"
+                end
+            end
+
+            error()
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign string to 'code' of type int"));
+        assert!(err.contains("Function 'partial' may finish without returning int"));
+        assert!(err.contains("error() takes exactly one argument, found 0"));
+
+        let err = engine.run(r#"
+            fn error(message: string) -> int
+                return 1
+            end
+            fn outer()
+                fn input() -> string
+                    return "typed"
+                end
+            end
+        "#).unwrap_err().to_string();
+        assert!(err.contains("Cannot declare function 'error': the name belongs to a built-in function"));
+        assert!(err.contains("Cannot declare function 'input': the name belongs to a built-in function"));
+    }
+
+    #[test]
+    fn checks_imported_modules() {
+        let path = std::env::temp_dir().join("wolf_checked_module.wolf");
+        std::fs::write(&path, "\
+struct Point
+    x: int
+end
+
+let origin: int = \"s\"
+
+fn broken() -> int
+    return \"str\"
+end
+
+fn make(x: int) -> Point
+    return Point(x)
+end
+").unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut engine = WolfEngine::new();
+        let err = engine.run(&format!(r#"
+            import "{}" as geo
+            let p: geo::Point = geo.make("one")
+            let x: string = p.x
+        "#, path)).unwrap_err().to_string();
+
+        assert!(err.contains("Cannot assign string to 'origin' of type int"));
+        assert!(err.contains("Expected to return int but found string"));
+        assert!(err.contains("Argument 'x' of 'make' expects int but got string"));
+        assert!(err.contains("Cannot assign int to 'x' of type string"));
+        assert!(!err.contains("Runtime Error"));
+    }
+
+    #[test]
+    fn checks_return_types() {
+        let mut engine = WolfEngine::new();
+        let err = engine.run(r#"
+            fn half(x: int) -> float
+                if x > 0
+                    return "positive"
+                end
+            end
+
+            let h: string = half(4)
+        "#).unwrap_err().to_string();
+
+        assert!(err.contains("Expected to return float but found string"));
+        assert!(err.contains("Function 'half' may finish without returning float"));
+        assert!(err.contains("Cannot assign float to 'h' of type string"));
+    }
+
+    #[test]
+    fn endless_loops_do_not_fall_off_the_end() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn first() -> int
+                while true
+                    return 1
+                end
+            end
+            let one: int = first()
+        "#).unwrap();
+        assert_eq!(engine.get_int("one"), Some(1));
+
+        let err = engine.run(r#"
+            fn stops() -> int
+                outer: while true
+                    while true
+                        break outer
+                    end
+                end
+            end
+        "#).unwrap_err().to_string();
+        assert!(err.contains("Function 'stops' may finish without returning int"));
+    }
+}
@@ -0,0 +1,1017 @@
+use std::{fs, mem};
+
+use crate::{
+    ast::{Expr, ExprKind, LiteralValue, MatchCase, Pattern, Slot, Stmt, StmtNode, Variant},
+    bytecode::{Access, Chunk, Function, Op},
+    error_handler::ParseError,
+    lexer,
+    native_functions,
+    parser::Parser,
+    resolver::Resolver,
+    span::{SourceFile, Span},
+    tokens::Token,
+    types::Type,
+    value::Value,
+    vm::{key_count, Vm},
+};
+
+/// A function body that is compiled after the surrounding program, once every
+/// global it may refer to has a slot.
+struct Pending {
+    index: u32,
+    /// Slots taken by `self`, the parameters and a lambda's captures.
+    slots: usize,
+    body: Vec<StmtNode>,
+}
+
+/// A loop whose body is being compiled.
+struct Loop {
+    label: Option<String>,
+    /// `break` jumps, patched to the end of the loop.
+    breaks: Vec<usize>,
+    /// `continue` jumps, patched to where the next iteration starts.
+    continues: Vec<usize>,
+    /// `try` blocks open around the loop, so a jump out of it knows how many to leave.
+    handlers: usize,
+}
+
+/// Compilation state of the function currently being emitted.
+#[derive(Default)]
+struct FnState {
+    chunk: Chunk,
+    /// Block nesting; items declared at depth 0 of the script were hoisted.
+    depth: usize,
+    /// Local slots the frame needs, grown as definitions are compiled.
+    max_slots: u32,
+    /// Enclosing loops, innermost last.
+    loops: Vec<Loop>,
+    /// `try` blocks open at the code being emitted.
+    handlers: usize,
+}
+
+/// Turns parsed statements into bytecode, registering functions, structs and
+/// globals in the `Vm` as it goes.
+pub struct Compiler<'a> {
+    vm: &'a mut Vm,
+    /// Alias of the module being compiled; its top-level names are prefixed with `alias::`.
+    module: Option<String>,
+    state: FnState,
+    pending: Vec<Pending>,
+    /// Further errors from a module whose first error stopped compilation.
+    module_errors: Vec<ParseError>,
+    /// Source location given to the instructions being emitted.
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(vm: &'a mut Vm) -> Self {
+        Compiler { vm, module: None, state: FnState::default(), pending: Vec::new(), module_errors: Vec::new(), span: Span::default() }
+    }
+
+    fn for_module(vm: &'a mut Vm, alias: &str) -> Self {
+        Compiler { module: Some(alias.to_string()), ..Compiler::new(vm) }
+    }
+
+    /// Compiles a whole program into the function that runs its top-level code.
+    /// Compilation stops at the first error, but an imported module reports all
+    /// of its syntax or resolve errors.
+    pub fn compile(mut self, program: &[StmtNode]) -> Result<Function, Vec<ParseError>> {
+        self.compile_program(program).map_err(|first| {
+            let mut errors = vec![first];
+            errors.append(&mut self.module_errors);
+            errors
+        })
+    }
+
+    fn compile_program(&mut self, program: &[StmtNode]) -> Result<Function, ParseError> {
+        let is_type = |node: &&StmtNode| matches!(node.stmt, Stmt::Struct { .. } | Stmt::Enum { .. });
+        for node in program.iter().filter(is_type) {
+            self.declare_item(node)?;
+        }
+        for node in program.iter().filter(|node| !is_type(node)) {
+            self.declare_item(node)?;
+        }
+
+        for node in program {
+            self.statement(node)?;
+        }
+        self.emit_return();
+
+        let state = mem::take(&mut self.state);
+        let name = match &self.module {
+            Some(alias) => format!("<module {}>", alias),
+            None => "<script>".to_string(),
+        };
+        let script = Function {
+            name,
+            module: self.module.clone(),
+            params: Vec::new(),
+            return_type: None,
+            is_method: false,
+            captures: 0,
+            locals: state.max_slots as usize,
+            chunk: state.chunk,
+        };
+
+        self.compile_pending()?;
+        Ok(script)
+    }
+
+    fn compile_pending(&mut self) -> Result<(), ParseError> {
+        while let Some(pending) = self.pending.pop() {
+            self.state = FnState {
+                depth: 1,
+                max_slots: pending.slots as u32,
+                ..FnState::default()
+            };
+
+            for node in &pending.body {
+                self.statement(node)?;
+            }
+            self.emit_return();
+
+            let state = mem::take(&mut self.state);
+            let mut function = (*self.vm.functions[pending.index as usize]).clone();
+            function.locals = state.max_slots as usize;
+            function.chunk = state.chunk;
+            self.vm.set_function(pending.index, function);
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError::CompileError { message, span: self.span.clone() }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.state.chunk.emit(op, self.span.clone())
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let index = self.state.chunk.add_constant(value);
+        self.emit(Op::Constant(index));
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_constant(Value::Nil);
+        self.emit(Op::Return);
+    }
+
+    fn name_constant(&mut self, name: &str) -> u32 {
+        self.state.chunk.add_constant(Value::String(name.to_string()))
+    }
+
+    /// Points a previously emitted jump at the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.state.chunk.code.len() as u32;
+        self.patch_to(at, target);
+    }
+
+    fn patch_to(&mut self, at: usize, target: u32) {
+        match &mut self.state.chunk.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::Or(to) | Op::And(to)
+            | Op::JumpIfNil(to) | Op::Coalesce(to) | Op::PushHandler(to)
+            | Op::ForRange(_, to) | Op::ForEach(_, to, _) => *to = target,
+            op => unreachable!("cannot patch {:?}", op),
+        }
+    }
+
+    fn count(&self, n: usize, what: &str) -> Result<u8, ParseError> {
+        u8::try_from(n).map_err(|_| self.error(format!("Too many {} ({}), the limit is 255", what, n)))
+    }
+
+    // ---- names ----
+
+    /// Prefixes a top-level name defined by the module being compiled.
+    fn qualified(&self, name: &str) -> String {
+        match &self.module {
+            Some(alias) => format!("{}::{}", alias, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Finds a name the module defines itself before falling back to the plain one.
+    fn lookup_name<T>(&self, name: &str, table: &std::collections::HashMap<String, T>) -> Option<String> {
+        if self.module.is_some() {
+            let qualified = self.qualified(name);
+            if table.contains_key(&qualified) {
+                return Some(qualified);
+            }
+        }
+        table.contains_key(name).then(|| name.to_string())
+    }
+
+    fn qualify_type(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Named(name) => {
+                let type_name = self.lookup_name(name, &self.vm.struct_defs)
+                    .or_else(|| self.lookup_name(name, &self.vm.enum_defs));
+                Type::Named(type_name.unwrap_or_else(|| name.clone()))
+            }
+            Type::List(inner) => Type::List(Box::new(self.qualify_type(inner))),
+            Type::Map(key, value) => Type::Map(Box::new(self.qualify_type(key)), Box::new(self.qualify_type(value))),
+            Type::Option(inner) => Type::Option(Box::new(self.qualify_type(inner))),
+            Type::Function(params, return_type) => Type::Function(
+                params.iter().map(|param| self.qualify_type(param)).collect(),
+                return_type.as_ref().map(|ty| Box::new(self.qualify_type(ty))),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// The slot the resolver assigned to a variable.
+    fn slot(&self, name: &str, slot: Option<Slot>) -> Result<Slot, ParseError> {
+        slot.ok_or_else(|| self.error(format!("Variable '{}' was not resolved", name)))
+    }
+
+    /// Makes room in the frame for a local defined at `slot`.
+    fn reserve(&mut self, slot: u32) {
+        self.state.max_slots = self.state.max_slots.max(slot + 1);
+    }
+
+    fn emit_set(&mut self, slot: Slot) {
+        match slot {
+            Slot::Local(index) => self.emit(Op::SetLocal(index)),
+            Slot::Global(index) => self.emit(Op::SetGlobal(index)),
+        };
+    }
+
+    // ---- declarations ----
+
+    /// Registers functions, structs and methods so they can be used before the
+    /// statement that defines them. Their bodies are compiled later.
+    fn declare_item(&mut self, node: &StmtNode) -> Result<(), ParseError> {
+        self.span = node.span.clone();
+        match &node.stmt {
+            Stmt::Func { name, params, return_type, body } => {
+                let index = self.declare_function(self.qualified(name), params, return_type, body, false, 0);
+                self.vm.function_names.insert(self.qualified(name), index);
+            }
+            Stmt::Struct { name, body } => {
+                let mut fields = Vec::new();
+                for field in body {
+                    match &field.stmt {
+                        Stmt::Let { name, data_type, .. } => fields.push((name.clone(), self.qualify_type(data_type))),
+                        _ => return Err(ParseError::CompileError {
+                            message: "Struct body must only contain field declarations!".to_string(),
+                            span: field.span.clone(),
+                        }),
+                    }
+                }
+                self.vm.struct_defs.insert(self.qualified(name), fields);
+            }
+            Stmt::Enum { name, variants } => {
+                // Registered before the payload types are qualified, so a variant may hold its own enum
+                self.vm.enum_defs.insert(self.qualified(name), variants.clone());
+                let variants = variants.iter().map(|variant| Variant {
+                    name: variant.name.clone(),
+                    fields: variant.fields.iter().map(|(name, ty)| (name.clone(), self.qualify_type(ty))).collect(),
+                }).collect();
+                self.vm.enum_defs.insert(self.qualified(name), variants);
+            }
+            Stmt::Impl { name, body } => {
+                let type_name = self.lookup_name(name, &self.vm.struct_defs)
+                    .or_else(|| self.lookup_name(name, &self.vm.enum_defs))
+                    .unwrap_or_else(|| name.clone());
+                for method in body {
+                    if let Stmt::Func { name: method_name, params, return_type, body } = &method.stmt {
+                        let full_name = format!("{}.{}", type_name, method_name);
+                        let index = self.declare_function(full_name, params, return_type, body, true, 0);
+                        self.vm.methods.entry(type_name.clone()).or_default().insert(method_name.clone(), index);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn declare_function(
+        &mut self,
+        name: String,
+        params: &[(String, Type)],
+        return_type: &Option<Type>,
+        body: &[StmtNode],
+        is_method: bool,
+        captures: usize,
+    ) -> u32 {
+        let params: Vec<(String, Type)> = params.iter().map(|(name, ty)| (name.clone(), self.qualify_type(ty))).collect();
+        let index = self.vm.add_function(Function {
+            name,
+            module: self.module.clone(),
+            params,
+            return_type: return_type.as_ref().map(|ty| self.qualify_type(ty)),
+            is_method,
+            captures,
+            locals: 0,
+            chunk: Chunk::default(),
+        });
+        let slots = self.vm.functions[index as usize].arity();
+        self.pending.push(Pending { index, slots, body: body.to_vec() });
+        index
+    }
+
+    // ---- statements ----
+
+    fn statement(&mut self, node: &StmtNode) -> Result<(), ParseError> {
+        self.span = node.span.clone();
+        match &node.stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit(Op::Pop);
+            }
+
+            Stmt::Print(exprs) => {
+                for expr in exprs {
+                    self.expression(expr)?;
+                }
+                let count = self.count(exprs.len(), "values to print")?;
+                self.emit(Op::Print(count));
+            }
+
+            Stmt::Let { name, data_type, value, slot } => {
+                let slot = self.slot(name, *slot)?;
+                self.expression(value)?;
+                if *data_type != Type::Any {
+                    let ty = self.state.chunk.add_type(self.qualify_type(data_type));
+                    self.emit(Op::CheckType(ty));
+                }
+                if let Slot::Local(index) = slot {
+                    self.reserve(index);
+                }
+                self.emit_set(slot);
+            }
+
+            Stmt::ListAssign { list_name, indices, value, slot } => {
+                let slot = self.slot(list_name, *slot)?;
+                for index in indices {
+                    self.expression(index)?;
+                }
+                self.expression(value)?;
+                let path = self.state.chunk.add_path(vec![Access::Index; indices.len()]);
+                self.emit(Op::SetPath(slot, path));
+                self.emit(Op::Pop);
+            }
+
+            Stmt::Block(statements) => {
+                self.state.depth += 1;
+                for node in statements {
+                    self.statement(node)?;
+                }
+                self.state.depth -= 1;
+            }
+
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.expression(condition)?;
+                let skip_then = self.emit(Op::JumpIfFalse(0));
+                self.statement(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let skip_else = self.emit(Op::Jump(0));
+                        self.patch(skip_then);
+                        self.statement(else_branch)?;
+                        self.patch(skip_else);
+                    }
+                    None => self.patch(skip_then),
+                }
+            }
+
+            Stmt::While { condition, body, label } => {
+                let start = self.state.chunk.code.len() as u32;
+                self.expression(condition)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.finish_loop(node, label, body, start, exit)?;
+            }
+
+            Stmt::For { var_name, start_value, end_value, step, body, slot, label } => {
+                let Slot::Local(var) = self.slot(var_name, *slot)? else {
+                    return Err(self.error(format!("Loop variable '{}' must be local", var_name)));
+                };
+                // The counter lives in its own slot so the body cannot change how often it runs.
+                let (counter, limit, step_slot) = (var + 1, var + 2, var + 3);
+                self.reserve(step_slot);
+                let int = self.state.chunk.add_type(Type::Int);
+                self.expression(start_value)?;
+                self.emit(Op::CheckType(int));
+                self.emit(Op::SetLocal(counter));
+                self.expression(end_value)?;
+                self.emit(Op::CheckType(int));
+                self.emit(Op::SetLocal(limit));
+                match step {
+                    Some(step) => {
+                        self.expression(step)?;
+                        self.emit(Op::CheckType(int));
+                    }
+                    None => self.emit_constant(Value::Integer(1)),
+                }
+                self.emit(Op::SetLocal(step_slot));
+
+                let start = self.state.chunk.code.len() as u32;
+                let exit = self.emit(Op::ForRange(var, 0));
+                self.finish_loop(node, label, body, start, exit)?;
+            }
+
+            Stmt::ForEach { var_name, key_name, iterable, body, slot, label } => {
+                let Slot::Local(var) = self.slot(var_name, *slot)? else {
+                    return Err(self.error(format!("Loop variable '{}' must be local", var_name)));
+                };
+                // The loop walks a copy of the collection, so the body may change the original.
+                let pair = key_name.is_some();
+                let items = var + 1 + pair as u32;
+                let position = items + 1;
+                self.reserve(position);
+                self.expression(iterable)?;
+                self.emit(Op::SetLocal(items));
+                self.emit_constant(Value::Integer(0));
+                self.emit(Op::SetLocal(position));
+
+                let start = self.state.chunk.code.len() as u32;
+                let exit = self.emit(Op::ForEach(var, 0, pair));
+                self.finish_loop(node, label, body, start, exit)?;
+            }
+
+            Stmt::Break { label } | Stmt::Continue { label } => {
+                let is_break = matches!(node.stmt, Stmt::Break { .. });
+                let keyword = if is_break { "break" } else { "continue" };
+                let target = match label {
+                    Some(label) => self.state.loops.iter().rposition(|l| l.label.as_ref() == Some(label))
+                        .ok_or_else(|| self.error(format!("No enclosing loop is labelled '{}'", label)))?,
+                    None => self.state.loops.len().checked_sub(1)
+                        .ok_or_else(|| self.error(format!("'{}' outside of a loop", keyword)))?,
+                };
+                // Leave the `try` blocks inside the loop that the jump skips the end of.
+                for _ in self.state.loops[target].handlers..self.state.handlers {
+                    self.emit(Op::PopHandler);
+                }
+                let jump = self.emit(Op::Jump(0));
+                let target = &mut self.state.loops[target];
+                if is_break {
+                    target.breaks.push(jump);
+                } else {
+                    target.continues.push(jump);
+                }
+            }
+
+            // Top-level items were declared before any code was emitted.
+            Stmt::Func { .. } | Stmt::Struct { .. } | Stmt::Enum { .. } | Stmt::Impl { .. } => {
+                if self.state.depth > 0 {
+                    self.declare_item(node)?;
+                }
+            }
+
+            Stmt::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_constant(Value::Nil),
+                }
+                self.emit(Op::Return);
+            }
+
+            Stmt::Import { directory, identifier } => self.import(directory, identifier)?,
+
+            Stmt::Match { value, cases, slot } => self.match_cases(node, value, cases, *slot)?,
+
+            // An error in the body unwinds to the handler with its message on the
+            // stack, which is stored in the error variable or dropped.
+            Stmt::Try { body, handler, slot, .. } => {
+                let enter = self.emit(Op::PushHandler(0));
+                self.state.depth += 1;
+                self.state.handlers += 1;
+                for node in body {
+                    self.statement(node)?;
+                }
+                self.state.handlers -= 1;
+                self.span = node.span.clone();
+                self.emit(Op::PopHandler);
+                let exit = self.emit(Op::Jump(0));
+
+                self.patch(enter);
+                match slot {
+                    Some(Slot::Local(slot)) => {
+                        self.reserve(*slot);
+                        self.emit(Op::SetLocal(*slot));
+                    }
+                    _ => {
+                        self.emit(Op::Pop);
+                    }
+                }
+                for node in handler {
+                    self.statement(node)?;
+                }
+                self.state.depth -= 1;
+                self.patch(exit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tests the cases in order against the value stored in `slot`. A case that
+    /// does not match jumps to the next one; the body of one that does jumps past
+    /// the rest. Without a `case _` the value reaching the end is an error.
+    fn match_cases(&mut self, node: &StmtNode, value: &Expr, cases: &[MatchCase], slot: Option<Slot>) -> Result<(), ParseError> {
+        let Slot::Local(slot) = self.slot(" match", slot)? else {
+            return Err(self.error("The value of a match must be local".to_string()));
+        };
+        self.reserve(slot);
+        self.expression(value)?;
+        self.emit(Op::SetLocal(slot));
+
+        let mut exits = Vec::new();
+        let mut exhaustive = false;
+        for case in cases {
+            self.span = case.span.clone();
+            let next = match &case.pattern {
+                Pattern::Wildcard => {
+                    exhaustive = true;
+                    None
+                }
+                Pattern::Literal(literal) => {
+                    self.emit(Op::GetLocal(slot));
+                    self.emit_constant(literal_value(literal));
+                    self.emit(Op::Equal);
+                    Some(self.emit(Op::JumpIfFalse(0)))
+                }
+                Pattern::Variant { variant, bindings, .. } => {
+                    self.emit(Op::GetLocal(slot));
+                    let variant = self.name_constant(variant);
+                    self.emit(Op::IsVariant(variant));
+                    let next = self.emit(Op::JumpIfFalse(0));
+                    for (position, (_, binding)) in bindings.iter().enumerate() {
+                        if let Some(Slot::Local(binding)) = binding {
+                            self.reserve(*binding);
+                            self.emit(Op::GetLocal(slot));
+                            self.emit(Op::Payload(self.count(position, "bindings")?));
+                            self.emit(Op::SetLocal(*binding));
+                        }
+                    }
+                    Some(next)
+                }
+            };
+            for node in &case.body {
+                self.statement(node)?;
+            }
+            if let Some(next) = next {
+                exits.push(self.emit(Op::Jump(0)));
+                self.patch(next);
+            } else {
+                break;
+            }
+        }
+
+        self.span = node.span.clone();
+        if !exhaustive {
+            self.emit(Op::GetLocal(slot));
+            self.emit(Op::NoMatch);
+        }
+        for exit in exits {
+            self.patch(exit);
+        }
+        Ok(())
+    }
+
+    /// Compiles the body of a loop that re-enters at `start`, where `exit` leaves
+    /// it, and patches its jumps.
+    fn finish_loop(&mut self, node: &StmtNode, label: &Option<String>, body: &StmtNode, start: u32, exit: usize) -> Result<(), ParseError> {
+        let body = self.loop_body(label, body)?;
+        self.span = node.span.clone();
+        for jump in body.continues {
+            self.patch_to(jump, start);
+        }
+        self.emit(Op::Jump(start));
+        self.patch(exit);
+        for jump in body.breaks {
+            self.patch(jump);
+        }
+        Ok(())
+    }
+
+    /// Compiles a loop body and hands back the `break` and `continue` jumps
+    /// that target this loop.
+    fn loop_body(&mut self, label: &Option<String>, body: &StmtNode) -> Result<Loop, ParseError> {
+        let handlers = self.state.handlers;
+        self.state.loops.push(Loop { label: label.clone(), breaks: Vec::new(), continues: Vec::new(), handlers });
+        self.statement(body)?;
+        Ok(self.state.loops.pop().expect("loop stack underflow"))
+    }
+
+    /// Compiles an imported file as a module whose top-level code runs at the import.
+    fn import(&mut self, directory: &str, identifier: &str) -> Result<(), ParseError> {
+        if self.vm.loaded_modules.contains_key(directory) {
+            return Ok(());
+        }
+        if self.vm.loaded_modules.values().any(|alias| alias == identifier) {
+            return Err(self.error("you can't assign same name in imports".to_string()));
+        }
+        // Registered up front so a module importing itself stops here, but
+        // forgotten again if it fails so a later import retries it.
+        self.vm.loaded_modules.insert(directory.to_string(), identifier.to_string());
+        let result = self.load_module(directory, identifier);
+        if result.is_err() {
+            self.vm.loaded_modules.remove(directory);
+        }
+        result
+    }
+
+    fn load_module(&mut self, directory: &str, identifier: &str) -> Result<(), ParseError> {
+        let source = fs::read_to_string(directory)
+            .map_err(|e| self.error(format!("could not read file '{}': {}", directory, e)))?;
+        // Errors inside the module keep their own spans, so they point into its file.
+        let tokens = lexer::tokenize(&SourceFile::new(directory, &source))?;
+
+        let mut program = Parser::new(tokens).parse_program().map_err(|errors| self.module_failed(errors))?;
+        Resolver::for_module(self.vm, identifier).resolve(&mut program).map_err(|errors| self.module_failed(errors))?;
+        let init = Compiler::for_module(self.vm, identifier).compile(&program).map_err(|errors| self.module_failed(errors))?;
+        let index = self.vm.add_function(init);
+        self.emit(Op::Call(index, 0));
+        self.emit(Op::Pop);
+        Ok(())
+    }
+
+    /// Returns the first error of a failed module and keeps the rest for `compile`.
+    fn module_failed(&mut self, mut errors: Vec<ParseError>) -> ParseError {
+        let first = errors.remove(0);
+        self.module_errors.append(&mut errors);
+        first
+    }
+
+    // ---- expressions ----
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), ParseError> {
+        let outer = mem::replace(&mut self.span, expr.span.clone());
+        let result = self.expression_kind(expr);
+        self.span = outer;
+        result
+    }
+
+    fn expression_kind(&mut self, expr: &Expr) -> Result<(), ParseError> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.emit_constant(literal_value(literal)),
+
+            ExprKind::Grouping(inner) => self.expression(inner)?,
+
+            // A name without a slot is a function used as a value.
+            ExprKind::Variable { name, slot: None } if let Some(function_name) = self.lookup_name(name, &self.vm.function_names) => {
+                let index = self.vm.function_names[&function_name];
+                self.emit(Op::MakeClosure(index, 0));
+            }
+
+            ExprKind::Variable { name, slot: None } if native_functions::is_builtin(name) || self.vm.native_fns.borrow().contains_key(name) => {
+                self.emit_constant(Value::NativeFunction(name.clone()));
+            }
+
+            ExprKind::Variable { name, slot } => {
+                match self.slot(name, *slot)? {
+                    Slot::Local(index) => self.emit(Op::GetLocal(index)),
+                    Slot::Global(index) => self.emit(Op::GetGlobal(index)),
+                };
+            }
+
+            ExprKind::Lambda { params, return_type, body, captures } => {
+                let count = self.count(captures.len(), "captured variables")?;
+                let index = self.declare_function("<lambda>".to_string(), params, &return_type.as_deref().cloned(), body, false, captures.len());
+                for (name, slot) in captures {
+                    match self.slot(name, *slot)? {
+                        Slot::Local(index) => self.emit(Op::GetLocal(index)),
+                        Slot::Global(index) => self.emit(Op::GetGlobal(index)),
+                    };
+                }
+                self.emit(Op::MakeClosure(index, count));
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let op = self.binary_op(op)?;
+                self.emit(op);
+            }
+
+            ExprKind::Unary { operator, right } => {
+                self.expression(right)?;
+                match operator {
+                    Token::Minus => self.emit(Op::Negate),
+                    Token::Bang => self.emit(Op::Not),
+                    Token::BitNot => self.emit(Op::BitNot),
+                    other => return Err(self.error(format!("Unsupported unary operator {:?}", other))),
+                };
+            }
+
+            ExprKind::Cast { value, target } => {
+                self.expression(value)?;
+                let target = self.state.chunk.add_type(target.clone());
+                self.emit(Op::Cast(target));
+            }
+
+            ExprKind::Logical { left, operator: Token::NilCoalesce, right } => {
+                self.expression(left)?;
+                let jump = self.emit(Op::Coalesce(0));
+                self.expression(right)?;
+                self.patch(jump);
+            }
+
+            ExprKind::Logical { left, operator, right } => {
+                self.expression(left)?;
+                let jump = match operator {
+                    Token::Or => self.emit(Op::Or(0)),
+                    _ => self.emit(Op::And(0)),
+                };
+                self.expression(right)?;
+                self.emit(Op::AssertBool);
+                self.patch(jump);
+            }
+
+            // A receiver that lives in a variable is tested first, then accessed in
+            // place like `.field` or `.method()`, so methods can still change it.
+            ExprKind::SafeMember { object, name, args } => {
+                let member = match args {
+                    None => ExprKind::FieldGet { object: object.clone(), field: name.clone() },
+                    Some(args) => ExprKind::MethodCall { object: object.clone(), method: name.clone(), args: args.clone() },
+                };
+                if is_place(object) {
+                    self.expression(object)?;
+                    let skip = self.emit(Op::JumpIfNil(0));
+                    self.emit(Op::Pop);
+                    self.expression(&Expr::new(member, expr.span.clone()))?;
+                    self.patch(skip);
+                } else {
+                    self.expression(object)?;
+                    let skip = self.emit(Op::JumpIfNil(0));
+                    match args {
+                        None => {
+                            let field = self.name_constant(name);
+                            self.emit(Op::GetField(field));
+                        }
+                        Some(args) => {
+                            for arg in args {
+                                self.expression(arg)?;
+                            }
+                            let argc = self.count(args.len(), "arguments")?;
+                            let method = self.name_constant(name);
+                            self.emit(Op::Invoke(method, argc));
+                        }
+                    }
+                    self.patch(skip);
+                }
+            }
+
+            ExprKind::Assign { name, value, slot } => {
+                let slot = self.slot(name, *slot)?;
+                self.expression(value)?;
+                let name = self.name_constant(name);
+                self.emit(Op::CheckAssign(slot, name));
+                self.emit(Op::Dup);
+                self.emit_set(slot);
+            }
+
+            ExprKind::Index { list, index } => {
+                if let Some((slot, path)) = self.place(expr)? {
+                    let path = self.state.chunk.add_path(path);
+                    self.emit(Op::GetPath(slot, path));
+                } else {
+                    self.expression(list)?;
+                    self.expression(index)?;
+                    self.emit(Op::GetIndex);
+                }
+            }
+
+            ExprKind::Slice { list, start, end } => {
+                self.expression(list)?;
+                for bound in [start, end].into_iter().flatten() {
+                    self.expression(bound)?;
+                }
+                self.emit(Op::Slice(start.is_some(), end.is_some()));
+            }
+
+            ExprKind::FieldGet { object, field } => {
+                if let Some(enum_name) = self.enum_name(object) {
+                    let type_name = self.name_constant(&enum_name);
+                    let variant = self.name_constant(field);
+                    self.emit(Op::MakeVariant(type_name, variant, 0));
+                } else if let Some((slot, path)) = self.place(expr)? {
+                    let path = self.state.chunk.add_path(path);
+                    self.emit(Op::GetPath(slot, path));
+                } else {
+                    self.expression(object)?;
+                    let field = self.name_constant(field);
+                    self.emit(Op::GetField(field));
+                }
+            }
+
+            ExprKind::FieldSet { object, field, value } => {
+                let (slot, mut path) = self.place(object)?
+                    .ok_or_else(|| self.error("Cannot assign to a field of a temporary value".to_string()))?;
+                path.push(Access::Field(field.clone()));
+                self.expression(value)?;
+                let path = self.state.chunk.add_path(path);
+                self.emit(Op::SetPath(slot, path));
+            }
+
+            ExprKind::IndexSet { list, index, value } => {
+                let (slot, mut path) = self.place(list)?
+                    .ok_or_else(|| self.error("Cannot assign to an element of a temporary value".to_string()))?;
+                self.expression(index)?;
+                path.push(Access::Index);
+                self.expression(value)?;
+                let path = self.state.chunk.add_path(path);
+                self.emit(Op::SetPath(slot, path));
+            }
+
+            ExprKind::CompoundAssign { target, op, value } => {
+                let (slot, path) = self.place(target)?
+                    .ok_or_else(|| self.error("Cannot assign to a part of a temporary value".to_string()))?;
+                // Copy the indices so the read leaves them for the store
+                let keys = key_count(&path) as u32;
+                let path = self.state.chunk.add_path(path);
+                self.emit(Op::DupMany(keys));
+                self.emit(Op::GetPath(slot, path));
+                self.expression(value)?;
+                let op = self.binary_op(op)?;
+                self.emit(op);
+                self.emit(Op::SetPath(slot, path));
+            }
+
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.emit(Op::MakeList(elements.len() as u32));
+            }
+
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.emit(Op::MakeMap(entries.len() as u32));
+            }
+
+            ExprKind::Call { callee, arguments, .. } => {
+                let name = match &callee.kind {
+                    ExprKind::Variable { name, slot: None } => name,
+                    // A function value held in a variable or produced by an expression
+                    _ => {
+                        self.expression(callee)?;
+                        for argument in arguments {
+                            self.expression(argument)?;
+                        }
+                        let argc = self.count(arguments.len(), "arguments")?;
+                        self.emit(Op::CallValue(argc));
+                        return Ok(());
+                    }
+                };
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                let argc = self.count(arguments.len(), "arguments")?;
+
+                if let Some(struct_name) = self.lookup_name(name, &self.vm.struct_defs) {
+                    let struct_name = self.name_constant(&struct_name);
+                    self.emit(Op::MakeStruct(struct_name, argc));
+                } else if native_functions::is_builtin(name) || self.vm.native_fns.borrow().contains_key(name) {
+                    let name = self.name_constant(name);
+                    self.emit(Op::CallNative(name, argc));
+                } else if let Some(function_name) = self.lookup_name(name, &self.vm.function_names) {
+                    let index = self.vm.function_names[&function_name];
+                    self.check_arity(index, name, arguments.len())?;
+                    self.emit(Op::Call(index, argc));
+                } else {
+                    // Resolved by name when called, e.g. a function defined by a later run.
+                    let name = self.name_constant(name);
+                    self.emit(Op::CallNative(name, argc));
+                }
+            }
+
+            ExprKind::MethodCall { object, method, args } => {
+                if let Some(enum_name) = self.enum_name(object) {
+                    for arg in args {
+                        self.expression(arg)?;
+                    }
+                    let argc = self.count(args.len(), "values")?;
+                    let type_name = self.name_constant(&enum_name);
+                    let variant = self.name_constant(method);
+                    self.emit(Op::MakeVariant(type_name, variant, argc));
+                    return Ok(());
+                }
+                if let ExprKind::Variable { name: alias, slot: None } = &object.kind
+                    && self.vm.loaded_modules.values().any(|loaded| loaded == alias) {
+                    let full_name = format!("{}::{}", alias, method);
+                    let index = *self.vm.function_names.get(&full_name)
+                        .ok_or_else(|| self.error(format!("Module '{}' has no function '{}'", alias, method)))?;
+                    self.check_arity(index, method, args.len())?;
+                    for arg in args {
+                        self.expression(arg)?;
+                    }
+                    let argc = self.count(args.len(), "arguments")?;
+                    self.emit(Op::Call(index, argc));
+                    return Ok(());
+                }
+
+                let place = self.place(object)?;
+                if place.is_none() {
+                    self.expression(object)?;
+                }
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let argc = self.count(args.len(), "arguments")?;
+                let method = self.name_constant(method);
+                match place {
+                    Some((slot, path)) => {
+                        let path = self.state.chunk.add_path(path);
+                        self.emit(Op::InvokePath(slot, path, method, argc));
+                    }
+                    None => {
+                        self.emit(Op::Invoke(method, argc));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the index keys of a place expression rooted at a variable, such as
+    /// `grid[y][x]` or `player.pos`, and returns where it lives. Returns `None`
+    /// without emitting anything when the expression is not a place.
+    fn place(&mut self, expr: &Expr) -> Result<Option<(Slot, Vec<Access>)>, ParseError> {
+        match &expr.kind {
+            ExprKind::Variable { slot, .. } => Ok(slot.map(|slot| (slot, Vec::new()))),
+            ExprKind::Grouping(inner) => self.place(inner),
+            ExprKind::Index { list, index } => match self.place(list)? {
+                Some((slot, mut path)) => {
+                    self.expression(index)?;
+                    path.push(Access::Index);
+                    Ok(Some((slot, path)))
+                }
+                None => Ok(None),
+            },
+            ExprKind::FieldGet { object, field } => match self.place(object)? {
+                Some((slot, mut path)) => {
+                    path.push(Access::Field(field.clone()));
+                    Ok(Some((slot, path)))
+                }
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// The enum named by `State` in `State.Idle`, unless a variable has that name.
+    fn enum_name(&self, object: &Expr) -> Option<String> {
+        match &object.kind {
+            ExprKind::Variable { name, slot: None } => self.lookup_name(name, &self.vm.enum_defs),
+            _ => None,
+        }
+    }
+
+    fn binary_op(&self, op: &Token) -> Result<Op, ParseError> {
+        Ok(match op {
+            Token::Plus => Op::Add,
+            Token::Minus => Op::Subtract,
+            Token::Multiply => Op::Multiply,
+            Token::Divide => Op::Divide,
+            Token::FloorDivide => Op::FloorDivide,
+            Token::Modulo => Op::Modulo,
+            Token::Power => Op::Power,
+            Token::BitAnd => Op::BitAnd,
+            Token::BitOr => Op::BitOr,
+            Token::BitXor => Op::BitXor,
+            Token::ShiftLeft => Op::ShiftLeft,
+            Token::ShiftRight => Op::ShiftRight,
+            Token::Equals => Op::Equal,
+            Token::NotEquals => Op::NotEqual,
+            Token::Greater => Op::Greater,
+            Token::Lesser => Op::Less,
+            Token::GreaterEquals => Op::GreaterEqual,
+            Token::LesserEquals => Op::LessEqual,
+            other => return Err(self.error(format!("Unsupported binary operator {:?}", other))),
+        })
+    }
+
+    fn check_arity(&self, index: u32, name: &str, given: usize) -> Result<(), ParseError> {
+        let expected = self.vm.functions[index as usize].params.len();
+        if expected != given {
+            return Err(self.error(format!("Function '{}' expects {} args but got {}", name, expected, given)));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `expr` names a place that `Compiler::place` can address: a variable
+/// followed by indices and fields.
+fn is_place(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Variable { slot, .. } => slot.is_some(),
+        ExprKind::Grouping(inner) => is_place(inner),
+        ExprKind::Index { list: object, .. } | ExprKind::FieldGet { object, .. } => is_place(object),
+        _ => false,
+    }
+}
+
+fn literal_value(literal: &LiteralValue) -> Value {
+    match literal {
+        LiteralValue::Int(i) => Value::Integer(*i),
+        LiteralValue::Float(f) => Value::Float(*f),
+        LiteralValue::Str(s) => Value::String(s.clone()),
+        LiteralValue::Bool(b) => Value::Boolean(*b),
+        LiteralValue::Nil => Value::Nil,
+    }
+}
@@ -1,12 +1,216 @@
-use crate::tokens::Token;
+use std::fmt;
+
+use crate::{span::Span, tokens::Token, types::Type, value::Value};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseError {
-    UnexpectedToken { expected: Token, found: Option<Token>, line: usize },
-    UnkownType { type_name: String, line: usize },
-    UndeclaredVariable { name: String, line: usize },
-    TypeMismatch { expected: Token, found: Token, line: usize },
-    Return { value: Token }, 
-
-    RuntimeError { message: String, line: usize },
-}
\ No newline at end of file
+    LexError { message: String, span: Span },
+    UnexpectedToken { expected: Token, found: Option<Token>, span: Span },
+    UnkownType { type_name: String, span: Span },
+    UndeclaredVariable { name: String, span: Span },
+    TypeMismatch { expected: Type, found: Value, span: Span },
+    CompileError { message: String, span: Span },
+    RuntimeError { message: String, span: Span },
+    TypeError { message: String, span: Span },
+}
+
+impl ParseError {
+    pub fn span(&self) -> &Span {
+        match self {
+            ParseError::LexError { span, .. }
+            | ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnkownType { span, .. }
+            | ParseError::UndeclaredVariable { span, .. }
+            | ParseError::TypeMismatch { span, .. }
+            | ParseError::CompileError { span, .. }
+            | ParseError::RuntimeError { span, .. }
+            | ParseError::TypeError { span, .. } => span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::LexError { message, .. }
+            | ParseError::CompileError { message, .. }
+            | ParseError::RuntimeError { message, .. }
+            | ParseError::TypeError { message, .. } => message.clone(),
+            ParseError::UnexpectedToken { expected, found, .. } => {
+                // An expected identifier holds a description of what was wanted
+                let expected = match expected {
+                    Token::Identifier(what) => what.clone(),
+                    other => other.describe(),
+                };
+                let found = found.as_ref().map_or("end of input".to_string(), Token::describe);
+                format!("expected {}, found {}", expected, found)
+            }
+            ParseError::UnkownType { type_name, .. } => format!("unknown type '{}'", type_name),
+            ParseError::UndeclaredVariable { name, .. } => format!("undeclared variable '{}'", name),
+            ParseError::TypeMismatch { expected, found, .. } => {
+                format!("expected a value of type {}, found {}", expected, found)
+            }
+        }
+    }
+
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a script before it runs, as reported to editors and tools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// The diagnostic rustc-style, e.g. ``error: expected `:`, found `,` ``
+    /// followed by the location and a caret under the source.
+    pub fn render(&self) -> String {
+        self.span.render(&format!("{}: {}", self.severity, self.message))
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Self {
+        Diagnostic { severity: Severity::Error, message: error.message(), span: error.span().clone() }
+    }
+}
+
+/// Calls shown in a printed stack trace; runaway recursion would print thousands.
+const MAX_TRACE_LINES: usize = 20;
+
+/// A call that was active when a runtime error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// Function name, `<script>` for top-level code and `<module alias>` for a
+    /// module's. Methods are named `Type.method`.
+    pub function: String,
+    /// Alias of the imported module the function belongs to.
+    pub module: Option<String>,
+    /// Where the function was executing: the failing instruction for the
+    /// innermost frame, the call site for the others.
+    pub span: Span,
+}
+
+/// Why a script could not be run, as returned by `WolfEngine::run` and `eval`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WolfError {
+    /// The source could not be split into tokens.
+    Lex(Diagnostic),
+    /// Every syntax error in the file.
+    Parse(Vec<Diagnostic>),
+    /// Type errors found before the script started.
+    Type(Vec<Diagnostic>),
+    /// Undeclared variables and other problems found while compiling.
+    Compile(Vec<Diagnostic>),
+    /// An error raised while the script was running. `trace` lists the active
+    /// calls, innermost first.
+    Runtime { diagnostic: Diagnostic, trace: Vec<TraceFrame> },
+}
+
+impl WolfError {
+    /// Groups errors found before running under the stage of the first one.
+    /// Errors from an imported module surface while compiling, but keep the
+    /// stage that found them.
+    pub(crate) fn from_errors(errors: Vec<ParseError>) -> Self {
+        let first = errors.first().cloned().expect("no errors to report");
+        let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::from).collect();
+        match first {
+            ParseError::LexError { .. } => WolfError::Lex(diagnostics.remove(0)),
+            ParseError::UnexpectedToken { .. } => WolfError::Parse(diagnostics),
+            ParseError::TypeError { .. } => WolfError::Type(diagnostics),
+            _ => WolfError::Compile(diagnostics),
+        }
+    }
+
+    pub(crate) fn runtime(error: ParseError, trace: Vec<TraceFrame>) -> Self {
+        WolfError::Runtime { diagnostic: error.into(), trace }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            WolfError::Lex(diagnostic) | WolfError::Runtime { diagnostic, .. } => std::slice::from_ref(diagnostic),
+            WolfError::Parse(diagnostics) | WolfError::Type(diagnostics) | WolfError::Compile(diagnostics) => diagnostics,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            WolfError::Lex(_) => "Lexer Error",
+            WolfError::Parse(_) => "Parser Error",
+            WolfError::Type(_) => "Type Error",
+            WolfError::Compile(_) => "Compile Error",
+            WolfError::Runtime { .. } => "Runtime Error",
+        }
+    }
+}
+
+impl fmt::Display for WolfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rendered: Vec<String> = self.diagnostics().iter()
+            .map(|diagnostic| diagnostic.span.render(&format!("{}: {}", self.label(), diagnostic.message)))
+            .collect();
+        write!(f, "{}", rendered.join("\n"))?;
+
+        if let WolfError::Runtime { trace, .. } = self
+            && trace.len() > 1 {
+            write!(f, "\nstack trace (innermost first):")?;
+            for frame in trace.iter().take(MAX_TRACE_LINES) {
+                write!(f, "\n    in {} at {}", frame.function, frame.span)?;
+            }
+            if trace.len() > MAX_TRACE_LINES {
+                write!(f, "\n    ... {} more calls", trace.len() - MAX_TRACE_LINES)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WolfError {}
+
+#[cfg(test)]
+mod tests {
+    use super::WolfError;
+    use crate::WolfEngine;
+
+    #[test]
+    fn errors_are_grouped_by_stage() {
+        let mut engine = WolfEngine::new();
+        assert!(matches!(engine.run("let a: int = 1 $"), Err(WolfError::Lex(_))));
+        assert!(matches!(engine.run("let a int = 1\nlet b int = 2"), Err(WolfError::Parse(d)) if d.len() == 2));
+        assert!(matches!(engine.run("let a: int = true"), Err(WolfError::Type(_))));
+        assert!(matches!(engine.run("print nowhere"), Err(WolfError::Compile(_))));
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_trace() {
+        let mut engine = WolfEngine::new();
+        let error = engine.run_source("bag.wolf", "fn item(at: int) -> int\n    let items: list<int> = [1]\n    return items[at]\nend\nprint item(3)\n").unwrap_err();
+
+        let WolfError::Runtime { diagnostic, trace } = &error else {
+            panic!("expected a runtime error, got {:?}", error);
+        };
+        assert_eq!(diagnostic.message, "Index out of bounds! Len: 1, Index: 3");
+        let frames: Vec<String> = trace.iter().map(|frame| format!("{} {}", frame.function, frame.span)).collect();
+        assert_eq!(frames, ["item bag.wolf:3:17", "<script> bag.wolf:5:7"]);
+
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert!(boxed.to_string().starts_with("Runtime Error: Index out of bounds! Len: 1, Index: 3\n --> bag.wolf:3:17"));
+        assert!(boxed.to_string().ends_with("    in item at bag.wolf:3:17\n    in <script> at bag.wolf:5:7"));
+    }
+}
@@ -1,224 +1,553 @@
-use crate::tokens::{ Token};
-
-pub fn lexer(content: &str) -> Result<Vec<(Token, usize)>, String> {
-    let mut token: Vec<(Token, usize)> = Vec::new();
-    let chars: Vec<char> = content.chars().collect();
-    let mut i = 0;
-    let mut line = 1;
-    
-    while i < chars.len() {
-        let c = chars[i];
-
-        if c == '\n' {
-            line += 1;
-            i += 1;
-            continue;
-        }
+use std::{mem, rc::Rc};
 
-        // Skip spaces
-        if c.is_whitespace() {
-            i += 1;
-            continue;
-        }
+use crate::{error_handler::ParseError, span::{SourceFile, Span}, tokens::Token};
 
-        // ---------- Identifiers and keywords ----------
-        if c.is_alphabetic() || c == '_' {
-            let start = i;
-            i += 1;
-            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
-                i += 1;
-            }
+/// Tokenizes a script that did not come from a file.
+pub fn lexer(content: &str) -> Result<Vec<(Token, Span)>, ParseError> {
+    tokenize(&SourceFile::new("<input>", content))
+}
 
-            let slice: String = chars[start..i].iter().collect();
-
-            match slice.as_str() {
-                "let" => token.push((Token::Let, line)),
-                "int" => token.push((Token::TypeInt, line)),
-                "float" => token.push((Token::TypeFloat, line)),
-                "bool" => token.push((Token::TypeBool, line)),
-                "string" => token.push((Token::TypeString, line)),
-                "list" => token.push((Token::TypeList(Box::new(Token::Unknown)), line)),
-                "print" => token.push((Token::Print, line)),
-                "true" => token.push((Token::Boolean(true), line)),
-                "false" => token.push((Token::Boolean(false), line)),
-                "if" => token.push((Token::If, line)),
-                "else" => token.push((Token::Else, line)),
-                "while" => token.push((Token::While, line)),
-                "for" => token.push((Token::For, line)),
-                "and" => token.push((Token::And, line)),
-                "or" => token.push((Token::Or, line)),
-                "fn" => token.push((Token::Func, line)),
-                "struct" => token.push((Token::Struct, line)),
-                "impl" => token.push((Token::Impl, line)),
-                "range" => token.push((Token::Range, line)),
-                "return" => token.push((Token::Return, line)),
-                "import" => token.push((Token::Import, line)),
-                "as" => token.push((Token::As, line)),
-                //other
-                "end" => token.push((Token::EndOfCondition, line)),
-                _ => token.push((Token::Identifier(slice), line)),
-            }
+/// Tokenizes `file`, tagging each token with the position it starts at.
+pub fn tokenize(file: &Rc<SourceFile>) -> Result<Vec<(Token, Span)>, ParseError> {
+    let mut lexer = Lexer { file, chars: file.text.chars().collect(), i: 0, line: 1, line_start: 0 };
+    let end = lexer.chars.len();
+    lexer.scan(end)
+}
 
-            continue;
-        }
+/// A piece of a string literal: text and where it starts, or the tokens of a
+/// `{...}` placeholder with the spans of its braces.
+enum Part {
+    Text(String, Span),
+    Code(Vec<(Token, Span)>, Span, Span),
+}
 
-        // ---------- Numbers --------------
-        if c.is_ascii_digit() {
-            let start = i;
-            i += 1;
-            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
-                i += 1;
-            }
-            
-            let slice: String = chars[start..i].iter().collect();
-
-            if slice.contains('.') {
-                let value: f64 = slice.parse().map_err(|_| format!("Invalid float number: {}", slice))?;
-                token.push((Token::Float(value), line));
-            } else {
-                let value: i64 = slice.parse().map_err(|_| format!("Invalid integer: {}", slice))?;
-                token.push((Token::Integer(value), line));
-            }
+struct Lexer<'a> {
+    file: &'a Rc<SourceFile>,
+    chars: Vec<char>,
+    i: usize,
+    line: usize,
+    line_start: usize,
+}
 
-            continue;
-        }
+impl Lexer<'_> {
+    /// Tokenizes `chars[i..end]`; `end` is the closing `}` when scanning a placeholder.
+    fn scan(&mut self, end: usize) -> Result<Vec<(Token, Span)>, ParseError> {
+        let mut token: Vec<(Token, Span)> = Vec::new();
 
-        // ---------- Strings --------------
+        while self.i < end {
+            let c = self.chars[self.i];
 
-        if c == '"' {
-            i += 1; // skip opening
-            let start = i;
-            while i < chars.len() && chars[i] != '"' {
-                i += 1;
+            if c == '\n' {
+                self.line += 1;
+                self.i += 1;
+                self.line_start = self.i;
+                continue;
             }
-            if i >= chars.len() {
-                return Err("Unterminated string literal".to_string());
+
+            // Skip spaces
+            if c.is_whitespace() {
+                self.i += 1;
+                continue;
             }
-            let slice: String = chars[start..i].iter().collect();
-            token.push((Token::String(slice), line));
-            i += 1; // skip closing
-            continue;
-        }
 
-        // ---------- Operators ----------
-        match c {
-            
-            '=' => { 
-                if i + 1 < chars.len() && chars[i + 1] == '=' {
-                    token.push((Token::Equals, line));
-                    i += 2;
-                } else {
-                    token.push((Token::Assign, line));
-                    i += 1;
+            let span = Span::new(self.file, self.line, self.i - self.line_start + 1);
+            let error = |message: String| ParseError::LexError { message, span: span.clone() };
+
+            // ---------- Identifiers and keywords ----------
+            if c.is_alphabetic() || c == '_' {
+                let start = self.i;
+                self.i += 1;
+                while self.i < self.chars.len() && (self.chars[self.i].is_alphanumeric() || self.chars[self.i] == '_') {
+                    self.i += 1;
                 }
+
+                let slice: String = self.chars[start..self.i].iter().collect();
+
+                match slice.as_str() {
+                    "let" => token.push((Token::Let, span)),
+                    "int" => token.push((Token::TypeInt, span)),
+                    "float" => token.push((Token::TypeFloat, span)),
+                    "bool" => token.push((Token::TypeBool, span)),
+                    "string" => token.push((Token::TypeString, span)),
+                    "list" => token.push((Token::TypeList, span)),
+                    "print" => token.push((Token::Print, span)),
+                    "true" => token.push((Token::Boolean(true), span)),
+                    "false" => token.push((Token::Boolean(false), span)),
+                    "nil" => token.push((Token::Nil, span)),
+                    "if" => token.push((Token::If, span)),
+                    "else" => token.push((Token::Else, span)),
+                    "while" => token.push((Token::While, span)),
+                    "for" => token.push((Token::For, span)),
+                    "and" => token.push((Token::And, span)),
+                    "or" => token.push((Token::Or, span)),
+                    "fn" => token.push((Token::Func, span)),
+                    "struct" => token.push((Token::Struct, span)),
+                    "impl" => token.push((Token::Impl, span)),
+                    "enum" => token.push((Token::Enum, span)),
+                    "match" => token.push((Token::Match, span)),
+                    "case" => token.push((Token::Case, span)),
+                    "try" => token.push((Token::Try, span)),
+                    "catch" => token.push((Token::Catch, span)),
+                    "range" => token.push((Token::Range, span)),
+                    "return" => token.push((Token::Return, span)),
+                    "break" => token.push((Token::Break, span)),
+                    "continue" => token.push((Token::Continue, span)),
+                    "import" => token.push((Token::Import, span)),
+                    "as" => token.push((Token::As, span)),
+                    //other
+                    "end" => token.push((Token::EndOfCondition, span)),
+                    _ => token.push((Token::Identifier(slice), span)),
+                }
+
                 continue;
             }
 
-            '!' => {
-                if i + 1 < chars.len() && chars[i + 1] == '=' {
-                    token.push((Token::NotEquals, line));
-                    i += 2;
-                    
+            // ---------- Numbers --------------
+            if c.is_ascii_digit() {
+                let start = self.i;
+                self.i += 1;
+                while self.i < self.chars.len() && (self.chars[self.i].is_ascii_digit() || self.chars[self.i] == '.') {
+                    self.i += 1;
+                }
+            
+                let slice: String = self.chars[start..self.i].iter().collect();
+
+                if slice.contains('.') {
+                    let value: f64 = slice.parse().map_err(|_| error(format!("Invalid float number: {}", slice)))?;
+                    token.push((Token::Float(value), span));
                 } else {
-                    token.push((Token::Bang, line)); 
-                    i += 1;
+                    let value: i64 = slice.parse().map_err(|_| error(format!("Invalid integer: {}", slice)))?;
+                    token.push((Token::Integer(value), span));
                 }
+
                 continue;
             }
 
-            '<' => { 
-                if i + 1 < chars.len() && chars[i + 1] == '=' {
-                    token.push((Token::LesserEquals, line));
-                    i += 2;
-                } else {
-                    token.push((Token::Lesser, line));
-                    i += 1;
-                }
+            // ---------- Strings --------------
+            if c == '"' {
+                self.string(&mut token, span)?;
                 continue;
             }
 
-            '>' => { 
-                if i + 1 < chars.len() && chars[i + 1] == '=' {
-                    token.push((Token::GreaterEquals, line));
-                    i += 2;
-                } else {
-                    token.push((Token::Greater, line));
-                    i += 1;
+            // ---------- Operators ----------
+            match c {
+            
+                '=' => { 
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::Equals, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Assign, span));
+                        self.i += 1;
+                    }
+                    continue;
                 }
-                continue;
+
+                '!' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::NotEquals, span));
+                        self.i += 2;
+                    
+                    } else {
+                        token.push((Token::Bang, span)); 
+                        self.i += 1;
+                    }
+                    continue;
+                }
+
+                '<' => { 
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::LesserEquals, span));
+                        self.i += 2;
+                    } else if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '<' {
+                        token.push((Token::ShiftLeft, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Lesser, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+
+                '>' => { 
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::GreaterEquals, span));
+                        self.i += 2;
+                    } else if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '>' {
+                        // Also closes two type arguments, as in `list<list<int>>`; the parser splits it there
+                        token.push((Token::ShiftRight, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Greater, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+
+                '#' => {
+                    // Skip comment until end of line
+                    while self.i < end && self.chars[self.i] != '\n' {
+                        self.i += 1;
+                    }
+                    continue;
+                }
+
+                '+' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::PlusAssign, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Plus, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '-' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '>' {
+                        token.push((Token::Arrow, span));
+                        self.i += 2;
+                    } else if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::MinusAssign, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Minus, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '*' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '*' {
+                        token.push((Token::Power, span));
+                        self.i += 2;
+                    } else if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::MultiplyAssign, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Multiply, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '/' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '/' {
+                        token.push((Token::FloorDivide, span));
+                        self.i += 2;
+                    } else if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::DivideAssign, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Divide, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '%' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == '=' {
+                        token.push((Token::ModuloAssign, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Modulo, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '&' => { token.push((Token::BitAnd, span)); self.i += 1; continue; }
+                '|' => { token.push((Token::BitOr, span)); self.i += 1; continue; }
+                '^' => { token.push((Token::BitXor, span)); self.i += 1; continue; }
+                '~' => { token.push((Token::BitNot, span)); self.i += 1; continue; }
+                '(' => { token.push((Token::LParen, span)); self.i += 1; continue; }
+                ')' => { token.push((Token::RParen, span)); self.i += 1; continue; }
+                '{' => { token.push((Token::LBrace, span)); self.i += 1; continue; }
+                '}' => { token.push((Token::RBrace, span)); self.i += 1; continue; }
+                '[' => { token.push((Token::LBracket, span)); self.i += 1; continue; }
+                ']' => { token.push((Token::RBracket, span)); self.i += 1; continue; }
+                ',' => { token.push((Token::Comma, span)); self.i += 1; continue; }
+                ':' => {
+                    if self.i + 1 < self.chars.len() && self.chars[self.i + 1] == ':' {
+                        token.push((Token::DoubleColon, span));
+                        self.i += 2;
+                    } else {
+                        token.push((Token::Colon, span));
+                        self.i += 1;
+                    }
+                    continue;
+                }
+                '.' => { token.push((Token::Dot, span)); self.i += 1; continue; }
+                '?' if self.i + 1 < self.chars.len() && matches!(self.chars[self.i + 1], '?' | '.') => {
+                    let operator = if self.chars[self.i + 1] == '?' { Token::NilCoalesce } else { Token::QuestionDot };
+                    token.push((operator, span));
+                    self.i += 2;
+                    continue;
+                }
+                _ => {}
             }
 
-            '#' => {
-                // Skip comment until end of line
-                while i < content.len() && chars[i] != '\n' {
-                    i += 1;
+            return Err(error(format!("Unexpected character '{}'", c)));
+        }
+
+        Ok(token)
+    }
+
+    fn starts_triple_quote(&self, at: usize) -> bool {
+        self.chars[at.min(self.chars.len())..].starts_with(&['"'; 3])
+    }
+
+    /// Reads the `"..."` or `"""..."""` literal starting at `chars[i]`. Escapes are
+    /// decoded, and a literal with `{expr}` placeholders becomes the tokens of
+    /// `("text" + string(expr) + ...)`.
+    fn string(&mut self, token: &mut Vec<(Token, Span)>, span: Span) -> Result<(), ParseError> {
+        let unterminated = || ParseError::LexError { message: "Unterminated string literal".to_string(), span: span.clone() };
+        let triple = self.starts_triple_quote(self.i);
+        let closing = self.string_end(self.i).ok_or_else(unterminated)?;
+        let margin = if triple { self.margin(closing - 2) } else { 0 };
+        self.i += if triple { 3 } else { 1 };
+
+        // A triple-quoted literal starting with a line break begins on the next line
+        if triple && let Some(offset) = self.chars[self.i..].iter().position(|c| !matches!(c, ' ' | '\t'))
+            && self.chars[self.i + offset] == '\n' {
+            self.i += offset;
+            self.new_line(margin);
+        }
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut text_span = span.clone();
+        loop {
+            let c = self.chars[self.i];
+            match c {
+                '"' if !triple => break,
+                '"' if self.starts_triple_quote(self.i) => break,
+                '\\' => text.push(self.escape()?),
+                '{' => {
+                    let placeholder = Span::new(self.file, self.line, self.i - self.line_start + 1);
+                    self.i += 1;
+                    let end = self.placeholder_end(self.i).ok_or_else(unterminated)?;
+                    let code = self.scan(end)?;
+                    let close = Span::new(self.file, self.line, end - self.line_start + 1);
+                    if code.is_empty() {
+                        return Err(ParseError::LexError {
+                            message: "Empty placeholder in string; write '\\{' for a literal brace".to_string(),
+                            span: placeholder,
+                        });
+                    }
+                    self.i = end + 1;
+                    parts.push(Part::Text(mem::take(&mut text), text_span));
+                    parts.push(Part::Code(code, placeholder, close));
+                    text_span = Span::new(self.file, self.line, self.i - self.line_start + 1);
+                }
+                // The line holding the closing quotes only sets the margin
+                '\n' if triple && self.chars[self.i + 1..closing - 2].iter().all(|c| matches!(c, ' ' | '\t')) => {
+                    self.new_line(0);
+                    self.i = closing - 2;
+                }
+                '\n' => {
+                    text.push('\n');
+                    self.new_line(if triple { margin } else { 0 });
+                }
+                _ => {
+                    text.push(c);
+                    self.i += 1;
                 }
-                continue;
             }
+        }
+        self.i += if triple { 3 } else { 1 };
+        parts.push(Part::Text(text, text_span));
 
-            '+' => { token.push((Token::Plus, line)); i += 1; continue; }
-            '-' => { token.push((Token::Minus, line)); i += 1; continue; }
-            '*' => { token.push((Token::Multiply, line)); i += 1; continue; }
-            '/' => { token.push((Token::Divide, line)); i += 1; continue; }
-            '(' => { token.push((Token::LParen, line)); i += 1; continue; }
-            ')' => { token.push((Token::RParen, line)); i += 1; continue; }
-            '[' => { token.push((Token::LBracket, line)); i += 1; continue; }
-            ']' => { token.push((Token::RBracket, line)); i += 1; continue; }
-            ',' => { token.push((Token::Comma, line)); i += 1; continue; }
-            ':' => {
-                if i + 1 < chars.len() && chars[i + 1] == ':' {
-                    token.push((Token::DoubleColon, line));
-                    i += 2;
-                } else {
-                    token.push((Token::Colon, line));
-                    i += 1;
+        if let [Part::Text(text, _)] = parts.as_mut_slice() {
+            token.push((Token::String(mem::take(text)), span));
+            return Ok(());
+        }
+
+        token.push((Token::LParen, span.clone()));
+        // The tokens standing in for a placeholder point at its braces, so
+        // errors inside it are reported there rather than at the quote.
+        let mut first = true;
+        for part in parts {
+            let tokens = match part {
+                Part::Text(text, _) if text.is_empty() => continue,
+                Part::Text(text, text_span) => vec![(Token::String(text), text_span)],
+                Part::Code(code, open, close) => {
+                    let mut tokens = vec![(Token::TypeString, open.clone()), (Token::LParen, open)];
+                    tokens.extend(code);
+                    tokens.push((Token::RParen, close));
+                    tokens
                 }
-                continue;
+            };
+            if !first {
+                token.push((Token::Plus, tokens[0].1.clone()));
             }
-            '.' => { token.push((Token::Dot, line)); i += 1; continue; }
-            _ => {}
+            first = false;
+            token.extend(tokens);
         }
+        token.push((Token::RParen, span));
+        Ok(())
+    }
+
+    /// Moves past the line break at `chars[i]` and up to `margin` spaces or tabs after it.
+    fn new_line(&mut self, margin: usize) {
+        self.i += 1;
+        self.line += 1;
+        self.line_start = self.i;
+        while self.i - self.line_start < margin && matches!(self.chars.get(self.i), Some(' ' | '\t')) {
+            self.i += 1;
+        }
+    }
 
-        token.push((Token::EOF, line));
+    /// The indentation of closing quotes that sit on a line of their own, which is
+    /// stripped from every line of a triple-quoted literal.
+    fn margin(&self, closing: usize) -> usize {
+        let indent = self.chars[..closing].iter().rev().take_while(|c| matches!(c, ' ' | '\t')).count();
+        match closing.checked_sub(indent + 1) {
+            Some(at) if at >= self.i + 3 && self.chars[at] == '\n' => indent,
+            _ => 0,
+        }
+    }
+
+    /// Decodes the escape sequence starting at the backslash at `chars[i]`.
+    fn escape(&mut self) -> Result<char, ParseError> {
+        let span = Span::new(self.file, self.line, self.i - self.line_start + 1);
+        let error = |message: String| ParseError::LexError { message, span: span.clone() };
+        let c = self.chars[self.i + 1];
+        self.i += 2;
+        let decoded = match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '"' | '{' | '}' => c,
+            // \u{1F43A}
+            'u' => {
+                let close = self.chars[self.i..].iter().take(8).position(|c| *c == '}');
+                let hex: String = match close {
+                    Some(close) if self.chars[self.i] == '{' => self.chars[self.i + 1..self.i + close].iter().collect(),
+                    _ => return Err(error("Unicode escapes are written '\\u{1F43A}'".to_string())),
+                };
+                self.i += close.unwrap_or(0) + 1;
+                u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                    .ok_or_else(|| error(format!("Invalid unicode escape '\\u{{{}}}'", hex)))?
+            }
+            other => return Err(error(format!("Unknown escape sequence '\\{}'", other))),
+        };
+        Ok(decoded)
+    }
 
-        return Err(format!("Unexpected token starting at index {}", i));
+    /// Index of the `}` that closes the placeholder whose code starts at `from`.
+    fn placeholder_end(&self, from: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut j = from;
+        loop {
+            match self.chars.get(j)? {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(j),
+                '}' => depth -= 1,
+                '"' => j = self.string_end(j)?,
+                _ => {}
+            }
+            j += 1;
+        }
     }
 
-    Ok(token)
+    /// Index of the last quote of the string literal opening at `from`.
+    fn string_end(&self, from: usize) -> Option<usize> {
+        let triple = self.starts_triple_quote(from);
+        let mut j = from + if triple { 3 } else { 1 };
+        loop {
+            match self.chars.get(j)? {
+                '\\' => j += 1,
+                '{' => j = self.placeholder_end(j + 1)?,
+                '"' if !triple => return Some(j),
+                '"' if self.starts_triple_quote(j) => return Some(j + 2),
+                _ => {}
+            }
+            j += 1;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::lexer;
+    use crate::{lexer::lexer, tokens::Token};
 
     #[test]
-    fn testLexer()
+    fn test_lexer()
     {
         let content = "let string message = \"hello world\" let int num = 1 + 1 print message print num if test > >= < <= \"test\" print \"hello world\" end";
-        let tokens = match lexer(&content) {
+        let tokens = match lexer(content) {
             Ok(tokens) => {
                 tokens
             }
             Err(err) => {
-                eprintln!("Lexer error: {}", err);
+                eprintln!("Lexer error: {:?}", err);
                 return;
             }
         };
         println!("{:?}", tokens)
     }
     #[test]
-    fn testLexer2()
+    fn test_lexer2()
     {
         let content = "while if";
-        let tokens = match lexer(&content) {
+        let tokens = match lexer(content) {
             Ok(tokens) => {
                 tokens
             }
             Err(err) => {
-                eprintln!("Lexer error: {}", err);
+                eprintln!("Lexer error: {:?}", err);
                 return;
             }
         };
         println!("{:?}", tokens)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tracks_columns()
+    {
+        let error = lexer("let x: int = 1\n  print \"a\nb\" x\n\t$").unwrap_err();
+        assert_eq!(error.span().line, 4);
+        assert_eq!(error.span().column, 2);
+
+        let tokens = lexer("let x: int = 1\n  print x").unwrap();
+        let columns: Vec<(usize, usize)> = tokens.iter().map(|(_, span)| (span.line, span.column)).collect();
+        assert_eq!(columns, [(1, 1), (1, 5), (1, 6), (1, 8), (1, 12), (1, 14), (2, 3), (2, 9)]);
+    }
+
+    #[test]
+    fn decodes_escapes_and_placeholders()
+    {
+        let tokens = lexer(r#""a\tb \"q\" \{x\} \u{41}\\""#).unwrap();
+        assert_eq!(tokens[0].0, Token::String("a\tb \"q\" {x} A\\".to_string()));
+
+        let tokens: Vec<Token> = lexer(r#""HP: {hp}!""#).unwrap().into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, [
+            Token::LParen, Token::String("HP: ".to_string()), Token::Plus,
+            Token::TypeString, Token::LParen, Token::Identifier("hp".to_string()), Token::RParen,
+            Token::Plus, Token::String("!".to_string()), Token::RParen,
+        ]);
+
+        let error = lexer("print \"bad \\q\"").unwrap_err();
+        assert_eq!(error.message(), "Unknown escape sequence '\\q'");
+        assert_eq!(error.span().column, 12);
+    }
+
+    #[test]
+    fn placeholder_errors_point_into_the_string()
+    {
+        let tokens = lexer("print \"a {hp} b\"").unwrap();
+        let columns: Vec<usize> = tokens.iter().map(|(_, span)| span.column).collect();
+        assert_eq!(columns, [1, 7, 7, 10, 10, 10, 11, 13, 14, 14, 7]);
+
+        let engine = crate::WolfEngine::new();
+        let diagnostics = engine.check("<input>", "let x: int = 1\nprint \"a {1 +} b\"\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "expected an expression, found `)`");
+        assert_eq!((diagnostics[0].span.line, diagnostics[0].span.column), (2, 14));
+    }
+
+    #[test]
+    fn strips_the_margin_of_triple_quoted_strings()
+    {
+        let tokens = lexer("let t: string = \"\"\"\n    Hi \"you\"\n      two\n    \"\"\"\nprint t").unwrap();
+        assert_eq!(tokens[5].0, Token::String("Hi \"you\"\n  two".to_string()));
+        assert_eq!((tokens[6].1.line, tokens[6].1.column), (5, 1));
+    }
+}
@@ -5,219 +5,296 @@ pub mod error_handler;
 pub mod stdlib;
 pub mod native_functions;
 pub mod ast;
-pub mod interpreter;
-
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+pub mod bytecode;
+pub mod compiler;
+pub mod resolver;
+pub mod vm;
+pub mod value;
+pub mod types;
+pub mod checker;
+pub mod span;
+
+use std::rc::Rc;
 use parser::Parser;
-use lexer::lexer;
 use tokens::Token;
+pub use value::Value;
+pub use error_handler::WolfError;
+pub use vm::Overflow;
 
 
-use crate::{ast::{Stmt, StmtNode}, interpreter::{Function, Interpreter}};
+use crate::{ast::{ExprKind, Stmt, StmtNode}, checker::{FnSignature, TypeChecker}, compiler::Compiler, error_handler::{Diagnostic, ParseError}, resolver::Resolver, span::SourceFile, types::Type, vm::Vm};
 
-pub type NativeFn = Rc<dyn Fn(Vec<Token>) -> Token>;
+/// A host function. An `Err` stops the script with that message unless a `try`
+/// block catches it.
+pub type NativeFn = Rc<dyn Fn(Vec<Value>) -> Result<Value, String>>;
 
 pub struct WolfEngine {
-    globals: HashMap<String, Token>,
-    native_fns: Rc<RefCell<HashMap<String, NativeFn>>>,
-    functions: Rc<RefCell<HashMap<String, Function>>>,
-    interpreter: Interpreter
+    vm: Vm
+}
+
+impl Default for WolfEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WolfEngine {
     pub fn new() -> Self {
         WolfEngine {
-            globals: HashMap::new(),
-            functions: Rc::new(RefCell::new(HashMap::new())),
-            native_fns: Rc::new(RefCell::new(HashMap::new())),
-            interpreter: Interpreter::new()
+            vm: Vm::new()
         }
     }
 
+    /// Chooses what integer arithmetic does on overflow. Scripts stop with a
+    /// runtime error by default.
+    pub fn set_overflow(&mut self, mode: Overflow) {
+        self.vm.overflow = mode;
+    }
+
     pub fn register_module(&mut self, module_name: &str) {
-        self.interpreter.loaded_modules.insert(module_name.to_string(), module_name.to_string());
+        self.vm.loaded_modules.insert(module_name.to_string(), module_name.to_string());
     }
     
+    pub fn push_value(&mut self, name: &str, value: Value) {
+        let slot = self.vm.global_slot(name);
+        // A nil pushed by the host says nothing about what may be stored later
+        let ty = if value == Value::Nil { Type::Any } else { Type::of(&value) };
+        self.vm.global_types.entry(name.to_string()).or_insert(ty);
+        self.vm.globals[slot as usize] = value;
+    }
+
     pub fn push_int(&mut self, name: &str, value: i64) {
-        if let Some(scope) = self.interpreter.scopes.first_mut() {
-            scope.insert(name.to_string(), Token::Integer(value));
-        }
+        self.push_value(name, Value::Integer(value));
     }
 
     pub fn push_float(&mut self, name: &str, value: f64) {
-        if let Some(scope) = self.interpreter.scopes.first_mut() {
-            scope.insert(name.to_string(), Token::Float(value));
-        }
+        self.push_value(name, Value::Float(value));
     }
 
     pub fn push_str(&mut self, name: &str, value: &str) {
-        if let Some(scope) = self.interpreter.scopes.first_mut() {
-            scope.insert(name.to_string(), Token::String(value.to_string()));
-        }
+        self.push_value(name, Value::String(value.to_string()));
     }
 
     pub fn push_bool(&mut self, name: &str, value: bool) {
-        if let Some(scope) = self.interpreter.scopes.first_mut() {
-            scope.insert(name.to_string(), Token::Boolean(value));
-        }
+        self.push_value(name, Value::Boolean(value));
     }
 
-    pub fn push_list(&mut self, name: &str, value: Vec<Token>) {
-        if let Some(scope) = self.interpreter.scopes.first_mut() {
-            scope.insert(name.to_string(), Token::List(value));
-        }
+    pub fn push_list(&mut self, name: &str, value: Vec<Value>) {
+        self.push_value(name, Value::List(value));
+    }
+
+    pub fn push_map(&mut self, name: &str, value: Vec<(Value, Value)>) {
+        self.push_value(name, Value::Map(value));
     }
 
     pub fn push_fn<F>(&mut self, name: &str, func: F)
     where
-        F: Fn(Vec<Token>) -> Token + 'static,
+        F: Fn(Vec<Value>) -> Value + 'static,
+    {
+        self.push_fallible_fn(name, move |args| Ok(func(args)));
+    }
+
+    /// Registers a host function that can fail, such as one reading a file. Its
+    /// error is a runtime error that scripts can handle with `try ... catch`.
+    pub fn push_fallible_fn<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, String> + 'static,
     {
         let wrapped = Rc::new(func);
-        self.interpreter.native_fns.borrow_mut().insert(name.to_string(), wrapped);
+        self.vm.native_fns.borrow_mut().insert(name.to_string(), wrapped);
     }
 
-    
+    pub fn run(&mut self, content: &str) -> Result<(), WolfError> {
+        self.run_source("<input>", content)
+    }
+
+    /// Like `run`, but errors name `file_name` when they point at the source.
+    pub fn run_source(&mut self, file_name: &str, content: &str) -> Result<(), WolfError> {
+        self.execute(file_name, content, false).map(|_| ())
+    }
 
-    // pub fn run(&mut self, content: &str) -> Result<(), String> {
-    //     let tokens = match lexer(content) {
-    //         Ok(t) => t,
-    //         Err(e) => return Err(format!("Lexer Error: {}", e)),
-    //     };
-
-    //     let mut parser = Parser::new(tokens);
-
-    //     if let Some(scope) = parser.scopes.first_mut() {
-    //         for (k, v) in &self.globals {
-    //             scope.insert(k.clone(), v.clone());
-    //         }
-    //     }
-
-    //     // Parser çalıştırılır
-    //     while parser.current_token().is_some() {
-    //         if let Err(e) = parser.parse_statement() {
-    //             return Err(format!("Parser Error: {:?}", e));
-    //         }
-    //     }
-
-    //     if let Some(final_scope) = parser.scopes.first() {
-    //         self.globals = final_scope.clone();
-    //     }
-
-        
-        
-    //     Ok(())
-    // }
-
-    pub fn run(&mut self, content: &str) -> Result<(), String> {
+    /// Like `run`, but hands back the value of the last statement when it is an
+    /// expression such as `x + 1` or `add(1, 2)`. This is what the REPL echoes.
+    pub fn eval(&mut self, content: &str) -> Result<Option<Value>, WolfError> {
+        self.execute("<input>", content, true)
+    }
+
+    fn execute(&mut self, file_name: &str, content: &str, keep_result: bool) -> Result<Option<Value>, WolfError> {
         // 1. Run Lexer
-        let tokens = match lexer(content) {
+        let tokens = match lexer::tokenize(&SourceFile::new(file_name, content)) {
             Ok(t) => t,
-            Err(e) => return Err(format!("Lexer Error: {}", e)),
+            Err(e) => return Err(WolfError::from_errors(vec![e])),
         };
 
-        // 2. Initialize Parser
-        let mut parser = Parser::new(tokens);
+        // 2. Parse the whole file, collecting every syntax error
+        let mut ast_tree: Vec<StmtNode> = Parser::new(tokens).parse_program().map_err(WolfError::from_errors)?;
+
+        // The trailing expression becomes the script's return value
+        let mut has_result = false;
+        if keep_result
+            && let Some(last) = ast_tree.last_mut()
+            && let Stmt::Expression(expr) = &last.stmt
+            && !matches!(expr.kind, ExprKind::Assign { .. } | ExprKind::FieldSet { .. } | ExprKind::CompoundAssign { .. }) {
+            last.stmt = Stmt::Return { keyword: Token::Return, value: Some(expr.clone()) };
+            has_result = true;
+        }
+
+        // 3. Check types before anything runs
+        let mut checker = self.type_checker();
+        checker.check(&ast_tree).map_err(WolfError::from_errors)?;
+
+        // 4. Bind every variable to a slot
+        Resolver::new(&mut self.vm).resolve(&mut ast_tree).map_err(WolfError::from_errors)?;
+
+        // 5. Compile to bytecode and run it
+        let script = Compiler::new(&mut self.vm).compile(&ast_tree).map_err(WolfError::from_errors)?;
+        // Globals whose declaration was not reached keep no type
+        let result = self.vm.interpret(script);
+        let declared = checker.globals().iter()
+            .filter(|(name, _)| result.is_ok() || self.vm.global(name).is_some_and(|value| *value != Value::Nil))
+            .map(|(name, ty)| (name.clone(), ty.clone()));
+        self.vm.global_types.extend(declared.collect::<Vec<_>>());
+        let value = result?;
+        Ok(Some(value).filter(|value| has_result && *value != Value::Nil))
+    }
+
+    /// Reports every syntax, type and undeclared-variable error in a script
+    /// without running it. Type and resolve errors are only looked for once the
+    /// file parses.
+    pub fn check(&self, file_name: &str, content: &str) -> Vec<Diagnostic> {
+        let tokens = match lexer::tokenize(&SourceFile::new(file_name, content)) {
+            Ok(tokens) => tokens,
+            Err(e) => return vec![e.into()],
+        };
+        let mut program = match Parser::new(tokens).parse_program() {
+            Ok(program) => program,
+            Err(errors) => return errors.into_iter().map(Diagnostic::from).collect(),
+        };
 
-        let mut ast_tree: Vec<StmtNode> = Vec::new();
+        let mut errors = self.type_checker().check(&program).err().unwrap_or_default();
+        // Resolving assigns global slots, so it runs against a copy of the VM.
+        let mut vm = self.vm.clone();
+        if let Err(resolve_errors) = Resolver::new(&mut vm).resolve(&mut program) {
+            errors.extend(resolve_errors);
+        }
+        errors.into_iter().map(Diagnostic::from).collect()
+    }
 
-        // 3. Loop and build the AST
-        // We keep calling parse_statement() until we hit EOF
-        while parser.current_token().is_some() && *parser.current_token().unwrap() != Token::EOF {
-            match parser.parse_statement() {
-                Ok(stmt) => ast_tree.push(stmt),
-                Err(e) => return Err(format!("Parser Error: {:?}", e)),
+    /// Builds a type checker that knows about everything defined by earlier runs
+    /// and pushed from the host.
+    fn type_checker(&self) -> TypeChecker {
+        let mut checker = TypeChecker::new();
+        for (name, ty) in &self.vm.global_types {
+            checker.declare_global(name, ty.clone());
+        }
+        for (name, index) in &self.vm.function_names {
+            checker.declare_function(name, self.vm.functions[*index as usize].signature());
+        }
+        for (name, fields) in &self.vm.struct_defs {
+            checker.declare_struct(name, fields.clone());
+        }
+        for (name, variants) in &self.vm.enum_defs {
+            checker.declare_enum(name, variants.clone());
+        }
+        for (type_name, methods) in &self.vm.methods {
+            for (name, index) in methods {
+                checker.declare_method(type_name, name, self.vm.functions[*index as usize].signature());
             }
         }
-        self.interpreter.interpret(ast_tree).map_err(|e| format!("Interpreter error: {:?}", e))?;
-        // 4. PRINT THE AST (This is your test!)
-        // The {:#?} formatter checks if your Enum structures are correct
+        for name in self.vm.native_fns.borrow().keys() {
+            checker.declare_native(name);
+        }
+        for (directory, alias) in &self.vm.loaded_modules {
+            checker.declare_module(directory, alias);
+        }
+        checker
+    }
 
-        Ok(())
+    pub fn get_value(&self, name: &str) -> Option<&Value> {
+        // Names the compiler has seen but nothing was stored in yet read as missing
+        self.vm.global(name).filter(|value| **value != Value::Nil)
     }
 
     pub fn get_int(&self, name: &str) -> Option<i64> {
-        // Look in the first scope
-        if let Some(scope) = self.interpreter.scopes.first() {
-            if let Some(Token::Integer(n)) = scope.get(name) {
-                return Some(*n);
-            }
+        match self.get_value(name) {
+            Some(Value::Integer(n)) => Some(*n),
+            _ => None,
         }
-        None
     }
 
     pub fn get_float(&self, name: &str) -> Option<f64> {
-        if let Some(scope) = self.interpreter.scopes.first() {
-            if let Some(Token::Float(n)) = scope.get(name) {
-                return Some(*n);
-            }
+        match self.get_value(name) {
+            Some(Value::Float(n)) => Some(*n),
+            _ => None,
         }
-        None
     }
 
     pub fn get_str(&self, name: &str) -> Option<String> {
-        if let Some(scope) = self.interpreter.scopes.first() {
-            if let Some(Token::String(n)) = scope.get(name) {
-                return Some(n.to_string());
-            }
+        match self.get_value(name) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
         }
-        None
     }
+
     pub fn get_bool(&self, name: &str) -> Option<bool> {
-        if let Some(scope) = self.interpreter.scopes.first() {
-            if let Some(Token::Boolean(n)) = scope.get(name) {
-                return Some(*n);
-            }
+        match self.get_value(name) {
+            Some(Value::Boolean(b)) => Some(*b),
+            _ => None,
         }
-        None
     }
 
-    pub fn get_list(&self, name: &str) -> Option<Vec<Token>> {
-        if let Some(scope) = self.interpreter.scopes.first() {
-            if let Some(Token::List(n)) = scope.get(name) {
-                return Some(n.clone());
-            }
+    pub fn get_list(&self, name: &str) -> Option<Vec<Value>> {
+        match self.get_value(name) {
+            Some(Value::List(elements)) => Some(elements.clone()),
+            _ => None,
         }
-        None
     }
 
-    pub fn get_fn(&mut self, name: &str, args: Vec<Token>) -> Option<Token> {
-        let func = self.interpreter.functions.borrow().get(name).cloned()?;
-
-        if func.params.len() != args.len() {
-            panic!(
-                "WolfEngine: Function '{}' expects {} args but got {}",
-                name, func.params.len(), args.len()
-            );
+    pub fn get_map(&self, name: &str) -> Option<Vec<(Value, Value)>> {
+        match self.get_value(name) {
+            Some(Value::Map(entries)) => Some(entries.clone()),
+            _ => None,
         }
+    }
 
-        let mut call_scope = std::collections::HashMap::new();
-        for ((param_name, _param_type), arg_val) in func.params.iter().zip(args) {
-            call_scope.insert(param_name.clone(), arg_val);
-        }
-        self.interpreter.scopes.push(call_scope);
-
-        let mut return_value = Token::Unknown;
-        for stmt in func.body {
-            match self.interpreter.execute(stmt) {
-                Ok(_) => {}
-                Err(crate::error_handler::ParseError::Return { value }) => {
-                    return_value = value;
-                    break;
-                }
-                Err(_) => {
-                    self.interpreter.scopes.pop();
-                    return Some(Token::Unknown);
-                }
-            }
-        }
+    /// Calls a script function. An unknown name, a wrong number of arguments or
+    /// an error raised by the function is returned as a runtime error.
+    pub fn get_fn(&mut self, name: &str, args: Vec<Value>) -> Result<Value, WolfError> {
+        let Some(&index) = self.vm.function_names.get(name) else {
+            let error = ParseError::RuntimeError { message: format!("Undefined function '{}'", name), span: Default::default() };
+            return Err(WolfError::runtime(error, Vec::new()));
+        };
+        self.vm.call_function(index, args)
+    }
+
+    /// Every global that holds a value, sorted by name.
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<(&str, &Value)> = self.vm.global_names.iter()
+            .map(|(name, slot)| (name.as_str(), &self.vm.globals[*slot as usize]))
+            .filter(|(_, value)| **value != Value::Nil)
+            .collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
 
-        self.interpreter.scopes.pop();
-        Some(return_value)
+    /// Signatures of every script function, sorted by name.
+    pub fn functions(&self) -> Vec<(&str, FnSignature)> {
+        let mut functions: Vec<(&str, FnSignature)> = self.vm.function_names.iter()
+            .map(|(name, index)| (name.as_str(), self.vm.functions[*index as usize].signature()))
+            .collect();
+        functions.sort_by_key(|(name, _)| *name);
+        functions
+    }
+
+    /// Parameter and declared return types of a script function, so the host
+    /// knows what `get_fn` will hand back.
+    pub fn get_fn_signature(&self, name: &str) -> Option<FnSignature> {
+        self.vm.function(name).map(|func| func.signature())
     }
     
 }
 
-pub fn run_script(content: &str) -> Result<(), String> {
+pub fn run_script(content: &str) -> Result<(), WolfError> {
     let mut engine = WolfEngine::new();
     engine.run(content)
 }
@@ -226,13 +303,13 @@ pub fn run_script(content: &str) -> Result<(), String> {
 #[cfg(test)]
 mod test
 {
-    use crate::{WolfEngine, tokens::Token};
+    use crate::{WolfEngine, Value, types::Type};
 
     #[test]
     fn integer() {
         let mut engine = WolfEngine::new();
         engine.push_int("variable", 10);
-        engine.run("print variable");
+        engine.run("print variable").unwrap();
         let value = engine.get_int("variable");
         println!("{:?}", value);
     }
@@ -241,7 +318,7 @@ mod test
     fn float() {
         let mut engine = WolfEngine::new();
         engine.push_float("variable", 10.10);
-        engine.run("print variable");
+        engine.run("print variable").unwrap();
         let value = engine.get_float("variable");
         println!("{:?}", value);
     }
@@ -250,7 +327,7 @@ mod test
     fn strings() {
         let mut engine = WolfEngine::new();
         engine.push_str("variable", "hello");
-        engine.run("print variable");
+        engine.run("print variable").unwrap();
         let value = engine.get_str("variable");
         println!("{:?}", value);
     }
@@ -259,7 +336,7 @@ mod test
     fn bools() {
         let mut engine = WolfEngine::new();
         engine.push_bool("variable", false);
-        engine.run("print variable");
+        engine.run("print variable").unwrap();
         let value = engine.get_bool("variable");
         println!("{:?}", value);
     }
@@ -267,21 +344,79 @@ mod test
     #[test]
     fn lists() {
         let mut engine = WolfEngine::new();
-        engine.push_list("variable", vec![Token::Integer(10)]);
-        engine.run("print variable[0]");
+        engine.push_list("variable", vec![Value::Integer(10)]);
+        engine.run("print variable[0]").unwrap();
         let value = engine.get_list("variable");
         println!("{:?}", value);
     }
 
+    #[test]
+    fn maps() {
+        let mut engine = WolfEngine::new();
+        engine.push_map("prices", vec![(Value::String("sword".to_string()), Value::Integer(30))]);
+        engine.run(r#"
+            prices["shield"] = prices["sword"] + 5
+            let cost: int = prices["shield"]
+        "#).unwrap();
+        assert_eq!(engine.get_int("cost"), Some(35));
+        assert_eq!(engine.get_map("prices").map(|entries| entries.len()), Some(2));
+    }
+
+    #[test]
+    fn struct_values() {
+        let mut engine = WolfEngine::new();
+        engine.push_value("p", Value::StructInstance {
+            type_name: "Point".to_string(),
+            fields: vec![("x".to_string(), Value::Integer(1)), ("y".to_string(), Value::Integer(2))],
+        });
+        engine.run("let x: int = p.x").unwrap();
+        assert_eq!(engine.get_int("x"), Some(1));
+    }
+
+    #[test]
+    fn globals_keep_their_declared_types() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let xs: list<int> = []
+            let p: option<int> = nil
+            let o: option<int> = 3
+        "#).unwrap();
+
+        let err = engine.run(r#"
+            xs.push("str")
+            p = "a string"
+        "#).unwrap_err().to_string();
+        assert!(err.contains("Argument 'value' of 'push' expects int but got string"));
+        assert!(err.contains("Cannot assign string to 'p' of type option<int>"));
+
+        engine.run("o = nil").unwrap();
+        assert_eq!(engine.get_value("o"), None);
+    }
+
+    #[test]
+    fn failed_runs_do_not_declare_unreached_globals() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let reached: int = 1
+            error("stop")
+            let late: string = "never"
+        "#).unwrap_err();
+
+        let err = engine.run("print late.len()").unwrap_err().to_string();
+        assert!(err.contains("undeclared variable 'late'"));
+        let err = engine.run("reached = \"one\"").unwrap_err().to_string();
+        assert!(err.contains("Cannot assign string to 'reached' of type int"));
+    }
+
     #[test]
     fn test_push_fn_print() {
         let mut engine = WolfEngine::new();
 
         engine.push_fn("add", |args| {
-            if let (Some(Token::Integer(a)), Some(Token::Integer(b))) = (args.get(0), args.get(1)) {
-                Token::Integer(a + b)
+            if let (Some(Value::Integer(a)), Some(Value::Integer(b))) = (args.first(), args.get(1)) {
+                Value::Integer(a + b)
             } else {
-                Token::Unknown
+                Value::Nil
             }
         });
 
@@ -304,8 +439,27 @@ mod test
             end
         "#).unwrap();
 
-        let result = engine.get_fn("add", vec![Token::Integer(10), Token::Integer(20)]);
-        assert_eq!(result, Some(Token::Integer(30)));
-        println!("{:?}", result);
+        let result = engine.get_fn("add", vec![Value::Integer(10), Value::Integer(20)]);
+        assert_eq!(result, Ok(Value::Integer(30)));
+
+        let err = engine.get_fn("add", vec![Value::Integer(10)]).unwrap_err().to_string();
+        assert!(err.contains("Function 'add' expects 2 args but got 1"));
+        let err = engine.get_fn("missing", Vec::new()).unwrap_err().to_string();
+        assert!(err.contains("Undefined function 'missing'"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_fn_signature() {
+        let mut engine = WolfEngine::new();
+
+        engine.run(r#"
+            fn scale(x: int, factor: float) -> float
+                return factor
+            end
+        "#).unwrap();
+
+        let signature = engine.get_fn_signature("scale").unwrap();
+        assert_eq!(signature.return_type, Some(Type::Float));
+        assert_eq!(signature.params[1], ("factor".to_string(), Type::Float));
+    }
+}
@@ -1,8 +1,9 @@
-use std::{fs, io::{self, BufRead, Read}};
-use clap::{Arg, Parser as otherParser};
-use std::time::Instant;
+mod repl;
 
-use wolflang::{WolfEngine, lexer::lexer, parser};
+use std::fs;
+use clap::Parser as otherParser;
+
+use wolflang::WolfEngine;
 
 
 
@@ -35,7 +36,7 @@ fn run_file(path: &str) {
     
     let mut engine = WolfEngine::new();
     engine.register_module(path);
-    if let Err(e) = engine.run(&content) {
+    if let Err(e) = engine.run_source(path, &content) {
         eprintln!("{}", e);
     }
 
@@ -67,44 +68,8 @@ fn run() {
 "#);
     println!("   W O L F L A N G  v0.1");
     println!("---------------------------");
-    let mut lines: Vec<String> = Vec::new();
-    let mut content;
-    loop {
-        let stdin = io::stdin();
-        let mut buffer: String = String::new();
-        for line in stdin.lock().lines() {
-            let line = line.expect("read error");
-
-            if line.trim() == "run()" {
-                // for döngüsünü bitir
-                break;
-            }
-
-            lines.push(line);
-        }
-
-        content = lines.join("");
-        break;
-    }
-    let tokens = match lexer(&content) {
-        Ok(tokens) => {
-            tokens
-        }
-        Err(err) => {
-            eprintln!("Lexer error: {}", err);
-            return;
-        }
-    };
-    println!("{:?}", tokens);
-
-    let mut parser = parser::Parser::new(tokens);
-
-    while parser.current_token().is_some() {
-        if let Err(e) = parser.parse_statement() {
-            eprintln!("❌ Parser Error: {:?}", e);
-            break;
-        }
-    }
+    println!("Type :help for commands, :quit to leave.");
+    repl::run();
 }
 
 
@@ -114,21 +79,21 @@ mod tests {
 
     #[test]
     fn counter() {
-        let mut Engine = WolfEngine::new();
+        let mut engine = WolfEngine::new();
         let mut counter: i64 = 0;
         while counter < 10 {
             
-            Engine.push_int("counter", counter);
+            engine.push_int("counter", counter);
     
             let code = r#"
                 counter = counter + 1
             "#;
     
-            if let Err(e) = Engine.run(code) {
+            if let Err(e) = engine.run(code) {
                 eprintln!("{}", e);
             }
     
-            let value = Engine.get_int("counter").unwrap();
+            let value = engine.get_int("counter").unwrap();
             counter = value;
             println!("{:?}", value);
         }
@@ -1,46 +1,101 @@
-use crate::tokens::Token;
+use crate::value::Value;
+use crate::types::Type;
 use crate::error_handler::ParseError;
+use crate::span::Span;
 use std::io::{self, Write};
 
 
-pub fn dispatch(name: &str, args: Vec<Token>) -> Option<Result<Token, ParseError>> {
+/// Names handled by `dispatch`, so the compiler can tell them apart from script functions.
+pub fn is_builtin(name: &str) -> bool {
+    matches!(name, "input" | "clear" | "int" | "float" | "string" | "error")
+}
+
+pub fn dispatch(name: &str, args: Vec<Value>) -> Option<Result<Value, ParseError>> {
     match name {
         "input" => Some(native_input(args)),
         "clear" => Some(native_clear(args)),
+        "int" => Some(native_convert(args, &Type::Int)),
+        "float" => Some(native_convert(args, &Type::Float)),
+        "string" => Some(native_convert(args, &Type::String)),
+        "error" => Some(native_error(args)),
         _ => None
     }
 }
 
-fn native_input(args: Vec<Token>) -> Result<Token, ParseError> {
+fn native_input(args: Vec<Value>) -> Result<Value, ParseError> {
 
     if let Some(first_arg) = args.first() {
         match first_arg {
-            Token::String(s) => print!("{}", s),
+            Value::String(s) => print!("{}", s),
             _ => print!("{:?}", first_arg),
         }
         // Flush stdout to ensure prompt appears before input
-        io::stdout().flush().map_err(|_| ParseError::UnkownType { 
-            type_name: "IO Flush Error".to_string(),
-            line: 0
-        })?;
+        io::stdout().flush().map_err(|error| io_error("write the prompt", error))?;
     }
 
     let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer).map_err(|_| ParseError::UnkownType { 
-        type_name: "Failed to read input".to_string(),
-        line: 0
-    })?;
+    let read = io::stdin().read_line(&mut buffer).map_err(|error| io_error("read input", error))?;
+    if read == 0 {
+        return Err(ParseError::RuntimeError {
+            message: "input() reached the end of input".to_string(),
+            span: Span::default(),
+        });
+    }
 
-    Ok(Token::String(buffer.trim_end().to_string()))
+    Ok(Value::String(buffer.trim_end().to_string()))
 }
 
-fn native_clear(_args: Vec<Token>) -> Result<Token, ParseError> {
+fn native_clear(_args: Vec<Value>) -> Result<Value, ParseError> {
     print!("\x1B[2J\x1B[1;1H");
     
-    io::stdout().flush().map_err(|_| ParseError::UnkownType { 
-        type_name: "IO Flush Error".to_string(),
-        line: 0 
-    })?;
+    io::stdout().flush().map_err(|error| io_error("clear the screen", error))?;
+
+    Ok(Value::Boolean(true))
+}
+
+fn io_error(action: &str, error: io::Error) -> ParseError {
+    ParseError::RuntimeError { message: format!("Failed to {}: {}", action, error), span: Span::default() }
+}
 
-    Ok(Token::Boolean(true))
-}
\ No newline at end of file
+/// `error(message)` stops the script with `message`, or jumps to the enclosing `catch`.
+fn native_error(args: Vec<Value>) -> Result<Value, ParseError> {
+    let message = match args.as_slice() {
+        [Value::String(message)] => message.clone(),
+        [other] => other.to_string(),
+        _ => format!("error() takes exactly one argument, found {}", args.len()),
+    };
+    Err(ParseError::RuntimeError { message, span: Span::default() })
+}
+
+/// Converts `value` for `value as target` and the `int`, `float` and `string` builtins.
+pub fn convert(value: &Value, target: &Type) -> Result<Value, String> {
+    let converted = match (value, target) {
+        (Value::Integer(n), Type::Int) => Some(Value::Integer(*n)),
+        // Truncates towards zero; NaN, infinities and out of range floats have no int
+        (Value::Float(f), Type::Int) if f.is_finite() && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            Some(Value::Integer(*f as i64))
+        }
+        (Value::Boolean(b), Type::Int) => Some(Value::Integer(*b as i64)),
+        (Value::String(s), Type::Int) => s.trim().parse().ok().map(Value::Integer),
+        (Value::Integer(n), Type::Float) => Some(Value::Float(*n as f64)),
+        (Value::Float(f), Type::Float) => Some(Value::Float(*f)),
+        (Value::String(s), Type::Float) => s.trim().parse().ok().map(Value::Float),
+        (Value::String(s), Type::String) => Some(Value::String(s.clone())),
+        (other, Type::String) => Some(Value::String(other.to_string())),
+        _ => None,
+    };
+    converted.ok_or_else(|| match value {
+        Value::String(s) => format!("Cannot convert \"{}\" to {}", s, target),
+        other => format!("Cannot convert {} to {}", other, target),
+    })
+}
+
+fn native_convert(args: Vec<Value>, target: &Type) -> Result<Value, ParseError> {
+    let [value] = args.as_slice() else {
+        return Err(ParseError::RuntimeError {
+            message: format!("{}() takes exactly one argument, found {}", target, args.len()),
+            span: Span::default(),
+        });
+    };
+    convert(value, target).map_err(|message| ParseError::RuntimeError { message, span: Span::default() })
+}
@@ -1,21 +1,82 @@
-use std::{collections::HashMap, convert::identity, ptr::null, thread::Scope, vec};
-
-use crate::{ast::Stmt, ast::LiteralValue, ast::Expr, error_handler::ParseError, tokens::{self, Token}};
+use crate::{ast::Stmt, ast::LiteralValue, ast::{Expr, ExprKind, MatchCase, Pattern, Variant}, checker::FnSignature, error_handler::ParseError, span::Span, tokens::Token, types::Type};
 use crate::ast::StmtNode;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parser {
-    tokens: Vec<(Token, usize)>,
+    tokens: Vec<(Token, Span)>,
     pos: usize,
     output: Vec<String>,
-    // A stack of HashMaps to manage variable scopes.
-    // The last HashMap in the Vec is the innermost (current) scope.
-    
+    /// Syntax errors recovered from so far.
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<(Token, usize)>) -> Self {
-        Parser { tokens, pos: 0, output: Vec::new() }
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        Parser { tokens, pos: 0, output: Vec::new(), errors: Vec::new() }
+    }
+
+    /// Parses every statement in the file. A statement that fails to parse is
+    /// skipped so that the errors in the rest of the file are reported too.
+    pub fn parse_program(&mut self) -> Result<Vec<StmtNode>, Vec<ParseError>> {
+        let mut program = Vec::new();
+        while self.current_token().is_some_and(|token| *token != Token::EOF) {
+            if let Some(node) = self.parse_statement_or_recover() {
+                program.push(node);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn parse_statement_or_recover(&mut self) -> Option<StmtNode> {
+        let start = self.pos;
+        match self.parse_statement() {
+            Ok(node) => Some(node),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize(start);
+                None
+            }
+        }
+    }
+
+    /// Skips the rest of the statement starting at `start`. A block statement is
+    /// skipped up to its matching `end` (and `else` block); anything else up to
+    /// the next token that can begin a statement.
+    fn synchronize(&mut self, start: usize) {
+        // A labelled loop opens its block after `name:`
+        let opener = match (self.tokens.get(start), self.tokens.get(start + 1)) {
+            (Some((Token::Identifier(_), _)), Some((Token::Colon, _))) => start + 2,
+            _ => start,
+        };
+        if opens_block_at(&self.tokens, opener) {
+            self.pos = opener;
+            let mut depth = 0usize;
+            while let Some(token) = self.current_token() {
+                if opens_block_at(&self.tokens, self.pos) {
+                    depth += 1;
+                } else if *token == Token::EndOfCondition {
+                    depth = depth.saturating_sub(1);
+                }
+                self.pos += 1;
+                if depth == 0 && !self.check(Token::Else) {
+                    return;
+                }
+            }
+            return;
+        }
+
+        self.pos = self.pos.max(start + 1);
+        while let Some(token) = self.current_token() {
+            if starts_statement(token) {
+                return;
+            }
+            self.pos += 1;
+        }
     }
 
     fn token_to_literal(&self, token: Token) -> LiteralValue {
@@ -34,8 +95,12 @@ impl Parser {
         self.tokens.get(self.pos).map(|(token, _)| token)
     }
 
-    pub fn current_line(&self) -> usize {
-        self.tokens.get(self.pos).map(|(_, line)| *line).unwrap_or(0)
+    /// Where the current token starts. Past the end this is the last token, so
+    /// that "found end of input" errors still point somewhere useful.
+    pub fn current_span(&self) -> Span {
+        self.tokens.get(self.pos).or(self.tokens.last())
+            .map(|(_, span)| span.clone())
+            .unwrap_or_default()
     }
 
     /// Returns a reference to the next token without consuming it.
@@ -47,7 +112,7 @@ impl Parser {
     /// Checks if the current token matches the expected `token_type`.
     /// If it matches, consumes the token and advances the parser. If not, it returns an `Err`.
     fn eat(&mut self, token_type: Token) -> Result<(), ParseError> {
-        let line = self.current_line();
+        let span = self.current_span();
         // gets token type from current token
         if let Some(tok) = self.current_token() {
             // Looks if tok equals to token type
@@ -61,7 +126,7 @@ impl Parser {
                 Err(ParseError::UnexpectedToken {
                     expected: token_type,
                     found: Some(tok.clone()),
-                    line
+                    span
                 })
             }
         }
@@ -70,7 +135,7 @@ impl Parser {
             Err(ParseError::UnexpectedToken {
                 expected: token_type,
                 found: None,
-                line
+                span
             })
         }
     }
@@ -83,8 +148,8 @@ impl Parser {
             self.pos += 1;
             name
         } else {
-            let line = self.current_line();
-            return  Err(ParseError::UnexpectedToken { expected: Token::TypeString, found: self.current_token().cloned(), line });
+            let span = self.current_span();
+            return  Err(ParseError::UnexpectedToken { expected: Token::Identifier("module path".to_string()), found: self.current_token().cloned(), span });
         };
 
         self.eat(Token::As)?;
@@ -93,11 +158,11 @@ impl Parser {
             self.pos += 1;
             name
         } else {
-            let line = self.current_line();
+            let span = self.current_span();
             return Err(ParseError::UnexpectedToken {
                 expected: Token::Identifier("module alias".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
         Ok(Stmt::Import { directory: dir, identifier: iden})
@@ -113,11 +178,11 @@ impl Parser {
             self.pos += 1;
             name
         } else {
-            let line = self.current_line();
+            let span = self.current_span();
             return Err(ParseError::UnexpectedToken {
                 expected: Token::Identifier("name".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
 
@@ -134,138 +199,138 @@ impl Parser {
             name: var_name,
             data_type: declared_type,
             value: value_expr,
+            slot: None,
         })
         
     }
 
-    fn parse_type(&mut self) -> Result<Token, ParseError> {
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
         // After `idenitifier` → expect type (number/string/bool)
         if let Some(next) = self.current_token().cloned() {
             match next {
-                Token::TypeInt | Token::TypeString | Token::TypeBool | Token::TypeFloat => {
-                    self.pos += 1;
-                    Ok(next)
-                }
+                Token::TypeInt => { self.pos += 1; Ok(Type::Int) }
+                Token::TypeFloat => { self.pos += 1; Ok(Type::Float) }
+                Token::TypeString => { self.pos += 1; Ok(Type::String) }
+                Token::TypeBool => { self.pos += 1; Ok(Type::Bool) }
 
-                Token::TypeList(_) => {
+                Token::TypeList => {
                     self.pos += 1;
                     self.eat(Token::Lesser)?;
                     let inner_type = self.parse_type()?;
-                    self.eat(Token::Greater)?;
+                    self.eat_type_close()?;
 
-                    Ok(Token::TypeList(Box::new(inner_type)))
+                    Ok(Type::List(Box::new(inner_type)))
 
                 },
 
+                // `map` is only a type name when followed by `<`, so it stays usable as a variable name
+                // fn(int, int) -> bool
+                Token::Func => {
+                    self.pos += 1;
+                    self.eat(Token::LParen)?;
+                    let mut params = Vec::new();
+                    if !self.check(Token::RParen) {
+                        loop {
+                            params.push(self.parse_type()?);
+                            if self.check(Token::Comma) {
+                                self.eat(Token::Comma)?;
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                    self.eat(Token::RParen)?;
+                    let return_type = if self.check(Token::Arrow) {
+                        self.eat(Token::Arrow)?;
+                        Some(Box::new(self.parse_type()?))
+                    } else {
+                        None
+                    };
+
+                    Ok(Type::Function(params, return_type))
+                }
+
+                Token::Identifier(name) if name == "option" && self.peek() == Some(&Token::Lesser) => {
+                    self.pos += 2;
+                    let inner_type = self.parse_type()?;
+                    self.eat_type_close()?;
+
+                    Ok(Type::Option(Box::new(inner_type)))
+                }
+
+                Token::Identifier(name) if name == "map" && self.peek() == Some(&Token::Lesser) => {
+                    self.pos += 2;
+                    let key_type = self.parse_type()?;
+                    self.eat(Token::Comma)?;
+                    let value_type = self.parse_type()?;
+                    self.eat_type_close()?;
+
+                    Ok(Type::Map(Box::new(key_type), Box::new(value_type)))
+                }
+
                 Token::Identifier(name) => {
                     self.pos += 1;
                     if self.check(Token::DoubleColon) {
                         self.eat(Token::DoubleColon)?;
                         if let Some(Token::Identifier(type_name)) = self.current_token().cloned() {
                             self.pos += 1;
-                            return Ok(Token::Identifier(format!("{}::{}", name, type_name)));
+                            return Ok(Type::Named(format!("{}::{}", name, type_name)));
                         }
                     }
-                    Ok(Token::Identifier(name))
+                    Ok(Type::Named(name))
                 }
 
                 _ => {
-                    let line = self.current_line();
+                    let span = self.current_span();
                     // throw error
                     Err(ParseError::UnexpectedToken {
-                        expected: Token::TypeInt, // Placeholder
+                        expected: Token::Identifier("type name".to_string()),
                         found: Some(next),
-                        line
+                        span
                     })
                 }
             }
         } else {
-            let line = self.current_line();
-            return Err(ParseError::UnexpectedToken {
-                expected: Token::TypeInt,
+            let span = self.current_span();
+            Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("type name".to_string()),
                 found: None,
-                line
-            });
+                span
+            })
         }
         
     }
 
-    // Note: Typo in function name, should be "parse_assignment"
-    fn parse_assigment(&mut self) -> Result<Stmt, ParseError> {
-        // 1. Get the variable name
-        let var_name = if let Some(Token::Identifier(name)) = self.current_token().cloned() {
-            self.pos += 1;
-            name
-        } else {
-            let line = self.current_line();
-            return Err(ParseError::UnexpectedToken {
-                expected: Token::Identifier("variable name".to_string()),
-                found: self.current_token().cloned(),
-                line
-            });
-        };
-
-        // 2. Consume '='
-        self.eat(Token::Assign)?;
-
-        // 3. Parse the value (the right-hand side)
-        let value_expr = self.parse_expr()?;
-
-        // 4. Return an Expression Statement containing the Assignment Expression
-        // This wraps the assignment logic into a statement so it can sit in a block.
-        Ok(Stmt::Expression(Expr::Assign {
-            name: var_name,
-            value: Box::new(value_expr),
-        }))
-    }
-
-    fn parse_list_index(&mut self, list_name: String) -> Result<Expr, ParseError> {
-        // Start with the variable: 'arr'
-        let mut current_expr = Expr::Variable(list_name);
-
-        // While we see '['...
-        while self.check(Token::LBracket) {
-            self.eat(Token::LBracket)?; 
-            
-            // Parse the index expression (e.g., 5 + x)
-            let index_expr = self.parse_expr()?;
-            
-            self.eat(Token::RBracket)?; 
-
-            // Wrap the current expression in an Index node
-            // arr[0] becomes Index(arr, 0)
-            // arr[0][1] becomes Index(Index(arr, 0), 1)
-            current_expr = Expr::Index {
-                list: Box::new(current_expr),
-                index: Box::new(index_expr),
-            };
+    /// Eats the `>` closing a type argument list. The lexer reads the end of
+    /// `list<list<int>>` as `>>`, which is split here into two `>`.
+    fn eat_type_close(&mut self) -> Result<(), ParseError> {
+        if let Some((token @ Token::ShiftRight, span)) = self.tokens.get_mut(self.pos) {
+            *token = Token::Greater;
+            span.column += 1;
+            return Ok(());
         }
-
-        Ok(current_expr)
+        self.eat(Token::Greater)
     }
 
-    fn parse_list_assignment(&mut self, list_name: String) -> Result<Stmt, ParseError> {
-        let mut indices = Vec::new();
+    /// Parses one `[index]` or `[start:end]` after `list`.
+    fn parse_index(&mut self, list: Expr) -> Result<Expr, ParseError> {
+        let bracket = self.current_span();
+        self.eat(Token::LBracket)?;
 
-        // 1. Collect all indices: [0][1]...
-        while self.check(Token::LBracket) {
-            self.eat(Token::LBracket)?;
-            indices.push(self.parse_expr()?);
+        // Parse the index expression (e.g., 5 + x); a slice may leave it out: arr[:2]
+        let index = if self.check(Token::Colon) { None } else { Some(self.parse_expr()?) };
+
+        if !self.check(Token::Colon) && let Some(index) = index {
             self.eat(Token::RBracket)?;
+            // arr[0][1] becomes Index(Index(arr, 0), 1)
+            return Ok(Expr::new(ExprKind::Index { list: Box::new(list), index: Box::new(index) }, bracket));
         }
 
-        // 2. Expect Assignment '='
-        self.eat(Token::Assign)?;
-
-        // 3. Parse the value to assign
-        let value = self.parse_expr()?;
-
-        // 4. Return the Statement
-        Ok(Stmt::ListAssign {
-            list_name,
-            indices,
-            value,
-        })
+        // A slice: arr[1:3], arr[1:], arr[:-1]
+        self.eat(Token::Colon)?;
+        let end = if self.check(Token::RBracket) { None } else { Some(Box::new(self.parse_expr()?)) };
+        self.eat(Token::RBracket)?;
+        Ok(Expr::new(ExprKind::Slice { list: Box::new(list), start: index.map(Box::new), end }, bracket))
     }
 
     /// Parses a 'print' statement. e.g., print "Hello", 10 + 5
@@ -277,7 +342,7 @@ impl Parser {
         exprs.push(self.parse_expr()?);
 
         while self.check(Token::Comma)  {
-            self.eat(Token::Comma);
+            self.eat(Token::Comma)?;
             exprs.push(self.parse_expr()?);
         }
 
@@ -295,25 +360,26 @@ impl Parser {
             if *tok == Token::EndOfCondition || *tok == Token::EOF {
                 break;
             }
-            statements.push(self.parse_statement()?);
+            if let Some(node) = self.parse_statement_or_recover() {
+                statements.push(node);
+            }
         }
         if let Some(Token::EndOfCondition) = self.current_token() {
             self.eat(Token::EndOfCondition)?; // 'end'i yok et
             Ok(statements)
         } else {
-            let line = self.current_line();
-            
-            return Err(ParseError::UnexpectedToken {
+            let span = self.current_span();
+            Err(ParseError::UnexpectedToken {
                 expected: Token::EndOfCondition,
                 found: self.current_token().cloned(),
-                line
+                span
             })
         }
     }
 
     /// Parses an 'if' statement and its block.
     fn parse_if(&mut self) -> Result<Stmt, ParseError> {
-        let current_line = self.current_line();
+        let current_span = self.current_span();
         self.eat(Token::If)?;
 
         // 1. Parse Condition (returns Expr, doesn't evaluate it)
@@ -324,7 +390,7 @@ impl Parser {
         let then_stmts = self.parse_block()?;
         let then_branch = StmtNode {
             stmt: Stmt::Block(then_stmts),
-            line: current_line,
+            span: current_span.clone(),
         };
 
         // 3. Parse the Optional "Else" block
@@ -333,7 +399,7 @@ impl Parser {
             let else_stmts = self.parse_block()?;
             Some(Box::new(StmtNode {
                 stmt: Stmt::Block(else_stmts),
-                line: current_line,
+                span: current_span.clone(),
             }))
         } else {
             None
@@ -348,7 +414,7 @@ impl Parser {
 
     /// Parses a 'while' loop.
     fn parse_while(&mut self) -> Result<Stmt, ParseError> {
-        let current_line = self.current_line();
+        let current_span = self.current_span();
         self.eat(Token::While)?;
 
         // 1. Parse Condition
@@ -356,53 +422,72 @@ impl Parser {
 
         // 2. Parse Body (Collects statements until 'end')
         let body_stmts = self.parse_block()?;
-        let body = StmtNode { stmt: Stmt::Block(body_stmts), line: current_line };
+        let body = StmtNode { stmt: Stmt::Block(body_stmts), span: current_span };
 
         
 
         Ok(Stmt::While {
             condition,
             body: Box::new(body),
+            label: None,
         })
     }
 
+    /// Parses `for int i = start range end[, step]`, `for i in range start, end[, step]`
+    /// and `for [key,] item in iterable`.
     fn parse_for(&mut self) -> Result<Stmt, ParseError> {
-        let current_line = self.current_line();
+        let current_span = self.current_span();
         // 1. Consume 'for'
         self.eat(Token::For)?;
-        
-        // 2. Expect 'int' (based on your old grammar rules)
-        if let Some(Token::TypeInt) = self.current_token() {
-            self.eat(Token::TypeInt)?; 
-        } else {
-            let line = self.current_line();
-            return Err(ParseError::UnexpectedToken { 
-                expected: Token::TypeInt, 
-                found: self.current_token().cloned(),
-                line
-            });
+
+        // 2. The counting form declares its variable as 'int'
+        let typed = self.check(Token::TypeInt);
+        if typed {
+            self.eat(Token::TypeInt)?;
         }
 
-        // 3. Get the loop variable name
-        let var_name = if let Some(Token::Identifier(name)) = self.current_token().cloned() {
-            self.pos += 1;
-            name
+        // 3. Get the loop variable name, and a second one for `key, item`
+        let mut var_name = self.parse_loop_variable()?;
+        let mut key_name = None;
+        if !typed && self.check(Token::Comma) {
+            self.eat(Token::Comma)?;
+            key_name = Some(var_name);
+            var_name = self.parse_loop_variable()?;
+        }
+
+        // 4. Parse the range (" = start range end" or " in range start, end") or the collection
+        let (start_value, end_value, step) = if typed {
+            self.eat(Token::Assign)?;
+            let start_value = self.parse_expr()?;
+            self.eat(Token::Range)?;
+            let end_value = self.parse_expr()?;
+            (start_value, end_value, self.parse_step()?)
         } else {
-            let line = self.current_line();
-            return Err(ParseError::UnexpectedToken { 
-                expected: Token::Identifier("variable".to_string()), 
-                found: self.current_token().cloned(),
-                line
-            });
+            self.eat_in()?;
+            if !self.check(Token::Range) {
+                let iterable = self.parse_expr()?;
+                let body_stmts = self.parse_block()?;
+                return Ok(Stmt::ForEach {
+                    key_name,
+                    var_name,
+                    iterable,
+                    body: Box::new(StmtNode { stmt: Stmt::Block(body_stmts), span: current_span }),
+                    slot: None,
+                    label: None,
+                });
+            }
+            // A range loop has a single variable
+            if key_name.is_some() {
+                let span = self.current_span();
+                return Err(ParseError::UnexpectedToken { expected: Token::Identifier("collection".to_string()), found: Some(Token::Range), span });
+            }
+            self.eat(Token::Range)?;
+            let start_value = self.parse_expr()?;
+            self.eat(Token::Comma)?;
+            let end_value = self.parse_expr()?;
+            (start_value, end_value, self.parse_step()?)
         };
 
-        // 4. Parse the range: " = start .. end"
-        self.eat(Token::Assign)?;
-        let start_value = self.parse_expr()?;
-
-        self.eat(Token::Range)?;
-        let end_value = self.parse_expr()?;
-
         // 5. Parse the body
         // This reuses the 'parse_block' helper we discussed earlier
         let body_stmts = self.parse_block()?;
@@ -412,10 +497,52 @@ impl Parser {
             var_name,
             start_value,
             end_value,
-            body: Box::new(StmtNode { stmt: Stmt::Block(body_stmts), line: current_line }),
+            step,
+            body: Box::new(StmtNode { stmt: Stmt::Block(body_stmts), span: current_span }),
+            slot: None,
+            label: None,
         })
 
     }
+
+    fn parse_loop_variable(&mut self) -> Result<String, ParseError> {
+        if let Some(Token::Identifier(name)) = self.current_token().cloned() {
+            self.pos += 1;
+            Ok(name)
+        } else {
+            let span = self.current_span();
+            Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("variable".to_string()),
+                found: self.current_token().cloned(),
+                span
+            })
+        }
+    }
+
+    /// `in` is only a keyword here, so it stays usable as a variable name.
+    fn eat_in(&mut self) -> Result<(), ParseError> {
+        match self.current_token() {
+            Some(Token::Identifier(name)) if name == "in" => {
+                self.pos += 1;
+                Ok(())
+            }
+            found => Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("`in`".to_string()),
+                found: found.cloned(),
+                span: self.current_span(),
+            }),
+        }
+    }
+
+    /// The optional `, step` after the end of a range.
+    fn parse_step(&mut self) -> Result<Option<Expr>, ParseError> {
+        if self.check(Token::Comma) {
+            self.eat(Token::Comma)?;
+            Ok(Some(self.parse_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
     
     fn parse_fn(&mut self) -> Result<Stmt, ParseError> {
         // 1. Consume 'fn' keyword
@@ -426,15 +553,46 @@ impl Parser {
             self.pos += 1;
             n
         } else {
-            let line = self.current_line();
+            let span = self.current_span();
             return Err(ParseError::UnexpectedToken {
                 expected: Token::Identifier("function name".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
 
-        // 3. Parse Parameters: (name: type, name: type)
+        // 3. Parse Parameters and the optional return type: (name: type) -> type
+        let FnSignature { params, return_type } = self.parse_signature()?;
+
+        // 4. Parse Body
+        // We reuse the parse_block() helper to recursively parse statements inside the function
+        let body = self.parse_block()?;
+
+        Ok(Stmt::Func {
+            name,
+            params,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses the parameter list and optional return type shared by named functions and lambdas.
+    fn parse_signature(&mut self) -> Result<FnSignature, ParseError> {
+        let params = self.parse_params()?;
+
+        // Optional return type: -> int
+        let return_type = if self.check(Token::Arrow) {
+            self.eat(Token::Arrow)?;
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok(FnSignature { params, return_type })
+    }
+
+    /// Parses `(name: type, name: type)`, the parameters of a function or the fields of an enum variant.
+    fn parse_params(&mut self) -> Result<Vec<(String, Type)>, ParseError> {
         self.eat(Token::LParen)?;
         let mut params = Vec::new();
 
@@ -446,11 +604,11 @@ impl Parser {
                     self.pos += 1;
                     n
                 } else {
-                    let line = self.current_line();
+                    let span = self.current_span();
                     return Err(ParseError::UnexpectedToken {
                         expected: Token::Identifier("param name".to_string()),
                         found: self.current_token().cloned(),
-                        line
+                        span
                     });
                 };
 
@@ -469,47 +627,36 @@ impl Parser {
             }
         }
         self.eat(Token::RParen)?;
-
-        // 4. Parse Body
-        // We reuse the parse_block() helper to recursively parse statements inside the function
-        let body = self.parse_block()?;
-
-        Ok(Stmt::Func {
-            name,
-            params,
-            body,
-        })
+        Ok(params)
     }
 
     fn parse_struct(&mut self) -> Result<Stmt, ParseError> {
-        let current_line = self.current_line();
-        
         self.eat(Token::Struct)?;
 
         let name = if let Some(Token::Identifier(n)) = self.current_token().cloned() {
             self.pos += 1;
             n
         } else {
-            let line = self.current_line();
+            let span = self.current_span();
             return Err(ParseError::UnexpectedToken {
                 expected: Token::Identifier("struct name".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
 
         let mut fields = Vec::new();
         while !self.check(Token::EndOfCondition) {
-            let field_line = self.current_line();
+            let field_span = self.current_span();
             let field_name = if let Some(Token::Identifier(n)) = self.current_token().cloned() {
                 self.pos += 1;
                 n
             } else {
-                let line = self.current_line();
+                let span = self.current_span();
                 return Err(ParseError::UnexpectedToken {
                     expected: Token::Identifier("struct name".to_string()),
                     found: self.current_token().cloned(),
-                    line
+                    span
                 });
             };
 
@@ -519,9 +666,10 @@ impl Parser {
                 stmt: Stmt::Let { 
                     name: field_name, 
                     data_type: field_type, 
-                    value: Expr::Literal(LiteralValue::Nil) 
+                    value: Expr::new(ExprKind::Literal(LiteralValue::Nil), field_span.clone()),
+                    slot: None,
                 },
-                line: field_line
+                span: field_span
             });
         }
 
@@ -529,6 +677,156 @@ impl Parser {
         Ok(Stmt::Struct { name, body: fields })
     }
 
+    /// Parses `enum State Idle Walking(speed: float) end`.
+    fn parse_enum(&mut self) -> Result<Stmt, ParseError> {
+        self.eat(Token::Enum)?;
+        let name = self.parse_identifier("enum name")?;
+
+        let mut variants = Vec::new();
+        while !self.check(Token::EndOfCondition) {
+            let variant = self.parse_identifier("variant name")?;
+            let fields = if self.check(Token::LParen) { self.parse_params()? } else { Vec::new() };
+            variants.push(Variant { name: variant, fields });
+        }
+
+        self.eat(Token::EndOfCondition)?;
+        Ok(Stmt::Enum { name, variants })
+    }
+
+    /// Parses `match value case pattern ... end`. Each case runs the statements up to
+    /// the next `case` or the closing `end`.
+    fn parse_match(&mut self) -> Result<Stmt, ParseError> {
+        self.eat(Token::Match)?;
+        let value = self.parse_expr()?;
+
+        let mut cases = Vec::new();
+        while self.check(Token::Case) {
+            let span = self.current_span();
+            self.eat(Token::Case)?;
+            let pattern = self.parse_pattern()?;
+            let mut body = Vec::new();
+            while let Some(token) = self.current_token() {
+                if matches!(token, Token::Case | Token::EndOfCondition | Token::EOF) {
+                    break;
+                }
+                if let Some(node) = self.parse_statement_or_recover() {
+                    body.push(node);
+                }
+            }
+            cases.push(MatchCase { pattern, body, span });
+        }
+
+        self.eat(Token::EndOfCondition)?;
+        Ok(Stmt::Match { value, cases, slot: None })
+    }
+
+    /// Parses `try ... catch err ... end`. The name after `catch` is optional and
+    /// must be on the same line, so the handler may start with any statement.
+    fn parse_try(&mut self) -> Result<Stmt, ParseError> {
+        self.eat(Token::Try)?;
+        let mut body = Vec::new();
+        while let Some(token) = self.current_token() {
+            if matches!(token, Token::Catch | Token::EndOfCondition | Token::EOF) {
+                break;
+            }
+            if let Some(node) = self.parse_statement_or_recover() {
+                body.push(node);
+            }
+        }
+        self.eat(Token::Catch)?;
+
+        let error_name = match self.current_token() {
+            Some(Token::Identifier(name)) if self.on_previous_line() => {
+                let name = name.clone();
+                self.pos += 1;
+                Some(name)
+            }
+            _ => None,
+        };
+
+        let handler = self.parse_block()?;
+        Ok(Stmt::Try { body, error_name, handler, slot: None })
+    }
+
+    /// Parses what follows `case`: `_`, a literal such as `-1` or `"idle"`, or a
+    /// variant such as `Idle` or `State.Walking(speed)`.
+    fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let span = self.current_span();
+        match self.current_token().cloned() {
+            Some(Token::Identifier(name)) if name == "_" => {
+                self.pos += 1;
+                Ok(Pattern::Wildcard)
+            }
+            Some(token @ (Token::Integer(_) | Token::Float(_) | Token::String(_) | Token::Boolean(_) | Token::Nil)) => {
+                self.pos += 1;
+                Ok(Pattern::Literal(self.token_to_literal(token)))
+            }
+            Some(Token::Minus) => {
+                self.pos += 1;
+                match self.current_token().cloned() {
+                    Some(Token::Integer(n)) => {
+                        self.pos += 1;
+                        Ok(Pattern::Literal(LiteralValue::Int(-n)))
+                    }
+                    Some(Token::Float(f)) => {
+                        self.pos += 1;
+                        Ok(Pattern::Literal(LiteralValue::Float(-f)))
+                    }
+                    found => Err(ParseError::UnexpectedToken {
+                        expected: Token::Identifier("a number".to_string()),
+                        found,
+                        span: self.current_span(),
+                    }),
+                }
+            }
+            Some(Token::Identifier(_)) => {
+                let mut type_name = None;
+                let mut variant = self.parse_identifier("variant name")?;
+                if self.check(Token::Dot) {
+                    self.eat(Token::Dot)?;
+                    type_name = Some(variant);
+                    variant = self.parse_identifier("variant name")?;
+                }
+
+                let mut bindings = Vec::new();
+                if self.check(Token::LParen) {
+                    self.eat(Token::LParen)?;
+                    if !self.check(Token::RParen) {
+                        loop {
+                            bindings.push((self.parse_identifier("binding name")?, None));
+                            if self.check(Token::Comma) {
+                                self.eat(Token::Comma)?;
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                    self.eat(Token::RParen)?;
+                }
+                Ok(Pattern::Variant { type_name, variant, bindings })
+            }
+            found => Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("pattern".to_string()),
+                found,
+                span,
+            }),
+        }
+    }
+
+    /// Consumes an identifier; `what` names it in the error otherwise.
+    fn parse_identifier(&mut self, what: &str) -> Result<String, ParseError> {
+        if let Some(Token::Identifier(name)) = self.current_token().cloned() {
+            self.pos += 1;
+            Ok(name)
+        } else {
+            Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier(what.to_string()),
+                found: self.current_token().cloned(),
+                span: self.current_span(),
+            })
+        }
+    }
+
     fn parse_impl(&mut self) -> Result<Stmt, ParseError> {
         self.eat(Token::Impl)?;
 
@@ -536,30 +834,30 @@ impl Parser {
             self.pos += 1;
             n
         } else {
-            let line = self.current_line();
+            let span = self.current_span();
             return Err(ParseError::UnexpectedToken {
                 expected: Token::Identifier("impl name".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
 
         let mut methods = Vec::new();
         while !self.check(Token::EndOfCondition) {
             if self.check(Token::Func) {
-                let func_line = self.current_line();
+                let func_span = self.current_span();
                 let func_stmt = self.parse_fn()?;
 
                 methods.push(StmtNode {
                     stmt: func_stmt,
-                    line: func_line
+                    span: func_span
                 });
             } else {
-                let line = self.current_line();
+                let span = self.current_span();
                 return Err(ParseError::UnexpectedToken {
                     expected: Token::Func,
                     found: self.current_token().cloned(),
-                    line
+                    span
                 });
             }
         }
@@ -586,6 +884,72 @@ impl Parser {
         })
     }
 
+    /// Parses `break` or `continue`, with an optional loop label on the same line.
+    fn parse_loop_jump(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.current_token().cloned();
+        let line = self.current_span().line;
+        self.pos += 1;
+
+        let label = match self.tokens.get(self.pos) {
+            Some((Token::Identifier(name), span)) if span.line == line => {
+                let name = name.clone();
+                self.pos += 1;
+                Some(name)
+            }
+            _ => None,
+        };
+
+        Ok(match keyword {
+            Some(Token::Break) => Stmt::Break { label },
+            _ => Stmt::Continue { label },
+        })
+    }
+
+    /// Parses `name: while ...` or `name: for ...`.
+    fn parse_labelled_loop(&mut self, name: String) -> Result<Stmt, ParseError> {
+        self.eat(Token::Colon)?;
+        let mut stmt = match self.current_token() {
+            Some(Token::While) => self.parse_while()?,
+            Some(Token::For) => self.parse_for()?,
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    expected: Token::While,
+                    found: self.current_token().cloned(),
+                    span: self.current_span(),
+                });
+            }
+        };
+        if let Stmt::While { label, .. } | Stmt::For { label, .. } | Stmt::ForEach { label, .. } = &mut stmt {
+            *label = Some(name);
+        }
+        Ok(stmt)
+    }
+
+    /// Parses the right side of `target op= value` and returns `target op value`.
+    /// Parses `target op= value`. A variable becomes `x = x op value`; an element
+    /// or field is read and stored in one step so its indices run only once.
+    fn parse_compound(&mut self, target: Expr, op: Token, span: Span) -> Result<Stmt, ParseError> {
+        let op_span = self.current_span();
+        self.pos += 1;
+        let value = Box::new(self.parse_expr()?);
+        let kind = match &target.kind {
+            ExprKind::Variable { name, .. } => ExprKind::Assign {
+                name: name.clone(),
+                value: Box::new(Expr::new(ExprKind::Binary { left: Box::new(target), op, right: value }, op_span)),
+                slot: None,
+            },
+            ExprKind::Index { .. } | ExprKind::FieldGet { .. } => {
+                return Ok(Stmt::Expression(Expr::new(ExprKind::CompoundAssign { target: Box::new(target), op, value }, op_span)));
+            }
+            _ => return Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("assignment target".to_string()),
+                found: Some(Token::Assign),
+                span: target.span,
+            }),
+        };
+        Ok(Stmt::Expression(Expr::new(kind, span)))
+    }
+
     fn check(&self, token: Token) -> bool {
         if let Some(t) = self.current_token() {
             *t == token
@@ -594,64 +958,134 @@ impl Parser {
         }
     }
 
-    /// Parses an expression (handles addition and subtraction).
-    /// This has lower precedence than `parse_term`.
+    /// Parses a full expression, from `or` down to literals.
     fn parse_expr(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_term()?;
+        self.parse_logic_or()
+    }
 
-        while let Some(tok) = self.current_token().cloned() {
-            match tok {
-                Token::Plus | Token::Minus => {
-                    self.pos += 1;
-                    let right = self.parse_term()?;
+    /// Parses a left-associative chain of the binary operators in `ops`, whose
+    /// operands are parsed by `operand`.
+    fn parse_binary(&mut self, ops: &[Token], operand: fn(&mut Self) -> Result<Expr, ParseError>) -> Result<Expr, ParseError> {
+        let mut left = operand(self)?;
 
-                    left = Expr::Binary {
-                        left: Box::new(left),
-                        op: tok,
-                        right: Box::new(right),
-                    }
-                }
-                _ => break,
+        while let Some(tok) = self.current_token().cloned() {
+            if !ops.contains(&tok) {
+                break;
             }
+            let span = self.current_span();
+            self.pos += 1;
+            let right = operand(self)?;
+            left = Expr::new(ExprKind::Binary {
+                left: Box::new(left),
+                op: tok,
+                right: Box::new(right),
+            }, span);
         }
-        
         Ok(left)
     }
 
-    /// Parses a term (handles multiplication and division).
-    /// This has higher precedence than `parse_expr`.
+    /// Bitwise operators bind tighter than comparisons: `a & 1 == 1` is `(a & 1) == 1`.
+    fn parse_bit_or(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(&[Token::BitOr], Self::parse_bit_xor)
+    }
+
+    fn parse_bit_xor(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(&[Token::BitXor], Self::parse_bit_and)
+    }
+
+    fn parse_bit_and(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(&[Token::BitAnd], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(&[Token::ShiftLeft, Token::ShiftRight], Self::parse_sum)
+    }
+
+    /// Parses addition and subtraction.
+    /// This has lower precedence than `parse_term`.
+    fn parse_sum(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(&[Token::Plus, Token::Minus], Self::parse_term)
+    }
+
+    /// Parses a term (handles multiplication, division and modulo).
+    /// This has higher precedence than `parse_sum`.
     fn parse_term(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_factor()?;
+        self.parse_binary(&[Token::Multiply, Token::Divide, Token::FloorDivide, Token::Modulo], Self::parse_cast)
+    }
 
-        while let Some(tok) = self.current_token().cloned() {
-            match tok {
-                Token::Multiply | Token::Divide => {
-                    self.pos += 1;
-                    let right = self.parse_factor()?;
-                    left = Expr::Binary {
-                        left: Box::new(left),
-                        op: tok,
-                        right: Box::new(right),
-                    };
-                }
-                _ => break,
+    /// Parses `value as type`, which binds tighter than `*` and looser than a
+    /// prefix operator: `-x as float * 2.0` is `((-x) as float) * 2.0`.
+    fn parse_cast(&mut self) -> Result<Expr, ParseError> {
+        let mut value = self.parse_unary()?;
+        while self.check(Token::As) {
+            let span = self.current_span();
+            self.eat(Token::As)?;
+            let target = self.parse_type()?;
+            value = Expr::new(ExprKind::Cast { value: Box::new(value), target }, span);
+        }
+        Ok(value)
+    }
+
+    /// Parses the prefix operators `-`, `!` and `~`.
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span();
+        match self.current_token() {
+            Some(operator @ (Token::Minus | Token::Bang | Token::BitNot)) => {
+                let operator = operator.clone();
+                self.pos += 1;
+                let right = self.parse_unary()?;
+                Ok(Expr::new(ExprKind::Unary { operator, right: Box::new(right) }, span))
             }
+            _ => self.parse_power(),
         }
-        Ok(left)
     }
 
-    /// Parses a factor (the highest precedence: literals, variables, unary ops, parentheses).
+    /// `**` is right-associative and binds tighter than a unary minus on its
+    /// left, so `-2 ** 2` is `-(2 ** 2)`.
+    fn parse_power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.parse_factor()?;
+        if !self.check(Token::Power) {
+            return Ok(base);
+        }
+        let span = self.current_span();
+        self.eat(Token::Power)?;
+        let exponent = self.parse_unary()?;
+        Ok(Expr::new(ExprKind::Binary {
+            left: Box::new(base),
+            op: Token::Power,
+            right: Box::new(exponent),
+        }, span))
+    }
+
+    /// Parses a factor (the highest precedence): a primary followed by any number
+    /// of `[index]`, `[start:end]`, `.field` and `.method(args)`.
     fn parse_factor(&mut self) -> Result<Expr, ParseError> {
-        let line = self.current_line();
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.check(Token::LBracket) {
+                expr = self.parse_index(expr)?;
+            } else if self.check(Token::Dot) || self.check(Token::QuestionDot) {
+                expr = self.parse_member(expr)?;
+            } else if self.check(Token::LParen) && self.on_previous_line() {
+                expr = self.parse_call_args(expr)?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses a primary expression: a literal, a variable, a call or a bracketed expression.
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span();
         let tok = self.current_token().cloned().ok_or(ParseError::UnexpectedToken {
-            expected: Token::Integer(0), found: None, line
+            expected: Token::Identifier("an expression".to_string()), found: None, span: span.clone()
         })?;
 
         match tok {
             // --- 1. Literals ---
-            Token::Integer(_) | Token::Float(_) | Token::String(_) | Token::Boolean(_) => {
+            Token::Integer(_) | Token::Float(_) | Token::String(_) | Token::Boolean(_) | Token::Nil => {
                 self.pos += 1;
-                Ok(Expr::Literal(self.token_to_literal(tok)))
+                Ok(Expr::new(ExprKind::Literal(self.token_to_literal(tok)), span))
             }
             
             // --- 2. Identifiers (Variables, Calls, Lists) ---
@@ -676,38 +1110,54 @@ impl Parser {
                         }
                         self.eat(Token::RParen)?;
                         // Pass as a regular Call with "namespace::name" as callee
-                        return Ok(Expr::Call {
-                            callee: Box::new(Expr::Variable(format!("{}::{}", name, fn_name))),
+                        let callee = ExprKind::Variable { name: format!("{}::{}", name, fn_name), slot: None };
+                        return Ok(Expr::new(ExprKind::Call {
+                            callee: Box::new(Expr::new(callee, span.clone())),
                             paren: Token::LParen,
                             arguments: args,
-                        });
+                        }, span));
                     }
                 }
                 
                 if self.check(Token::LParen) {
                     // It is a Function Call: run()
-                    self.parse_call_expr(name)
-                } 
-                else if self.check(Token::LBracket) {
-                    // It is a List Access: arr[0]
-                    self.parse_list_index(name)
-                }
-                else if self.check(Token::Dot) {
-                    // It is a Method Call: list.push()
-                    self.parse_method_call(name)
+                    self.parse_call_expr(name, span)
                 }
                 else {
                     // It is just a Variable: x
-                    Ok(Expr::Variable(name))
+                    Ok(Expr::new(ExprKind::Variable { name, slot: None }, span))
                 }
             },
 
+            // Conversion builtins share their names with types: int("42"), string(3.5), xs.map(string)
+            Token::TypeInt | Token::TypeFloat | Token::TypeString => {
+                self.pos += 1;
+                let name = match tok {
+                    Token::TypeInt => "int",
+                    Token::TypeFloat => "float",
+                    _ => "string",
+                };
+                if self.check(Token::LParen) {
+                    self.parse_call_expr(name.to_string(), span)
+                } else {
+                    Ok(Expr::new(ExprKind::Variable { name: name.to_string(), slot: None }, span))
+                }
+            }
+
+            // A lambda: fn(x: int) -> int return x * 2 end
+            Token::Func => {
+                self.pos += 1;
+                let FnSignature { params, return_type } = self.parse_signature()?;
+                let body = self.parse_block()?;
+                Ok(Expr::new(ExprKind::Lambda { params, return_type: return_type.map(Box::new), body, captures: Vec::new() }, span))
+            }
+
             // --- 3. Parentheses (Grouping) ---
             Token::LParen => {
                 self.eat(Token::LParen)?;
                 let expr = self.parse_expr()?;
                 self.eat(Token::RParen)?;
-                Ok(Expr::Grouping(Box::new(expr)))
+                Ok(Expr::new(ExprKind::Grouping(Box::new(expr)), span))
             },
 
             Token::LBracket => {
@@ -734,38 +1184,39 @@ impl Parser {
                 self.eat(Token::RBracket)?; // Consume ']'
                 
                 // Return the AST node for a List
-                Ok(Expr::List(elements))
+                Ok(Expr::new(ExprKind::List(elements), span))
             },
 
-            // --- 4. Unary Minus (-5) ---
-            Token::Minus => {
-                self.eat(Token::Minus)?;
-                let right = self.parse_factor()?;
-                Ok(Expr::Unary {
-                    operator: Token::Minus,
-                    right: Box::new(right),
-                })
-            },
+            Token::LBrace => {
+                self.eat(Token::LBrace)?;
 
-            Token::Bang => {
-                
-                self.eat(Token::Bang)?;
-                                
-                let right_expr = self.parse_factor()?;
+                let mut entries = Vec::new();
+                if !self.check(Token::RBrace) {
+                    loop {
+                        let key = self.parse_expr()?;
+                        self.eat(Token::Colon)?;
+                        let value = self.parse_expr()?;
+                        entries.push((key, value));
 
-                Ok(Expr::Unary { 
-                    operator: Token::Bang, 
-                    right: Box::new(right_expr) 
-                })
+                        if self.check(Token::Comma) {
+                            self.eat(Token::Comma)?;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+
+                self.eat(Token::RBrace)?;
+                Ok(Expr::new(ExprKind::Map(entries), span))
             },
-            
+
             _ => {
-                let line = self.current_line();
-                Err(ParseError::UnexpectedToken { expected: Token::Unknown, found: Some(tok), line })}
+                let span = self.current_span();
+                Err(ParseError::UnexpectedToken { expected: Token::Identifier("an expression".to_string()), found: Some(tok), span })}
         }
     }
 
-    fn parse_call_expr(&mut self, name: String) -> Result<Expr, ParseError> {
+    fn parse_call_expr(&mut self, name: String, span: Span) -> Result<Expr, ParseError> {
         // We already consumed the identifier in parse_factor, now consume '('
         self.eat(Token::LParen)?;
         
@@ -784,24 +1235,53 @@ impl Parser {
         self.eat(Token::RParen)?;
 
         // Return the Call variant from your ast::Expr
-        Ok(Expr::Call {
-            callee: Box::new(Expr::Variable(name)),
+        Ok(Expr::new(ExprKind::Call {
+            callee: Box::new(Expr::new(ExprKind::Variable { name, slot: None }, span.clone())),
             paren: Token::RParen, // Stored for error reporting later
             arguments,
-        })
+        }, span))
+    }
+
+    /// Parses the arguments of a call on a function value, as in `handlers[0](5)` or `make_adder(1)(2)`.
+    fn parse_call_args(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let span = self.current_span();
+        self.eat(Token::LParen)?;
+        let mut arguments = Vec::new();
+        if !self.check(Token::RParen) {
+            loop {
+                arguments.push(self.parse_expr()?);
+                if self.check(Token::Comma) {
+                    self.eat(Token::Comma)?;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.eat(Token::RParen)?;
+        Ok(Expr::new(ExprKind::Call { callee: Box::new(callee), paren: Token::RParen, arguments }, span))
+    }
+
+    /// Whether the current token is on the same line as the one before it. A `(` that
+    /// starts a new line begins a new statement rather than calling the value before it.
+    fn on_previous_line(&self) -> bool {
+        match (self.pos.checked_sub(1).and_then(|at| self.tokens.get(at)), self.tokens.get(self.pos)) {
+            (Some((_, previous)), Some((_, current))) => previous.line == current.line,
+            _ => false,
+        }
     }
 
     fn parse_logic_or(&mut self) -> Result<Expr, ParseError> {
         let mut left = self.parse_logic_and()?;
 
         while let Some(Token::Or) = self.current_token().cloned() {
+            let span = self.current_span();
             self.eat(Token::Or)?;
             let right = self.parse_logic_and()?;
-            left = Expr::Logical {
+            left = Expr::new(ExprKind::Logical {
                 left: Box::new(left),
                 operator: Token::Or,
                 right: Box::new(right),
-            };
+            }, span);
         }
         Ok(left)
     }
@@ -810,13 +1290,14 @@ impl Parser {
         let mut left = self.parse_equality()?;
 
         while let Some(Token::And) = self.current_token().cloned() {
+            let span = self.current_span();
             self.eat(Token::And)?;
             let right = self.parse_equality()?;
-            left = Expr::Logical {
+            left = Expr::new(ExprKind::Logical {
                 left: Box::new(left),
                 operator: Token::And,
                 right: Box::new(right),
-            };
+            }, span);
         }
         
         Ok(left)
@@ -827,13 +1308,14 @@ impl Parser {
 
         while let Some(tok) = self.current_token().cloned() {
             if tok == Token::Equals || tok == Token::NotEquals {
+                let span = self.current_span();
                 self.eat(tok.clone())?;
                 let right = self.parse_comparison()?;
-                left = Expr::Binary {
+                left = Expr::new(ExprKind::Binary {
                     left: Box::new(left),
                     op: tok,
                     right: Box::new(right),
-                };
+                }, span);
             } else {
                 break;
             }
@@ -842,65 +1324,67 @@ impl Parser {
     }
 
     fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_term()?; 
-
-        if let Some(tok) = self.current_token().cloned() {
-            if matches!(tok, Token::Greater | Token::Lesser | Token::GreaterEquals | Token::LesserEquals) {
-                self.eat(tok.clone())?;
-                let right = self.parse_expr()?;
-                left = Expr::Binary {
-                    left: Box::new(left),
-                    op: tok,
-                    right: Box::new(right),
-                };
-            }
+        let mut left = self.parse_coalesce()?;
+
+        if let Some(tok) = self.current_token().cloned()
+            && matches!(tok, Token::Greater | Token::Lesser | Token::GreaterEquals | Token::LesserEquals) {
+            let span = self.current_span();
+            self.eat(tok.clone())?;
+            let right = self.parse_coalesce()?;
+            left = Expr::new(ExprKind::Binary {
+                left: Box::new(left),
+                op: tok,
+                right: Box::new(right),
+            }, span);
         }
         Ok(left)
     }
 
-    fn parse_method_call(&mut self, var_name: String) -> Result<Expr, ParseError> {
-        // 1. Consume the dot '.'
-        self.eat(Token::Dot)?;
+    /// `a ?? b` binds looser than arithmetic and tighter than comparisons, so
+    /// `hp ?? 0 + 1 > 3` is `(hp ?? (0 + 1)) > 3`. It groups to the right.
+    fn parse_coalesce(&mut self) -> Result<Expr, ParseError> {
+        let left = self.parse_bit_or()?;
+        if !self.check(Token::NilCoalesce) {
+            return Ok(left);
+        }
+        let span = self.current_span();
+        self.eat(Token::NilCoalesce)?;
+        let right = self.parse_coalesce()?;
+        Ok(Expr::new(ExprKind::Logical {
+            left: Box::new(left),
+            operator: Token::NilCoalesce,
+            right: Box::new(right),
+        }, span))
+    }
+
+    /// Parses `.field` or `.method(args)` after `object`, or their `?.` forms.
+    fn parse_member(&mut self, object: Expr) -> Result<Expr, ParseError> {
+        let safe = self.check(Token::QuestionDot);
+        self.pos += 1;
+        let span = self.current_span();
 
-        // 2. Get the method name (e.g., "push", "len")
-        let method_name = if let Some(Token::Identifier(name)) = self.current_token().cloned() {
+        let name = if let Some(Token::Identifier(name)) = self.current_token().cloned() {
             self.pos += 1;
             name
         } else {
-            let line = self.current_line();
-            return Err(ParseError::UnexpectedToken { 
-                expected: Token::Identifier("method name".to_string()), 
+            return Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("method name".to_string()),
                 found: self.current_token().cloned(),
-                line
+                span
             });
         };
 
         if !self.check(Token::LParen) {
-            if self.check(Token::Assign) {
-                self.eat(Token::Assign)?;
-                let value = self.parse_expr()?;
-                return Ok(Expr::FieldSet {
-                    object: Box::new(Expr::Variable(var_name)),
-                    field: method_name,
-                    value: Box::new(value),
-                });
+            if safe {
+                return Ok(Expr::new(ExprKind::SafeMember { object: Box::new(object), name, args: None }, span));
             }
-            return Ok(Expr::FieldGet {
-                object: Box::new(Expr::Variable(var_name)),
-                field: method_name,
-            });
+            return Ok(Expr::new(ExprKind::FieldGet { object: Box::new(object), field: name }, span));
         }
 
-        // 3. Consume '('
         self.eat(Token::LParen)?;
-
-        // 4. Parse arguments: (arg1, arg2)
         let mut args: Vec<Expr> = Vec::new();
-
-        // Check if the next token is NOT ')', meaning we have params
         if !self.check(Token::RParen) {
             loop {
-                // Recursively parse the argument as an expression
                 args.push(self.parse_expr()?);
 
                 if let Some(Token::Comma) = self.current_token() {
@@ -910,28 +1394,52 @@ impl Parser {
                 }
             }
         }
-
-        // 5. Consume ')'
         self.eat(Token::RParen)?;
 
-        // 6. Return the AST Node
-        // We wrap the object name (var_name) in an Expr::Variable so the Interpreter
-        // knows to look it up later.
-        Ok(Expr::MethodCall {
-            object: Box::new(Expr::Variable(var_name)),
-            method: method_name,
-            args,
-        })
+        if safe {
+            return Ok(Expr::new(ExprKind::SafeMember { object: Box::new(object), name, args: Some(args) }, span));
+        }
+        Ok(Expr::new(ExprKind::MethodCall { object: Box::new(object), method: name, args }, span))
+    }
+
+    /// Turns `target = value` into the statement that stores into `target`, which
+    /// may be any place: `x`, `grid[y][x]`, `players[0].hp`, `self.pos.x`.
+    fn parse_assignment(&mut self, target: Expr, span: Span) -> Result<Stmt, ParseError> {
+        if let Some(op) = self.current_token().and_then(compound_operator) {
+            return self.parse_compound(target, op, span);
+        }
+        self.eat(Token::Assign)?;
+        let value = Box::new(self.parse_expr()?);
+        let stmt = match target.kind {
+            ExprKind::Variable { name, .. } => {
+                Stmt::Expression(Expr::new(ExprKind::Assign { name, value, slot: None }, span))
+            }
+            ExprKind::Index { .. } if let Some((list_name, indices)) = index_chain(&target) => {
+                Stmt::ListAssign { list_name, indices, value: *value, slot: None }
+            }
+            ExprKind::Index { list, index } => {
+                Stmt::Expression(Expr::new(ExprKind::IndexSet { list, index, value }, target.span))
+            }
+            ExprKind::FieldGet { object, field } => {
+                Stmt::Expression(Expr::new(ExprKind::FieldSet { object, field, value }, target.span))
+            }
+            _ => return Err(ParseError::UnexpectedToken {
+                expected: Token::Identifier("assignment target".to_string()),
+                found: Some(Token::Assign),
+                span: target.span,
+            }),
+        };
+        Ok(stmt)
     }
 
     /// The main dispatch function. It "senses" what the current token is
     /// and dispatches to the correct parsing function for that statement.
     pub fn parse_statement(&mut self) -> Result<StmtNode, ParseError> {
-        let current_line = self.current_line();
+        let current_span = self.current_span();
         if self.current_token().is_none() {
             
             return Err(ParseError::UnexpectedToken { 
-                expected: Token::Unknown, found: None, line: current_line
+                expected: Token::Identifier("a statement".to_string()), found: None, span: current_span
             });
         }
 
@@ -947,82 +1455,177 @@ impl Parser {
             Token::Func => self.parse_fn(),
             Token::Struct => self.parse_struct(),
             Token::Impl => self.parse_impl(),
+            Token::Enum => self.parse_enum(),
+            Token::Match => self.parse_match(),
+            Token::Try => self.parse_try(),
             Token::Return => self.parse_return(),
+            Token::Break | Token::Continue => self.parse_loop_jump(),
             
             // --- The Tricky Part: Identifiers ---
             Token::Identifier(name) => {
                 // We consume the identifier here because we need to see what comes AFTER it.
+                let start = self.pos;
                 self.pos += 1; 
 
-                // Case A: Variable Assignment (x = 10)
-                if self.check(Token::Assign) {
-                    self.eat(Token::Assign)?;
-                    let value = self.parse_expr()?;
-                    Ok(Stmt::Expression(Expr::Assign {
-                        name,
-                        value: Box::new(value),
-                    }))
+                // A labelled loop (outer: while ...)
+                if self.check(Token::Colon) {
+                    self.parse_labelled_loop(name)
                 }
-                // Case B: List Assignment (arr[0] = 10) OR List Access (arr[0])
-                else if self.check(Token::LBracket) {
-                    // We parse the indices: [0][1]
-                    let mut indices = Vec::new();
-                    while self.check(Token::LBracket) {
-                        self.eat(Token::LBracket)?;
-                        indices.push(self.parse_expr()?);
-                        self.eat(Token::RBracket)?;
-                    }
-
-                    // Check if an '=' follows the brackets
-                    if self.check(Token::Assign) {
-                        // It is a List Assignment: arr[0] = 5
-                        self.eat(Token::Assign)?;
-                        let value = self.parse_expr()?;
-                        Ok(Stmt::ListAssign { list_name: name, indices, value })
+                // Anything else starting with a name is an expression, or the target of an
+                // assignment: x = 10, arr[0] += 5, players[0].hp = 3, list.push(1), run()
+                else {
+                    self.pos = start;
+                    let target = self.parse_logic_or()?;
+                    if self.check(Token::Assign) || self.current_token().and_then(compound_operator).is_some() {
+                        self.parse_assignment(target, current_span.clone())
                     } else {
-                        // It was just a List Access expression: arr[0]
-                        // We reconstruct the expression tree
-                        let mut expr = Expr::Variable(name);
-                        for idx in indices {
-                            expr = Expr::Index { 
-                                list: Box::new(expr), 
-                                index: Box::new(idx) 
-                            };
-                        }
-                        Ok(Stmt::Expression(expr))
+                        Ok(Stmt::Expression(target))
                     }
                 }
-                // Case C: Method Call (list.push())
-                else if self.check(Token::Dot) {
-                    // We call the helper we wrote earlier
-                    // Note: we pass 'name' because we already consumed the identifier
-                    let expr = self.parse_method_call(name)?;
-                    Ok(Stmt::Expression(expr))
-                }
-                // Case D: Function Call (run())
-                else if self.check(Token::LParen) {
-                    let expr = self.parse_call_expr(name)?;
-                    Ok(Stmt::Expression(expr))
-                }
-                // Case E: Just a standalone variable (x)
-                else {
-                    Ok(Stmt::Expression(Expr::Variable(name)))
-                }
             },
+
+            // Expressions starting with a literal, a bracket or a prefix operator
+            Token::Integer(_) | Token::Float(_) | Token::String(_) | Token::Boolean(_) | Token::Nil
+            | Token::TypeInt | Token::TypeFloat | Token::TypeString
+            | Token::LParen | Token::LBracket | Token::LBrace | Token::Minus | Token::Bang | Token::BitNot => {
+                Ok(Stmt::Expression(self.parse_logic_or()?))
+            }
             
             _ => {
-                let line = self.current_line();
+                let span = self.current_span();
                 Err(ParseError::UnexpectedToken {
-                expected: Token::Unknown,
+                expected: Token::Identifier("a statement".to_string()),
                 found: Some(token),
-                line
+                span
             })},
         }?;
 
         Ok(StmtNode {
             stmt,
-            line: current_line,
+            span: current_span,
         })
     }
         
-}
\ No newline at end of file
+}
+
+/// The variable and indices of `name[i][j]...`, which is stored by `Stmt::ListAssign`.
+fn index_chain(expr: &Expr) -> Option<(String, Vec<Expr>)> {
+    match &expr.kind {
+        ExprKind::Variable { name, .. } => Some((name.clone(), Vec::new())),
+        ExprKind::Index { list, index } => {
+            let (name, mut indices) = index_chain(list)?;
+            indices.push((**index).clone());
+            Some((name, indices))
+        }
+        _ => None,
+    }
+}
+
+/// The operator applied by a compound assignment such as `+=`.
+fn compound_operator(token: &Token) -> Option<Token> {
+    match token {
+        Token::PlusAssign => Some(Token::Plus),
+        Token::MinusAssign => Some(Token::Minus),
+        Token::MultiplyAssign => Some(Token::Multiply),
+        Token::DivideAssign => Some(Token::Divide),
+        Token::ModuloAssign => Some(Token::Modulo),
+        _ => None,
+    }
+}
+
+/// Tokens that start a block closed by `end`.
+fn opens_block(token: &Token) -> bool {
+    matches!(token, Token::If | Token::Else | Token::While | Token::For | Token::Func | Token::Struct | Token::Impl | Token::Enum | Token::Match | Token::Try)
+}
+
+/// Whether the token at `at` starts a block closed by `end`. A `fn` does, unless it
+/// begins a function type such as `fn(int) -> int`. Lambda parameters are always
+/// written `name: type`, so only `fn()` is told apart by what comes before it.
+pub fn opens_block_at(tokens: &[(Token, Span)], at: usize) -> bool {
+    let token = |offset: usize| at.checked_add(offset).and_then(|i| tokens.get(i)).map(|(token, _)| token);
+    let previous = |back: usize| at.checked_sub(back).and_then(|i| tokens.get(i)).map(|(token, _)| token);
+    match (token(0), token(1), token(2), token(3)) {
+        (Some(Token::Func), Some(Token::LParen), Some(Token::RParen), _) => !matches!(
+            (previous(2), previous(1)),
+            (_, Some(Token::Arrow | Token::Lesser)) | (Some(Token::Identifier(_)), Some(Token::Colon))
+        ),
+        (Some(Token::Func), Some(Token::LParen), Some(Token::Identifier(_)), Some(Token::Colon)) => true,
+        (Some(Token::Func), Some(Token::LParen), _, _) => false,
+        (Some(token), _, _, _) => opens_block(token),
+        _ => false,
+    }
+}
+
+/// Keywords a statement can begin with; parsing resumes at one of these after an error.
+fn starts_statement(token: &Token) -> bool {
+    opens_block(token) || matches!(
+        token,
+        Token::EndOfCondition | Token::Case | Token::Catch | Token::Let | Token::Print | Token::Return | Token::Break | Token::Continue | Token::Import
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{WolfEngine, error_handler::Severity};
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let engine = WolfEngine::new();
+        let diagnostics = engine.check("level.wolf", r#"
+let hp: int = 10
+let speed float = 1.5
+fn heal(amount)
+    hp = hp + amount
+end
+while hp <
+    print hp
+end
+let name: string = "wolf"
+print name +
+let ok: bool = true
+"#);
+
+        let found: Vec<(usize, usize)> = diagnostics.iter().map(|d| (d.span.line, d.span.column)).collect();
+        assert_eq!(found, [(3, 11), (4, 15), (8, 5), (12, 1)]);
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(diagnostics[0].message, "expected `:`, found `float`");
+    }
+
+    #[test]
+    fn names_tokens_readably() {
+        let engine = WolfEngine::new();
+        let messages: Vec<String> = engine.check("<input>", "let x: = 1\nlet y: int = )\nfn (a: int)\nend\n")
+            .into_iter().map(|d| d.message).collect();
+        assert_eq!(messages, [
+            "expected type name, found `=`",
+            "expected an expression, found `)`",
+            "expected function name, found `(`",
+        ]);
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let engine = WolfEngine::new();
+        let diagnostics = engine.check("<input>", r#"
+fn first()
+    let a int = 1
+    print a
+end
+fn second()
+    print 1 +
+end
+print missing
+"#);
+
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, [3, 8]);
+    }
+
+    #[test]
+    fn run_reports_all_syntax_errors() {
+        let mut engine = WolfEngine::new();
+        let error = engine.run("let a int = 1\nlet b: int = \nprint a\n").unwrap_err().to_string();
+        assert!(error.contains("<input>:1:7"));
+        assert!(error.contains("<input>:3:1"));
+    }
+}
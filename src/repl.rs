@@ -0,0 +1,265 @@
+use std::{fs, io::{self, BufRead, Write}};
+
+use wolflang::{WolfEngine, lexer::lexer, parser::opens_block_at, tokens::Token};
+
+const HELP: &str = "\
+:vars          list global variables
+:funcs         list script functions
+:load <file>   run a .wolf file in this session
+:reset         forget everything defined so far and any unfinished input
+:help          show this message
+:quit          leave the REPL";
+
+/// Whether the text typed so far can be run.
+#[derive(Debug, PartialEq)]
+enum Status {
+    Complete,
+    /// A block opened by `if`, `fn`, `while`, ... has no matching `end` yet,
+    /// or a map literal is still open.
+    NeedsMore,
+    /// Complete, but ends with an `if` that the next line may continue with `else`.
+    MaybeElse,
+}
+
+fn status(source: &str) -> Status {
+    let tokens = match lexer(source) {
+        Ok(tokens) => tokens,
+        // Strings may span several lines
+        Err(error) if error.message() == "Unterminated string literal" => return Status::NeedsMore,
+        // Other lexer errors are reported when the input runs.
+        Err(_) => return Status::Complete,
+    };
+
+    let mut open = Vec::new();
+    let mut braces = 0i32;
+    let mut ends_with_if = false;
+    for (at, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::LBrace => braces += 1,
+            Token::RBrace => braces -= 1,
+            _ => {}
+        }
+        match token {
+            _ if opens_block_at(&tokens, at) => open.push(token.clone()),
+            Token::EndOfCondition => {
+                let closed = open.pop();
+                ends_with_if = open.is_empty() && closed == Some(Token::If);
+            }
+            Token::EOF => {}
+            _ if open.is_empty() => ends_with_if = false,
+            _ => {}
+        }
+    }
+
+    if !open.is_empty() || braces > 0 {
+        Status::NeedsMore
+    } else if ends_with_if {
+        Status::MaybeElse
+    } else {
+        Status::Complete
+    }
+}
+
+pub struct Repl {
+    engine: WolfEngine,
+    buffer: String,
+    awaiting_else: bool,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl { engine: WolfEngine::new(), buffer: String::new(), awaiting_else: false }
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() { "wolf> " } else { "...   " }
+    }
+
+    /// Handles one line of input. Returns false once the session should end.
+    pub fn feed(&mut self, line: &str) -> bool {
+        let trimmed = line.trim();
+
+        // Anything but `else`, an empty line included, runs the finished `if`
+        if self.awaiting_else {
+            self.awaiting_else = false;
+            if !trimmed.starts_with("else") {
+                self.flush();
+                return self.feed(line);
+            }
+        }
+
+        // Commands work in the middle of unfinished input too, so `:reset` gets
+        // out of a string or block that was never closed.
+        if trimmed.starts_with(':') {
+            return self.command(trimmed);
+        }
+        if self.buffer.is_empty() && trimmed.is_empty() {
+            return true;
+        }
+
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        match status(&self.buffer) {
+            Status::Complete => self.flush(),
+            Status::NeedsMore => {}
+            Status::MaybeElse => self.awaiting_else = true,
+        }
+        true
+    }
+
+    /// Runs whatever is buffered, echoing the value of a trailing expression.
+    pub fn flush(&mut self) {
+        self.awaiting_else = false;
+        let source = std::mem::take(&mut self.buffer);
+        if source.trim().is_empty() {
+            return;
+        }
+        match self.engine.eval(&source) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    fn command(&mut self, input: &str) -> bool {
+        let (command, argument) = match input.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (input, ""),
+        };
+
+        match command {
+            ":vars" => {
+                for (name, value) in self.engine.globals() {
+                    println!("{}: {} = {}", name, wolflang::types::Type::of(value), value);
+                }
+            }
+            ":funcs" => {
+                for (name, signature) in self.engine.functions() {
+                    let params: Vec<String> = signature.params.iter()
+                        .map(|(name, ty)| format!("{}: {}", name, ty))
+                        .collect();
+                    match signature.return_type {
+                        Some(return_type) => println!("{}({}) -> {}", name, params.join(", "), return_type),
+                        None => println!("{}({})", name, params.join(", ")),
+                    }
+                }
+            }
+            ":load" if !argument.is_empty() => match fs::read_to_string(argument) {
+                Ok(content) => {
+                    if let Err(e) = self.engine.run_source(argument, &content) {
+                        eprintln!("{}", e);
+                    }
+                }
+                Err(e) => eprintln!("Could not read '{}': {}", argument, e),
+            },
+            ":reset" => {
+                self.engine = WolfEngine::new();
+                self.buffer.clear();
+                println!("Session reset.");
+            }
+            ":help" => println!("{}", HELP),
+            ":quit" | ":q" => return false,
+            _ => eprintln!("Unknown command '{}'. Type :help for the list of commands.", input),
+        }
+        true
+    }
+
+    #[cfg(test)]
+    fn engine(&self) -> &WolfEngine {
+        &self.engine
+    }
+}
+
+pub fn run() {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("{}", repl.prompt());
+        let _ = io::stdout().flush();
+
+        match lines.next() {
+            Some(Ok(line)) => {
+                if !repl.feed(&line) {
+                    break;
+                }
+            }
+            Some(Err(e)) => {
+                eprintln!("Read error: {}", e);
+                break;
+            }
+            None => {
+                println!();
+                repl.flush();
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repl, Status, status};
+
+    #[test]
+    fn waits_for_unfinished_blocks() {
+        assert_eq!(status("let x: int = 1\n"), Status::Complete);
+        assert_eq!(status("fn add(a: int, b: int)\n"), Status::NeedsMore);
+        assert_eq!(status("let m: map<string, int> = {\n\"a\": 1,\n"), Status::NeedsMore);
+        assert_eq!(status("let m: map<string, int> = {\n\"a\": 1\n}\n"), Status::Complete);
+        assert_eq!(status("while x < 3\nif x == 1\nend\n"), Status::NeedsMore);
+        assert_eq!(status("if x == 1\nprint x\nend\n"), Status::MaybeElse);
+        assert_eq!(status("if x == 1\nprint x\nend else\nprint 0\nend\n"), Status::Complete);
+        assert_eq!(status("print \"\"\"\nHello\n"), Status::NeedsMore);
+        assert_eq!(status("print \"\"\"\nHello\n\"\"\"\n"), Status::Complete);
+        assert_eq!(status("let f: fn(int) -> fn() -> int = make\n"), Status::Complete);
+        assert_eq!(status("let f: fn(int) -> int = fn(x: int) -> int\n"), Status::NeedsMore);
+        assert_eq!(status("schedule(fn()\nprint 1\nend)\n"), Status::Complete);
+    }
+
+    #[test]
+    fn keeps_globals_between_inputs() {
+        let mut repl = Repl::new();
+        repl.feed("let x: int = 1");
+        repl.feed("fn bump(by: int)");
+        repl.feed("x = x + by");
+        repl.feed("end");
+        repl.feed("if x == 1");
+        repl.feed("bump(10)");
+        repl.feed("end");
+        repl.feed("else");
+        repl.feed("bump(100)");
+        repl.feed("end");
+        assert_eq!(repl.engine().get_int("x"), Some(11));
+
+        assert!(repl.feed(":reset"));
+        assert_eq!(repl.engine().get_int("x"), None);
+        assert!(!repl.feed(":quit"));
+    }
+
+    #[test]
+    fn reset_leaves_unfinished_input() {
+        let mut repl = Repl::new();
+        repl.feed("print \"\"\"");
+        repl.feed("still inside the string");
+        assert_eq!(repl.prompt(), "...   ");
+        assert!(repl.feed(":reset"));
+        assert_eq!(repl.prompt(), "wolf> ");
+        repl.feed("let x: int = 1");
+        assert_eq!(repl.engine().get_int("x"), Some(1));
+    }
+
+    #[test]
+    fn empty_line_runs_a_finished_if() {
+        let mut repl = Repl::new();
+        repl.feed("let x: int = 0");
+        repl.feed("if x == 0");
+        repl.feed("x = 5");
+        repl.feed("end");
+        assert_eq!(repl.engine().get_int("x"), Some(0));
+        repl.feed("");
+        assert_eq!(repl.engine().get_int("x"), Some(5));
+        assert_eq!(repl.prompt(), "wolf> ");
+    }
+}
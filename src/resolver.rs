@@ -0,0 +1,559 @@
+use std::{collections::HashSet, mem};
+
+use crate::{
+    ast::{Expr, ExprKind, Pattern, Slot, Stmt, StmtNode},
+    error_handler::ParseError,
+    native_functions,
+    span::Span,
+    types::Type,
+    value::Value,
+    vm::Vm,
+};
+
+/// Binds every variable in the program to a frame slot or a global slot before
+/// it is compiled, and reports names that are used without being declared.
+pub struct Resolver<'a> {
+    vm: &'a mut Vm,
+    /// Alias of the module being resolved; its globals are prefixed with `alias::`.
+    module: Option<String>,
+    /// Local scopes of the function being resolved. Empty at the top level of a
+    /// script, where `let` defines globals.
+    scopes: Vec<Vec<(String, u32)>>,
+    next_slot: u32,
+    in_function: bool,
+    /// Globals that top-level code can use at the current point of the program.
+    defined: HashSet<String>,
+    /// Every global the program defines. Function bodies may use any of them,
+    /// because they only run once the top-level code has been reached.
+    program_globals: HashSet<String>,
+    modules: HashSet<String>,
+    /// Functions and structs the program declares, which a call may name instead
+    /// of a variable. Functions can also be used as values.
+    functions: HashSet<String>,
+    structs: HashSet<String>,
+    /// Enums the program declares, whose variants are built with `State.Idle`.
+    enums: HashSet<String>,
+    /// Local scopes of the functions enclosing the lambda being resolved, innermost last.
+    enclosing: Vec<Vec<Vec<(String, u32)>>>,
+    /// Variables of enclosing functions used by the lambda being resolved.
+    captures: Vec<String>,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(vm: &'a mut Vm) -> Self {
+        Resolver {
+            // A global whose declaration never ran holds nil and has no type
+            defined: vm.global_names.iter()
+                .filter(|(name, slot)| vm.globals[**slot as usize] != Value::Nil || vm.global_types.contains_key(*name))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            modules: vm.loaded_modules.values().cloned().collect(),
+            functions: vm.function_names.keys().cloned().collect(),
+            structs: vm.struct_defs.keys().cloned().collect(),
+            enums: vm.enum_defs.keys().cloned().collect(),
+            vm,
+            module: None,
+            scopes: Vec::new(),
+            next_slot: 0,
+            in_function: false,
+            program_globals: HashSet::new(),
+            enclosing: Vec::new(),
+            captures: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn for_module(vm: &'a mut Vm, alias: &str) -> Self {
+        Resolver { module: Some(alias.to_string()), ..Resolver::new(vm) }
+    }
+
+    pub fn resolve(mut self, program: &mut [StmtNode]) -> Result<(), Vec<ParseError>> {
+        for node in program.iter() {
+            match &node.stmt {
+                Stmt::Let { name, .. } => {
+                    let global = self.qualified(name);
+                    self.program_globals.insert(global);
+                }
+                Stmt::Import { identifier, .. } => {
+                    self.modules.insert(identifier.clone());
+                }
+                Stmt::Func { name, .. } => {
+                    let function = self.qualified(name);
+                    self.functions.insert(function);
+                }
+                Stmt::Struct { name, .. } => {
+                    let type_name = self.qualified(name);
+                    self.structs.insert(type_name);
+                }
+                Stmt::Enum { name, .. } => {
+                    let type_name = self.qualified(name);
+                    self.enums.insert(type_name);
+                }
+                _ => {}
+            }
+        }
+
+        for node in program.iter_mut() {
+            self.statement(node);
+        }
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        match &self.module {
+            Some(alias) => format!("{}::{}", alias, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<Slot> {
+        let local = self.scopes.iter().rev()
+            .find_map(|scope| scope.iter().rev().find(|(local, _)| local == name));
+        if let Some((_, slot)) = local {
+            return Some(Slot::Local(*slot));
+        }
+
+        // A variable of an enclosing function is captured by the lambda; its slot
+        // is only known once every capture was found, see `lambda`.
+        let captured = self.enclosing.iter().rev()
+            .any(|scopes| scopes.iter().any(|scope| scope.iter().any(|(local, _)| local == name)));
+        if captured {
+            if !self.captures.iter().any(|capture| capture == name) {
+                self.captures.push(name.to_string());
+            }
+            return Some(Slot::Local(0));
+        }
+
+        let mut candidates = vec![name.to_string()];
+        if self.module.is_some() {
+            candidates.insert(0, self.qualified(name));
+        }
+        candidates.into_iter()
+            .find(|global| self.defined.contains(global) || (self.in_function && self.program_globals.contains(global)))
+            .map(|global| Slot::Global(self.vm.global_slot(&global)))
+    }
+
+    /// Whether `name` is a script, built-in or host function rather than a variable.
+    fn is_function(&self, name: &str) -> bool {
+        self.functions.contains(&self.qualified(name)) || self.functions.contains(name)
+            || native_functions::is_builtin(name) || self.vm.native_fns.borrow().contains_key(name)
+    }
+
+    /// Whether a call to `name` goes to a function or builds a struct.
+    fn is_callable(&self, name: &str) -> bool {
+        self.is_function(name) || self.structs.contains(&self.qualified(name)) || self.structs.contains(name)
+    }
+
+    /// Whether `name` is an enum, so that `name.Variant` builds a value of it.
+    fn is_enum(&self, name: &str) -> bool {
+        self.enums.contains(&self.qualified(name)) || self.enums.contains(name)
+    }
+
+    fn resolve_name(&mut self, name: &str, span: &Span) -> Option<Slot> {
+        let slot = self.lookup(name);
+        // A function used as a value keeps no slot
+        if slot.is_none() && !self.is_function(name) {
+            self.errors.push(ParseError::UndeclaredVariable { name: name.to_string(), span: span.clone() });
+        }
+        slot
+    }
+
+    fn declare_local(&mut self, name: &str) -> u32 {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.scopes.last_mut().expect("local declared outside of a scope").push((name.to_string(), slot));
+        slot
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            self.next_slot -= scope.len() as u32;
+        }
+    }
+
+    fn statement(&mut self, node: &mut StmtNode) {
+        let span = &node.span;
+        match &mut node.stmt {
+            Stmt::Expression(expr) => self.expression(expr),
+
+            Stmt::Print(exprs) => {
+                for expr in exprs {
+                    self.expression(expr);
+                }
+            }
+
+            Stmt::Let { name, value, slot, .. } => {
+                self.expression(value);
+                if self.scopes.is_empty() {
+                    let global = self.qualified(name);
+                    *slot = Some(Slot::Global(self.vm.global_slot(&global)));
+                    self.defined.insert(global);
+                } else {
+                    *slot = Some(Slot::Local(self.declare_local(name)));
+                }
+            }
+
+            Stmt::ListAssign { list_name, indices, value, slot } => {
+                *slot = self.resolve_name(list_name, span);
+                for index in indices {
+                    self.expression(index);
+                }
+                self.expression(value);
+            }
+
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                for node in statements {
+                    self.statement(node);
+                }
+                self.end_scope();
+            }
+
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+
+            Stmt::While { condition, body, .. } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+
+            Stmt::For { var_name, start_value, end_value, step, body, slot, .. } => {
+                self.expression(start_value);
+                self.expression(end_value);
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+                self.begin_scope();
+                let var = self.declare_local(var_name);
+                self.declare_local(" counter");
+                self.declare_local(" limit");
+                self.declare_local(" step");
+                *slot = Some(Slot::Local(var));
+                self.statement(body);
+                self.end_scope();
+            }
+
+            Stmt::ForEach { key_name, var_name, iterable, body, slot, .. } => {
+                self.expression(iterable);
+                self.begin_scope();
+                let first = match key_name {
+                    Some(key_name) => {
+                        let key = self.declare_local(key_name);
+                        self.declare_local(var_name);
+                        key
+                    }
+                    None => self.declare_local(var_name),
+                };
+                self.declare_local(" items");
+                self.declare_local(" position");
+                *slot = Some(Slot::Local(first));
+                self.statement(body);
+                self.end_scope();
+            }
+
+            Stmt::Func { name, params, body, .. } => {
+                // Nested functions become usable once they are reached.
+                let function = self.qualified(name);
+                self.functions.insert(function);
+                self.function(params.iter().map(|(name, _)| name), body, false);
+            }
+
+            Stmt::Impl { body, .. } => {
+                for method in body {
+                    if let Stmt::Func { params, body, .. } = &mut method.stmt {
+                        self.function(params.iter().map(|(name, _)| name), body, true);
+                    }
+                }
+            }
+
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+
+            // The value is kept in a hidden local while the cases test it, and the
+            // bindings of each case are locals of that case.
+            Stmt::Match { value, cases, slot } => {
+                self.expression(value);
+                self.begin_scope();
+                *slot = Some(Slot::Local(self.declare_local(" match")));
+                for case in cases {
+                    self.begin_scope();
+                    if let Pattern::Variant { bindings, .. } = &mut case.pattern {
+                        for (name, slot) in bindings {
+                            if name != "_" {
+                                *slot = Some(Slot::Local(self.declare_local(name)));
+                            }
+                        }
+                    }
+                    for node in &mut case.body {
+                        self.statement(node);
+                    }
+                    self.end_scope();
+                }
+                self.end_scope();
+            }
+
+            // The body and the handler are separate scopes; the caught message is a
+            // local of the handler.
+            Stmt::Try { body, error_name, handler, slot } => {
+                self.begin_scope();
+                for node in body {
+                    self.statement(node);
+                }
+                self.end_scope();
+
+                self.begin_scope();
+                *slot = error_name.as_ref().map(|name| Slot::Local(self.declare_local(name)));
+                for node in handler {
+                    self.statement(node);
+                }
+                self.end_scope();
+            }
+
+            Stmt::Import { identifier, .. } => {
+                self.modules.insert(identifier.clone());
+            }
+
+            Stmt::Enum { name, .. } => {
+                let type_name = self.qualified(name);
+                self.enums.insert(type_name);
+            }
+
+            Stmt::Struct { .. } | Stmt::Break { .. } | Stmt::Continue { .. } => {}
+        }
+    }
+
+    /// Functions get a fresh frame: parameters (after `self` for methods) take the
+    /// first slots and only globals are visible from the outside.
+    fn function<'p>(&mut self, params: impl Iterator<Item = &'p String>, body: &mut [StmtNode], is_method: bool) {
+        let mut scope = Vec::new();
+        if is_method {
+            scope.push(("self".to_string(), 0));
+        }
+        for name in params {
+            scope.push((name.clone(), scope.len() as u32));
+        }
+
+        let saved_slot = mem::replace(&mut self.next_slot, scope.len() as u32);
+        let saved_scopes = mem::replace(&mut self.scopes, vec![scope]);
+        let saved_in_function = mem::replace(&mut self.in_function, true);
+        let saved_enclosing = mem::take(&mut self.enclosing);
+
+        for node in body {
+            self.statement(node);
+        }
+
+        self.next_slot = saved_slot;
+        self.scopes = saved_scopes;
+        self.in_function = saved_in_function;
+        self.enclosing = saved_enclosing;
+    }
+
+    /// Lambdas get a fresh frame like functions, but may also use the variables of
+    /// the functions around them. Those are captured by value: they are copied into
+    /// the slots after the parameters when the lambda is created.
+    ///
+    /// The body is resolved twice: once to find what it captures, and again with
+    /// the captures declared, so that every use gets its final slot.
+    fn lambda(&mut self, params: &[(String, Type)], body: &mut [StmtNode], captures: &mut Vec<(String, Option<Slot>)>) {
+        let errors = self.errors.len();
+        let found = self.lambda_body(params, body, &[]);
+        self.errors.truncate(errors);
+        self.lambda_body(params, body, &found);
+
+        *captures = found.into_iter().map(|name| {
+            let slot = self.lookup(&name);
+            (name, slot)
+        }).collect();
+    }
+
+    /// Resolves a lambda body with `captured` declared after the parameters and
+    /// returns the variables of enclosing functions that it uses.
+    fn lambda_body(&mut self, params: &[(String, Type)], body: &mut [StmtNode], captured: &[String]) -> Vec<String> {
+        let mut scope = Vec::new();
+        for name in params.iter().map(|(name, _)| name).chain(captured) {
+            scope.push((name.clone(), scope.len() as u32));
+        }
+
+        let saved_slot = mem::replace(&mut self.next_slot, scope.len() as u32);
+        let saved_scopes = mem::replace(&mut self.scopes, vec![scope]);
+        let saved_in_function = mem::replace(&mut self.in_function, true);
+        let saved_captures = mem::take(&mut self.captures);
+        self.enclosing.push(saved_scopes);
+
+        for node in body {
+            self.statement(node);
+        }
+
+        self.scopes = self.enclosing.pop().expect("lambda scopes were pushed");
+        self.next_slot = saved_slot;
+        self.in_function = saved_in_function;
+        mem::replace(&mut self.captures, saved_captures)
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        let span = &expr.span;
+        match &mut expr.kind {
+            ExprKind::Literal(_) => {}
+
+            ExprKind::Variable { name, slot } => *slot = self.resolve_name(name, span),
+
+            ExprKind::Assign { name, value, slot } => {
+                self.expression(value);
+                *slot = self.resolve_name(name, span);
+            }
+
+            ExprKind::Grouping(inner) => self.expression(inner),
+
+            ExprKind::Unary { right, .. } => self.expression(right),
+
+            ExprKind::Cast { value, .. } => self.expression(value),
+
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+
+            ExprKind::Index { list, index } => {
+                self.expression(list);
+                self.expression(index);
+            }
+
+            ExprKind::Slice { list, start, end } => {
+                self.expression(list);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expression(bound);
+                }
+            }
+
+            // A callee that names a function or struct keeps no slot. Local variables
+            // shadow those names; globals do not, so existing calls keep working.
+            ExprKind::Call { callee, arguments, .. } => {
+                match &mut callee.kind {
+                    ExprKind::Variable { name, slot } => {
+                        *slot = match self.lookup(name) {
+                            Some(Slot::Global(_)) if self.is_callable(name) => None,
+                            found => found,
+                        };
+                    }
+                    _ => self.expression(callee),
+                }
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+
+            ExprKind::MethodCall { object, args, .. } => {
+                match &mut object.kind {
+                    ExprKind::Variable { name, slot } => {
+                        *slot = self.lookup(name);
+                        if slot.is_none() && !self.modules.contains(name.as_str()) && !self.is_enum(name) {
+                            self.errors.push(ParseError::UndeclaredVariable { name: name.clone(), span: object.span.clone() });
+                        }
+                    }
+                    _ => self.expression(object),
+                }
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+
+            // `State.Idle` names a variant rather than reading a field
+            ExprKind::FieldGet { object, .. } => match &mut object.kind {
+                ExprKind::Variable { name, slot } if self.is_enum(name) => *slot = self.lookup(name),
+                _ => self.expression(object),
+            },
+
+            ExprKind::SafeMember { object, args, .. } => {
+                self.expression(object);
+                for arg in args.iter_mut().flatten() {
+                    self.expression(arg);
+                }
+            }
+
+            ExprKind::Lambda { params, body, captures, .. } => self.lambda(params, body, captures),
+
+            ExprKind::FieldSet { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+
+            ExprKind::IndexSet { list, index, value } => {
+                self.expression(list);
+                self.expression(index);
+                self.expression(value);
+            }
+
+            ExprKind::CompoundAssign { target, value, .. } => {
+                self.expression(target);
+                self.expression(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WolfEngine;
+
+    #[test]
+    fn reports_undeclared_variables_before_running() {
+        let mut engine = WolfEngine::new();
+        let error = engine.run(r#"
+            print "never printed"
+            print missing
+            let early: int = later
+            let later: int = 1
+        "#).unwrap_err().to_string();
+        assert!(error.contains("undeclared variable 'missing'\n --> <input>:3:19"));
+        assert!(error.contains("undeclared variable 'later'\n --> <input>:4:30"));
+
+        let error = engine.run("try\n    let inside: int = 1\ncatch\n    print inside\nend").unwrap_err().to_string();
+        assert!(error.contains("undeclared variable 'inside'\n --> <input>:4:11"));
+    }
+
+    #[test]
+    fn functions_see_globals_declared_after_them() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn bump()
+                score = score + 1
+            end
+
+            let score: int = 0
+            bump()
+            bump()
+        "#).unwrap();
+        assert_eq!(engine.get_int("score"), Some(2));
+    }
+}
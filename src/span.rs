@@ -0,0 +1,109 @@
+use std::{fmt, rc::Rc};
+
+/// A script or module as it was read, kept so that errors can quote it.
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Rc<Self> {
+        Rc::new(SourceFile { name: name.to_string(), text: text.to_string() })
+    }
+
+    /// The text of a 1-based line, without its line break.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text.lines().nth(line.checked_sub(1)?)
+    }
+}
+
+/// Where a token or syntax node starts. Lines and columns count from 1.
+#[derive(Clone)]
+pub struct Span {
+    pub file: Rc<SourceFile>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file: &Rc<SourceFile>, line: usize, column: usize) -> Self {
+        Span { file: Rc::clone(file), line, column }
+    }
+
+    /// Renders an error the way rustc does: `header`, the location, the source
+    /// line and a caret under the column.
+    pub fn render(&self, header: &str) -> String {
+        let mut out = format!("{}\n", header);
+        let Some(source) = self.file.line(self.line) else {
+            out.push_str(&format!(" --> {}", self));
+            return out;
+        };
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        // Tabs are kept so that the caret lines up with the source above it.
+        let padding: String = source.chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out.push_str(&format!("{}--> {}\n", gutter, self));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&format!("{} | {}\n", self.line, source));
+        out.push_str(&format!("{} | {}^", gutter, padding));
+        out
+    }
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span { file: SourceFile::new("<unknown>", ""), line: 0, column: 0 }
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, other: &Self) -> bool {
+        self.file.name == other.file.name && self.line == other.line && self.column == other.column
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.name, self.line, self.column)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WolfEngine;
+
+    #[test]
+    fn renders_a_caret_under_the_error() {
+        let mut engine = WolfEngine::new();
+        let error = engine.run_source("game.wolf", "let hp: int = 10\nlet name: string = hp\n").unwrap_err().to_string();
+        assert_eq!(error, "\
+Type Error: Cannot assign int to 'name' of type string
+ --> game.wolf:2:20
+  |
+2 | let name: string = hp
+  |                    ^");
+    }
+
+    #[test]
+    fn module_errors_point_into_the_module() {
+        let path = std::env::temp_dir().join("wolf_span_module.wolf");
+        std::fs::write(&path, "fn area(w: int) -> int\n    return w * wdth\nend\n").unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut engine = WolfEngine::new();
+        let error = engine.run_source("main.wolf", &format!("import \"{}\" as shapes\nprint shapes.area(2)\n", path)).unwrap_err().to_string();
+        assert!(error.starts_with("Compile Error: undeclared variable 'wdth'"));
+        assert!(error.contains(&format!("--> {}:2:16", path)));
+        assert!(error.ends_with("2 |     return w * wdth\n  |                ^"));
+    }
+}
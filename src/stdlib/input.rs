@@ -1,6 +1,6 @@
-use std::io::{self, Read};
+use std::io;
 
-fn input() -> io::Result<String> {
+pub fn input() -> io::Result<String> {
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
     Ok(input.trim().to_string())
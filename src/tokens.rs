@@ -5,7 +5,7 @@ pub enum Token {
     TypeInt,
     TypeFloat,
     TypeBool,
-    TypeList(Box<Token>),
+    TypeList,
 
     // Keywords
     Let,
@@ -17,7 +17,7 @@ pub enum Token {
     Float(f64),
     String(String),
     Boolean(bool),
-    List(Vec<Token>),
+    Nil,
 
     // Operators
     Assign,    // =
@@ -25,7 +25,23 @@ pub enum Token {
     Minus,
     Multiply,
     Divide,
+    FloorDivide, // //
+    Modulo,      // %
+    Power,       // **
+    BitAnd,      // &
+    BitOr,       // |
+    BitXor,      // ^
+    BitNot,      // ~
+    ShiftLeft,   // <<
+    ShiftRight,  // >>
+    PlusAssign,     // +=
+    MinusAssign,    // -=
+    MultiplyAssign, // *=
+    DivideAssign,   // /=
+    ModuloAssign,   // %=
     Dot,
+    QuestionDot,    // ?.
+    NilCoalesce,    // ??
 
     // Parantez / blok
     LParen,
@@ -61,20 +77,107 @@ pub enum Token {
     //struct-impl
     Struct,
     Impl,
+    Enum,
+    Match,
+    Case,
+    Try,
+    Catch,
 
-    StructInstance {
-        type_name: String,
-        fields: Vec<(String, Token)>,
-    },
-    
     // other
     EndOfCondition,
     Range,
     Comma,
     Colon,
     Return,
+    Break,
+    Continue,
+    Arrow,
     Bang,
     As,
     Unknown,
     EOF,
 }
+
+impl Token {
+    /// How the token reads in a diagnostic, e.g. "`=`", "`end`" or "identifier `hp`".
+    pub fn describe(&self) -> String {
+        let symbol = match self {
+            Token::Identifier(name) => return format!("identifier `{}`", name),
+            Token::Integer(n) => return format!("`{}`", n),
+            Token::Float(f) => return format!("`{}`", f),
+            Token::String(s) => return format!("string {:?}", s),
+            Token::Boolean(b) => return format!("`{}`", b),
+            Token::Unknown => return "unknown token".to_string(),
+            Token::EOF => return "end of input".to_string(),
+            Token::TypeString => "string",
+            Token::TypeInt => "int",
+            Token::TypeFloat => "float",
+            Token::TypeBool => "bool",
+            Token::TypeList => "list",
+            Token::Let => "let",
+            Token::Print => "print",
+            Token::Nil => "nil",
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::FloorDivide => "//",
+            Token::Modulo => "%",
+            Token::Power => "**",
+            Token::BitAnd => "&",
+            Token::BitOr => "|",
+            Token::BitXor => "^",
+            Token::BitNot => "~",
+            Token::ShiftLeft => "<<",
+            Token::ShiftRight => ">>",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::MultiplyAssign => "*=",
+            Token::DivideAssign => "/=",
+            Token::ModuloAssign => "%=",
+            Token::Dot => ".",
+            Token::QuestionDot => "?.",
+            Token::NilCoalesce => "??",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::For => "for",
+            Token::While => "while",
+            Token::Equals => "==",
+            Token::Greater => ">",
+            Token::Lesser => "<",
+            Token::GreaterEquals => ">=",
+            Token::LesserEquals => "<=",
+            Token::NotEquals => "!=",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::DoubleColon => "::",
+            Token::Func => "fn",
+            Token::Import => "import",
+            Token::Struct => "struct",
+            Token::Impl => "impl",
+            Token::Enum => "enum",
+            Token::Match => "match",
+            Token::Case => "case",
+            Token::Try => "try",
+            Token::Catch => "catch",
+            Token::EndOfCondition => "end",
+            Token::Range => "range",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::Return => "return",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Arrow => "->",
+            Token::Bang => "!",
+            Token::As => "as",
+        };
+        format!("`{}`", symbol)
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+use crate::value::Value;
+
+/// A type annotation as written in source (`int`, `list<int>`, `Point`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    String,
+    Bool,
+    List(Box<Type>),
+    /// `map<K, V>`; keys are `int`, `string` or `bool`.
+    Map(Box<Type>, Box<Type>),
+    /// A user-defined type such as a struct, possibly namespaced (`geo::Point`).
+    Named(String),
+    /// `fn(int, int) -> int`; functions without a declared return type return anything.
+    Function(Vec<Type>, Option<Box<Type>>),
+    /// `option<T>`: a `T` or `nil`.
+    Option(Box<Type>),
+    /// The type of the `nil` literal, which only fits an `option<T>`.
+    Nil,
+    /// Used by the type checker when a type cannot be known statically,
+    /// e.g. values returned by host functions.
+    Any,
+}
+
+impl Type {
+    /// The type of an already evaluated value.
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Integer(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::String(_) => Type::String,
+            Value::Boolean(_) => Type::Bool,
+            Value::List(elements) => {
+                Type::List(Box::new(elements.first().map(Type::of).unwrap_or(Type::Any)))
+            }
+            Value::Map(entries) => match entries.first() {
+                Some((key, value)) => Type::Map(Box::new(Type::of(key)), Box::new(Type::of(value))),
+                None => Type::Map(Box::new(Type::Any), Box::new(Type::Any)),
+            },
+            Value::StructInstance { type_name, .. } => Type::Named(type_name.clone()),
+            Value::Enum(value) => Type::Named(value.type_name.clone()),
+            Value::Function(closure) => {
+                let function = &closure.function;
+                Type::Function(
+                    function.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                    function.return_type.clone().map(Box::new),
+                )
+            }
+            // Host functions declare no types
+            Value::NativeFunction(_) => Type::Any,
+            Value::Nil => Type::Nil,
+        }
+    }
+
+    /// Returns true if a value of type `actual` can be stored where `self` is expected.
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Option(_), Type::Nil) => true,
+            (Type::Option(expected), Type::Option(found)) => expected.accepts(found),
+            (Type::Option(expected), found) => expected.accepts(found),
+            (Type::List(expected), Type::List(found)) => expected.accepts(found),
+            (Type::Map(expected_key, expected_value), Type::Map(found_key, found_value)) => {
+                expected_key.accepts(found_key) && expected_value.accepts(found_value)
+            }
+            (Type::Function(expected_params, expected_return), Type::Function(found_params, found_return)) => {
+                expected_params.len() == found_params.len()
+                    && expected_params.iter().zip(found_params).all(|(expected, found)| expected.accepts(found))
+                    && match (expected_return, found_return) {
+                        (Some(expected), Some(found)) => expected.accepts(found),
+                        _ => true,
+                    }
+            }
+            _ => self == actual,
+        }
+    }
+
+    /// Runtime check used when a value is bound to an annotated name.
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Any, _) => true,
+            (Type::Int, Value::Integer(_)) => true,
+            (Type::Float, Value::Float(_)) => true,
+            (Type::String, Value::String(_)) => true,
+            (Type::Bool, Value::Boolean(_)) => true,
+            (Type::List(_), Value::List(_)) => true,
+            (Type::Map(_, _), Value::Map(_)) => true,
+            (Type::Named(type_name), Value::StructInstance { type_name: instance_type, .. }) => {
+                type_name == instance_type
+            }
+            (Type::Named(type_name), Value::Enum(value)) => *type_name == value.type_name,
+            (Type::Function(_, _), Value::Function(_)) => self.accepts(&Type::of(value)),
+            (Type::Function(_, _), Value::NativeFunction(_)) => true,
+            (Type::Option(inner), value) => *value == Value::Nil || inner.matches(value),
+            (Type::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+
+    /// Types that can be used as map keys.
+    pub fn is_key(&self) -> bool {
+        matches!(self, Type::Int | Type::String | Type::Bool | Type::Any)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float | Type::Any)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::List(inner) => write!(f, "list<{}>", inner),
+            Type::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Function(params, return_type) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ")")?;
+                match return_type {
+                    Some(return_type) => write!(f, " -> {}", return_type),
+                    None => Ok(()),
+                }
+            }
+            Type::Option(inner) => write!(f, "option<{}>", inner),
+            Type::Nil => write!(f, "nil"),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fmt, rc::Rc};
+
+use crate::bytecode::Function;
+
+/// A runtime value produced by evaluating WolfLang code.
+/// Unlike `Token`, which only describes source text, this is what lives in
+/// scopes, gets passed to native functions and crosses the embedding API.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    List(Vec<Value>),
+    /// Entries in insertion order. Keys are ints, strings or bools and never repeat.
+    Map(Vec<(Value, Value)>),
+
+    StructInstance {
+        type_name: String,
+        fields: Vec<(String, Value)>,
+    },
+    /// A variant of an enum with its payload, such as `State.Walking(1.5)`.
+    Enum(Rc<EnumValue>),
+    /// A named function or a lambda used as a value.
+    Function(Rc<Closure>),
+    /// A built-in or host function used as a value, called by name.
+    NativeFunction(String),
+
+    Nil,
+}
+
+/// A function together with the values a lambda captured when it was created.
+/// Captures are passed after the arguments, in the local slots following the parameters.
+#[derive(Clone)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub captures: Vec<Value>,
+}
+
+/// The value of an enum variant. Its payload cannot be changed, so copies share it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    pub type_name: String,
+    pub variant: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({})", self.function.name)
+    }
+}
+
+impl Value {
+    /// Whether the value can be used as a map key.
+    pub fn is_key(&self) -> bool {
+        matches!(self, Value::Integer(_) | Value::String(_) | Value::Boolean(_))
+    }
+}
+
+/// Maps are equal when they hold the same entries, whatever order they were inserted in.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len() && a.iter().all(|entry| b.contains(entry))
+            }
+            (
+                Value::StructInstance { type_name: a_type, fields: a_fields },
+                Value::StructInstance { type_name: b_type, fields: b_fields },
+            ) => a_type == b_type && a_fields == b_fields,
+            (Value::Enum(a), Value::Enum(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => {
+                Rc::ptr_eq(&a.function, &b.function) && a.captures == b.captures
+            }
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::List(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::StructInstance { type_name, fields } => {
+                write!(f, "{} {{ ", type_name)?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Enum(value) => {
+                write!(f, "{}.{}", value.type_name, value.variant)?;
+                if !value.fields.is_empty() {
+                    write!(f, "(")?;
+                    for (i, (_, value)) in value.fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Function(closure) => match closure.function.name.as_str() {
+                // Lambdas are named `<lambda>`
+                name if name.starts_with('<') => write!(f, "{}", name),
+                name => write!(f, "<fn {}>", name),
+            },
+            Value::NativeFunction(name) => write!(f, "<native {}>", name),
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+}
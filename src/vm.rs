@@ -0,0 +1,2417 @@
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc};
+
+use crate::{
+    NativeFn,
+    ast::{Slot, Variant},
+    bytecode::{Access, Function, Op},
+    error_handler::{ParseError, TraceFrame, WolfError},
+    native_functions,
+    span::Span,
+    types::Type,
+    value::{Closure, EnumValue, Value},
+};
+
+/// Deepest call nesting allowed before a script is stopped with an error.
+const MAX_FRAMES: usize = 4096;
+
+/// Deepest nesting of callbacks run from methods such as `map`, each of which
+/// runs the called function on the native stack.
+const MAX_CALLBACK_DEPTH: usize = 32;
+
+/// Longest string, in bytes, that `repeat` may build.
+const MAX_REPEAT_LEN: usize = 1 << 28;
+
+/// What integer arithmetic does when a result does not fit in an `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    /// Stop the script with a runtime error.
+    #[default]
+    Error,
+    /// Wrap around in two's complement, as release builds of Rust do.
+    Wrap,
+    /// Clamp to `i64::MIN` or `i64::MAX`.
+    Saturate,
+}
+
+impl Overflow {
+    /// The result of an integer operation whose checked form gave `checked`.
+    fn resolve(self, checked: Option<i64>, wrapped: impl FnOnce() -> i64, saturated: impl FnOnce() -> i64, symbol: &str) -> Result<Value, String> {
+        match (checked, self) {
+            (Some(n), _) => Ok(Value::Integer(n)),
+            (None, Overflow::Wrap) => Ok(Value::Integer(wrapped())),
+            (None, Overflow::Saturate) => Ok(Value::Integer(saturated())),
+            (None, Overflow::Error) => Err(format!("Integer overflow in '{}'", symbol)),
+        }
+    }
+}
+
+/// Where a place expression starts: an absolute stack index or a global slot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Root {
+    Stack(usize),
+    Global(usize),
+}
+
+/// A receiver that a method was called on, so changes to `self` can be copied back.
+#[derive(Debug, Clone, PartialEq)]
+struct Place {
+    root: Root,
+    path: Vec<Access>,
+    keys: Vec<Value>,
+}
+
+#[derive(Debug, Clone)]
+struct CallFrame {
+    function: Rc<Function>,
+    ip: usize,
+    /// Stack index of the first local slot.
+    base: usize,
+    write_back: Option<Place>,
+}
+
+/// A `try` block being run: an error unwinds the frames and the stack to where they
+/// were when it was entered and jumps to its `catch`.
+#[derive(Debug, Clone)]
+struct Handler {
+    frames: usize,
+    stack: usize,
+    target: u32,
+}
+
+/// Stack machine that runs the bytecode produced by `Compiler`.
+#[derive(Clone, Default)]
+pub struct Vm {
+    pub globals: Vec<Value>,
+    pub global_names: HashMap<String, u32>,
+    /// Declared type of each global, kept so later runs check against it.
+    pub global_types: HashMap<String, Type>,
+    pub functions: Vec<Rc<Function>>,
+    pub function_names: HashMap<String, u32>,
+    pub native_fns: Rc<RefCell<HashMap<String, NativeFn>>>,
+    pub struct_defs: HashMap<String, Vec<(String, Type)>>,
+    pub enum_defs: HashMap<String, Vec<Variant>>,
+    pub methods: HashMap<String, HashMap<String, u32>>,
+    /// Imported file paths and the alias each was imported as.
+    pub loaded_modules: HashMap<String, String>,
+    pub overflow: Overflow,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    /// Open `try` blocks, innermost last.
+    handlers: Vec<Handler>,
+    /// Callbacks currently running inside `call_value`.
+    callback_depth: usize,
+}
+
+impl std::fmt::Debug for Vm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vm")
+            .field("globals", &self.global_names)
+            .field("functions", &self.function_names)
+            .field("native_fns", &"<native functions>")
+            .finish()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Slot of a global, created holding `Nil` the first time the name is seen.
+    pub fn global_slot(&mut self, name: &str) -> u32 {
+        if let Some(slot) = self.global_names.get(name) {
+            return *slot;
+        }
+        self.globals.push(Value::Nil);
+        let slot = (self.globals.len() - 1) as u32;
+        self.global_names.insert(name.to_string(), slot);
+        slot
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.global_names.get(name).map(|slot| &self.globals[*slot as usize])
+    }
+
+    pub fn add_function(&mut self, function: Function) -> u32 {
+        self.functions.push(Rc::new(function));
+        (self.functions.len() - 1) as u32
+    }
+
+    pub fn set_function(&mut self, index: u32, function: Function) {
+        self.functions[index as usize] = Rc::new(function);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Rc<Function>> {
+        self.function_names.get(name).map(|index| &self.functions[*index as usize])
+    }
+
+    /// Runs the top-level code of a compiled program and returns what it returned.
+    pub fn interpret(&mut self, script: Function) -> Result<Value, WolfError> {
+        self.call_with(Rc::new(script), Vec::new())
+    }
+
+    /// Calls a script function from the host and returns its result.
+    pub fn call_function(&mut self, index: u32, args: Vec<Value>) -> Result<Value, WolfError> {
+        let function = Rc::clone(&self.functions[index as usize]);
+        self.call_with(function, args)
+    }
+
+    fn call_with(&mut self, function: Rc<Function>, args: Vec<Value>) -> Result<Value, WolfError> {
+        let depth = self.frames.len();
+        let height = self.stack.len();
+        let argc = args.len();
+        self.stack.extend(args);
+        self.call(function, argc, None).and_then(|_| self.run(depth)).map_err(|error| {
+            let trace = self.trace(depth);
+            self.frames.truncate(depth);
+            self.stack.truncate(height);
+            self.handlers.retain(|handler| handler.frames <= depth);
+            WolfError::runtime(error, trace)
+        })
+    }
+
+    /// The calls above `depth`, innermost first, each with where it was executing.
+    fn trace(&self, depth: usize) -> Vec<TraceFrame> {
+        self.frames[depth..].iter().rev()
+            .map(|frame| TraceFrame {
+                function: frame.function.name.clone(),
+                module: frame.function.module.clone(),
+                span: frame_span(frame),
+            })
+            .collect()
+    }
+
+    fn span(&self) -> Span {
+        self.frames.last().map(frame_span).unwrap_or_default()
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError::RuntimeError { message, span: self.span() }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("value stack underflow")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("value stack underflow")
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<Value> {
+        let at = self.stack.len() - count;
+        self.stack.split_off(at)
+    }
+
+    fn function_ref(&self) -> &Function {
+        &self.frames.last().expect("no active call frame").function
+    }
+
+    fn constant_name(&self, index: u32) -> String {
+        match &self.function_ref().chunk.constants[index as usize] {
+            Value::String(name) => name.clone(),
+            other => unreachable!("name constant is {:?}", other),
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("no active call frame").ip = target as usize;
+    }
+
+    fn root(slot: Slot, base: usize) -> Root {
+        match slot {
+            Slot::Local(index) => Root::Stack(base + index as usize),
+            Slot::Global(index) => Root::Global(index as usize),
+        }
+    }
+
+    fn call(&mut self, function: Rc<Function>, argc: usize, write_back: Option<Place>) -> Result<(), ParseError> {
+        if argc != function.arity() {
+            let given = argc - function.is_method as usize - function.captures;
+            return Err(self.error(format!(
+                "Function '{}' expects {} args but got {}", function.name, function.params.len(), given
+            )));
+        }
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(format!("Stack overflow: more than {} nested calls", MAX_FRAMES)));
+        }
+        let base = self.stack.len() - argc;
+        self.stack.resize(base + function.locals.max(argc), Value::Nil);
+        self.frames.push(CallFrame { function, ip: 0, base, write_back });
+        Ok(())
+    }
+
+    /// Runs until the frame at `stop_depth` returns. An error raised in a `try` block
+    /// of those frames goes to its `catch` rather than stopping the run.
+    fn run(&mut self, stop_depth: usize) -> Result<Value, ParseError> {
+        loop {
+            match self.execute(stop_depth) {
+                Err(error) if self.handlers.last().is_some_and(|handler| handler.frames > stop_depth) => {
+                    let handler = self.handlers.pop().expect("handler was checked");
+                    self.frames.truncate(handler.frames);
+                    self.stack.truncate(handler.stack);
+                    self.stack.push(Value::String(error.message()));
+                    self.jump(handler.target);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn execute(&mut self, stop_depth: usize) -> Result<Value, ParseError> {
+        loop {
+            let frame = self.frames.last_mut().expect("no active call frame");
+            let op = frame.function.chunk.code[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+
+            match op {
+                Op::Constant(index) => {
+                    let value = self.function_ref().chunk.constants[index as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => {
+                    let value = self.peek().clone();
+                    self.stack.push(value);
+                }
+                Op::DupMany(count) => {
+                    let from = self.stack.len() - count as usize;
+                    self.stack.extend_from_within(from..);
+                }
+
+                Op::GetLocal(slot) => {
+                    let value = self.stack[base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    self.stack[base + slot as usize] = self.pop();
+                }
+                Op::GetGlobal(slot) => {
+                    let value = self.globals[slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::SetGlobal(slot) => {
+                    self.globals[slot as usize] = self.pop();
+                }
+
+                Op::GetPath(slot, path) => {
+                    let function = Rc::clone(&self.frames.last().expect("no active call frame").function);
+                    let path = &function.chunk.paths[path as usize];
+                    let keys_at = self.stack.len() - key_count(path);
+                    let root = match Self::root(slot, base) {
+                        Root::Stack(index) => &self.stack[index],
+                        Root::Global(index) => &self.globals[index],
+                    };
+                    let keys = &self.stack[keys_at..];
+                    // The last step may index a string, which yields a new value
+                    let value = match path.split_last() {
+                        Some((Access::Index, parent)) => navigate(root, parent, &keys[..keys.len() - 1])
+                            .and_then(|container| index_value(container, &keys[keys.len() - 1])),
+                        _ => navigate(root, path, keys).cloned(),
+                    }.map_err(|message| self.error(message))?;
+                    self.stack.truncate(keys_at);
+                    self.stack.push(value);
+                }
+                Op::SetPath(slot, path) => {
+                    let span = self.span();
+                    let function = Rc::clone(&self.frames.last().expect("no active call frame").function);
+                    let path = &function.chunk.paths[path as usize];
+                    let value = self.pop();
+                    let keys_at = self.stack.len() - key_count(path);
+                    let (lower, keys) = self.stack.split_at_mut(keys_at);
+                    let root = match Self::root(slot, base) {
+                        Root::Stack(index) => &mut lower[index],
+                        Root::Global(index) => &mut self.globals[index],
+                    };
+                    store(root, path, keys, value.clone())
+                        .map_err(|message| ParseError::RuntimeError { message, span: span.clone() })?;
+                    self.stack.truncate(keys_at);
+                    self.stack.push(value);
+                }
+                Op::GetIndex => {
+                    let index = self.pop();
+                    let list = self.pop();
+                    let value = index_value(&list, &index).map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::GetField(name) => {
+                    let name = self.constant_name(name);
+                    let value = match self.pop() {
+                        Value::StructInstance { type_name, fields } => fields.into_iter()
+                            .find(|(field, _)| *field == name)
+                            .map(|(_, value)| value)
+                            .ok_or_else(|| self.error(format!("Struct '{}' has no field '{}'", type_name, name)))?,
+                        Value::Nil => return Err(self.error(format!("Cannot read field '{}' of nil", name))),
+                        _ => return Err(self.error("Field access on non-struct value".to_string())),
+                    };
+                    self.stack.push(value);
+                }
+
+                Op::CheckType(index) => {
+                    let expected = &self.function_ref().chunk.types[index as usize];
+                    if !expected.matches(self.peek()) {
+                        return Err(ParseError::TypeMismatch {
+                            expected: expected.clone(),
+                            found: self.peek().clone(),
+                            span: self.span(),
+                        });
+                    }
+                }
+                Op::CheckAssign(slot, name) => {
+                    let old_value = match Self::root(slot, base) {
+                        Root::Stack(index) => &self.stack[index],
+                        Root::Global(index) => &self.globals[index],
+                    };
+                    if !is_same_kind(old_value, self.peek()) {
+                        return Err(self.error(format!(
+                            "Type mismatch! Variable '{}' is {} but you tried to assign {}",
+                            self.constant_name(name), Type::of(old_value), Type::of(self.peek())
+                        )));
+                    }
+                }
+
+                Op::Add | Op::Subtract | Op::Multiply | Op::Divide
+                | Op::FloorDivide | Op::Modulo | Op::Power
+                | Op::BitAnd | Op::BitOr | Op::BitXor | Op::ShiftLeft | Op::ShiftRight
+                | Op::Greater | Op::Less | Op::GreaterEqual | Op::LessEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = arithmetic(op, left, right, self.overflow).map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(equals(&left, &right)));
+                }
+                Op::NotEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(!equals(&left, &right)));
+                }
+                Op::Cast(index) => {
+                    let target = &self.function_ref().chunk.types[index as usize];
+                    let value = native_functions::convert(self.peek(), target).map_err(|message| self.error(message))?;
+                    *self.stack.last_mut().expect("value stack underflow") = value;
+                }
+                Op::Negate => {
+                    let value = match self.pop() {
+                        Value::Integer(n) => self.overflow.resolve(n.checked_neg(), || n.wrapping_neg(), || n.saturating_neg(), "-")
+                            .map_err(|message| self.error(message))?,
+                        Value::Float(n) => Value::Float(-n),
+                        Value::Nil => return Err(self.error("Cannot negate nil".to_string())),
+                        other => return Err(self.error(format!("'-' expects int or float, found {}", Type::of(&other)))),
+                    };
+                    self.stack.push(value);
+                }
+                Op::Not => {
+                    let value = match self.pop() {
+                        Value::Boolean(b) => Value::Boolean(!b),
+                        Value::Nil => return Err(self.error("Cannot apply '!' to nil".to_string())),
+                        other => return Err(self.error(format!("'!' expects bool, found {}", Type::of(&other)))),
+                    };
+                    self.stack.push(value);
+                }
+                Op::BitNot => {
+                    let value = match self.pop() {
+                        Value::Integer(n) => Value::Integer(!n),
+                        other => return Err(self.error(format!("'~' expects int, found {}", Type::of(&other)))),
+                    };
+                    self.stack.push(value);
+                }
+
+                Op::Jump(target) => self.jump(target),
+                Op::JumpIfFalse(target) => match self.pop() {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => self.jump(target),
+                    Value::Nil => return Err(self.error("Condition is nil, expected a bool".to_string())),
+                    other => return Err(self.error(format!("Condition must be boolean! Found: {}", Type::of(&other)))),
+                },
+                Op::Or(target) => match self.peek() {
+                    Value::Boolean(true) => self.jump(target),
+                    Value::Boolean(false) => {
+                        self.pop();
+                    }
+                    _ => return Err(self.error("'or' operator's left needs to be Boolean!".to_string())),
+                },
+                Op::And(target) => match self.peek() {
+                    Value::Boolean(false) => self.jump(target),
+                    Value::Boolean(true) => {
+                        self.pop();
+                    }
+                    _ => return Err(self.error("'and' operator's left needs to be Boolean!".to_string())),
+                },
+                Op::JumpIfNil(target) => {
+                    if *self.peek() == Value::Nil {
+                        self.jump(target);
+                    }
+                }
+                Op::Coalesce(target) => {
+                    if *self.peek() == Value::Nil {
+                        self.pop();
+                    } else {
+                        self.jump(target);
+                    }
+                }
+                Op::AssertBool => {
+                    if !matches!(self.peek(), Value::Boolean(_)) {
+                        return Err(self.error("'and'/'or' operator's right needs to be Boolean!".to_string()));
+                    }
+                }
+
+                Op::ForRange(var, exit) => {
+                    let var = base + var as usize;
+                    let [counter, limit, step] = [1, 2, 3].map(|offset| match self.stack[var + offset] {
+                        Value::Integer(n) => n,
+                        ref other => unreachable!("range bound is {:?}", other),
+                    });
+                    let running = match step {
+                        0 => return Err(self.error("Range step cannot be zero".to_string())),
+                        1.. => counter < limit,
+                        _ => counter > limit,
+                    };
+                    if running {
+                        self.stack[var] = Value::Integer(counter);
+                        // Past i64::MAX the loop has nowhere left to go
+                        self.stack[var + 1] = Value::Integer(counter.checked_add(step).unwrap_or(limit));
+                    } else {
+                        self.jump(exit);
+                    }
+                }
+                Op::ForEach(vars, exit, pair) => {
+                    let vars = base + vars as usize;
+                    let (items, position) = (vars + 1 + pair as usize, vars + 2 + pair as usize);
+                    // Strings are split into characters the first time round
+                    if let Value::String(text) = &self.stack[items] {
+                        let chars = text.chars().map(|c| Value::String(c.to_string())).collect();
+                        self.stack[items] = Value::List(chars);
+                    }
+                    let Value::Integer(at) = self.stack[position] else {
+                        unreachable!("loop position is {:?}", self.stack[position]);
+                    };
+                    let (key, item) = match &self.stack[items] {
+                        Value::List(elements) => match elements.get(at as usize) {
+                            Some(element) => (Value::Integer(at), element.clone()),
+                            None => {
+                                self.jump(exit);
+                                continue;
+                            }
+                        },
+                        // A map visited through one variable hands out its keys
+                        Value::Map(entries) => match entries.get(at as usize) {
+                            Some((key, value)) if pair => (key.clone(), value.clone()),
+                            Some((key, _)) => (Value::Nil, key.clone()),
+                            None => {
+                                self.jump(exit);
+                                continue;
+                            }
+                        },
+                        other => return Err(self.error(format!("Cannot iterate over {}", Type::of(other)))),
+                    };
+                    self.stack[position] = Value::Integer(at + 1);
+                    if pair {
+                        self.stack[vars] = key;
+                        self.stack[vars + 1] = item;
+                    } else {
+                        self.stack[vars] = item;
+                    }
+                }
+
+                Op::Call(index, argc) => {
+                    let function = Rc::clone(&self.functions[index as usize]);
+                    self.call(function, argc as usize, None)?;
+                }
+                Op::CallValue(argc) => {
+                    let callee_at = self.stack.len() - argc as usize - 1;
+                    match self.stack.remove(callee_at) {
+                        Value::Function(closure) => self.call_closure(&closure, argc as usize)?,
+                        Value::NativeFunction(name) => self.call_native(&name, argc as usize)?,
+                        Value::Nil => return Err(self.error("Cannot call nil".to_string())),
+                        other => return Err(self.error(format!("Cannot call {} of type {}", other, Type::of(&other)))),
+                    }
+                }
+                Op::CallNative(name, argc) => {
+                    let name = self.constant_name(name);
+                    self.call_native(&name, argc as usize)?;
+                }
+                Op::Invoke(name, argc) => {
+                    let name = self.constant_name(name);
+                    let argc = argc as usize;
+                    let receiver_at = self.stack.len() - argc - 1;
+                    if let Some(type_name) = instance_type(&self.stack[receiver_at]) {
+                        match field_function(&self.methods, &self.stack[receiver_at], type_name, &name) {
+                            Some(closure) => {
+                                self.stack.remove(receiver_at);
+                                self.call_closure(&closure, argc)?;
+                            }
+                            None => {
+                                let function = self.method(type_name, &name)?;
+                                self.call(function, argc + 1, None)?;
+                            }
+                        }
+                    } else {
+                        let args = self.pop_many(argc);
+                        let mut receiver = self.pop();
+                        let result = match &mut receiver {
+                            Value::List(elements) if takes_callback(&name) => self.callback_method(elements, &name, args)?,
+                            _ => builtin_method(&mut receiver, &name, args).map_err(|message| self.error(message))?,
+                        };
+                        self.stack.push(result);
+                    }
+                }
+                Op::InvokePath(slot, path, name, argc) => {
+                    let span = self.span();
+                    let name = self.constant_name(name);
+                    let function = Rc::clone(&self.frames.last().expect("no active call frame").function);
+                    let path = &function.chunk.paths[path as usize];
+                    let args = self.pop_many(argc as usize);
+                    let keys = self.pop_many(key_count(path));
+                    let root = Self::root(slot, base);
+                    let receiver = match root {
+                        Root::Stack(index) => &mut self.stack[index],
+                        Root::Global(index) => &mut self.globals[index],
+                    };
+                    let receiver = navigate_mut(receiver, path, &keys)
+                        .map_err(|message| ParseError::RuntimeError { message, span: span.clone() })?;
+
+                    if let Value::StructInstance { type_name, .. } = &*receiver
+                        && let Some(closure) = field_function(&self.methods, receiver, type_name, &name) {
+                        self.stack.extend(args);
+                        self.call_closure(&closure, argc as usize)?;
+                    } else if let Some(type_name) = instance_type(receiver) {
+                        let type_name = type_name.clone();
+                        let receiver = receiver.clone();
+                        let method = self.method(&type_name, &name)?;
+                        self.stack.push(receiver);
+                        self.stack.extend(args);
+                        let place = Place { root, path: path.clone(), keys };
+                        self.call(method, argc as usize + 1, Some(place))?;
+                    } else if let Value::List(elements) = &*receiver
+                        && takes_callback(&name) {
+                        // Callbacks may change the list themselves, so they run on a copy
+                        // that `sort_by` stores back once it is sorted.
+                        let mut elements = elements.clone();
+                        let result = self.callback_method(&mut elements, &name, args)?;
+                        if name == "sort_by" {
+                            let root = match root {
+                                Root::Stack(index) => &mut self.stack[index],
+                                Root::Global(index) => &mut self.globals[index],
+                            };
+                            *navigate_mut(root, path, &keys).map_err(|message| ParseError::RuntimeError { message, span: span.clone() })? = Value::List(elements);
+                        }
+                        self.stack.push(result);
+                    } else {
+                        let result = builtin_method(receiver, &name, args)
+                            .map_err(|message| ParseError::RuntimeError { message, span: span.clone() })?;
+                        self.stack.push(result);
+                    }
+                }
+
+                Op::MakeList(count) => {
+                    let elements = self.pop_many(count as usize);
+                    self.stack.push(Value::List(elements));
+                }
+                Op::Slice(has_start, has_end) => {
+                    let end = if has_end { Some(self.pop()) } else { None };
+                    let start = if has_start { Some(self.pop()) } else { None };
+                    let container = self.pop();
+                    let value = slice_value(&container, start.as_ref(), end.as_ref()).map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::MakeMap(count) => {
+                    let values = self.pop_many(2 * count as usize);
+                    let mut entries = Vec::with_capacity(count as usize);
+                    let mut values = values.into_iter();
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        map_insert(&mut entries, key, value).map_err(|message| self.error(message))?;
+                    }
+                    self.stack.push(Value::Map(entries));
+                }
+                Op::MakeClosure(index, count) => {
+                    let captures = self.pop_many(count as usize);
+                    let function = Rc::clone(&self.functions[index as usize]);
+                    self.stack.push(Value::Function(Rc::new(Closure { function, captures })));
+                }
+                Op::MakeStruct(name, argc) => {
+                    let type_name = self.constant_name(name);
+                    let fields = &self.struct_defs[&type_name];
+                    if fields.len() != argc as usize {
+                        return Err(self.error(format!(
+                            "Struct '{}' expects {} fields but got {}", type_name, fields.len(), argc
+                        )));
+                    }
+                    let names: Vec<String> = fields.iter().map(|(field, _)| field.clone()).collect();
+                    let values = self.pop_many(argc as usize);
+                    self.stack.push(Value::StructInstance { type_name, fields: names.into_iter().zip(values).collect() });
+                }
+                Op::MakeVariant(type_name, variant, argc) => {
+                    let type_name = self.constant_name(type_name);
+                    let variant = self.constant_name(variant);
+                    let fields = self.enum_defs.get(&type_name)
+                        .and_then(|variants| variants.iter().find(|v| v.name == variant))
+                        .map(|v| v.fields.iter().map(|(field, _)| field.clone()).collect::<Vec<_>>())
+                        .ok_or_else(|| self.error(format!("Enum '{}' has no variant '{}'", type_name, variant)))?;
+                    if fields.len() != argc as usize {
+                        return Err(self.error(format!(
+                            "Variant '{}.{}' expects {} values but got {}", type_name, variant, fields.len(), argc
+                        )));
+                    }
+                    let values = self.pop_many(argc as usize);
+                    let fields = fields.into_iter().zip(values).collect();
+                    self.stack.push(Value::Enum(Rc::new(EnumValue { type_name, variant, fields })));
+                }
+                Op::IsVariant(name) => {
+                    let name = self.constant_name(name);
+                    let is_variant = matches!(self.pop(), Value::Enum(value) if value.variant == name);
+                    self.stack.push(Value::Boolean(is_variant));
+                }
+                Op::Payload(position) => match self.pop() {
+                    Value::Enum(value) if (position as usize) < value.fields.len() => {
+                        self.stack.push(value.fields[position as usize].1.clone());
+                    }
+                    other => return Err(self.error(format!("{} has no value at position {}", other, position))),
+                },
+                Op::NoMatch => {
+                    let value = self.pop();
+                    return Err(self.error(format!("No case matches {}", value)));
+                }
+                Op::PushHandler(target) => {
+                    self.handlers.push(Handler { frames: self.frames.len(), stack: self.stack.len(), target });
+                }
+                Op::PopHandler => {
+                    self.handlers.pop();
+                }
+
+                Op::Print(count) => {
+                    let values = self.pop_many(count as usize);
+                    for value in &values {
+                        print_value(value).map_err(|message| self.error(message))?;
+                    }
+                    println!();
+                }
+
+                Op::Return => {
+                    let result = self.pop();
+                    let span = self.span();
+                    let frame = self.frames.pop().expect("no active call frame");
+                    if let Err(e) = frame.function.check_return(&result, &span) {
+                        self.frames.push(frame);
+                        return Err(e);
+                    }
+                    // A `return` inside a `try` leaves its block
+                    let frames = self.frames.len();
+                    self.handlers.retain(|handler| handler.frames <= frames);
+                    if let Some(place) = frame.write_back {
+                        let receiver = std::mem::replace(&mut self.stack[frame.base], Value::Nil);
+                        let root = match place.root {
+                            Root::Stack(index) => &mut self.stack[index],
+                            Root::Global(index) => &mut self.globals[index],
+                        };
+                        match navigate_mut(root, &place.path, &place.keys) {
+                            Ok(target) => *target = receiver,
+                            Err(message) => return Err(ParseError::RuntimeError { message, span: span.clone() }),
+                        }
+                    }
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == stop_depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+
+    /// Calls a function value and runs it to completion, for methods that take a callback.
+    fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, ParseError> {
+        let depth = self.frames.len();
+        let argc = args.len();
+        self.stack.extend(args);
+        match callee {
+            Value::Function(closure) => self.call_closure(closure, argc)?,
+            Value::NativeFunction(name) => self.call_native(name, argc)?,
+            other => return Err(self.error(format!("Cannot call {} of type {}", other, Type::of(other)))),
+        }
+        if self.frames.len() > depth {
+            if self.callback_depth >= MAX_CALLBACK_DEPTH {
+                self.frames.truncate(depth);
+                return Err(self.error(format!("Stack overflow: more than {} nested callbacks", MAX_CALLBACK_DEPTH)));
+            }
+            self.callback_depth += 1;
+            let result = self.run(depth);
+            self.callback_depth -= 1;
+            result
+        } else {
+            Ok(self.pop())
+        }
+    }
+
+    /// Calls a callback that decides something about its arguments.
+    fn call_predicate(&mut self, callee: &Value, method: &str, args: Vec<Value>) -> Result<bool, ParseError> {
+        match self.call_value(callee, args)? {
+            Value::Boolean(b) => Ok(b),
+            other => Err(self.error(format!("The function passed to '{}' must return bool, found {}", method, Type::of(&other)))),
+        }
+    }
+
+    /// List methods that call back into the script. `sort_by` sorts `elements` in place.
+    fn callback_method(&mut self, elements: &mut Vec<Value>, method: &str, args: Vec<Value>) -> Result<Value, ParseError> {
+        let mut args = args.into_iter();
+        let initial = if method == "reduce" { args.next() } else { None };
+        let callback = match args.next() {
+            Some(callback @ (Value::Function(_) | Value::NativeFunction(_))) => callback,
+            other => return Err(self.error(format!("'{}' expects a function argument, found {}", method, arg_type(other.as_ref())))),
+        };
+
+        match method {
+            "map" => {
+                let mut mapped = Vec::with_capacity(elements.len());
+                for element in elements.iter() {
+                    mapped.push(self.call_value(&callback, vec![element.clone()])?);
+                }
+                Ok(Value::List(mapped))
+            }
+            "filter" => {
+                let mut kept = Vec::new();
+                for element in elements.iter() {
+                    if self.call_predicate(&callback, method, vec![element.clone()])? {
+                        kept.push(element.clone());
+                    }
+                }
+                Ok(Value::List(kept))
+            }
+            "reduce" => {
+                let mut total = initial.unwrap_or(Value::Nil);
+                for element in elements.iter() {
+                    total = self.call_value(&callback, vec![total, element.clone()])?;
+                }
+                Ok(total)
+            }
+            "any" | "all" => {
+                // Stops at the first element that settles the answer
+                let wanted = method == "any";
+                for element in elements.iter() {
+                    if self.call_predicate(&callback, method, vec![element.clone()])? == wanted {
+                        return Ok(Value::Boolean(wanted));
+                    }
+                }
+                Ok(Value::Boolean(!wanted))
+            }
+            "find" => {
+                for element in elements.iter() {
+                    if self.call_predicate(&callback, method, vec![element.clone()])? {
+                        return Ok(element.clone());
+                    }
+                }
+                Ok(Value::Nil)
+            }
+            "sort_by" => {
+                *elements = self.merge_sort(elements.clone(), &callback)?;
+                Ok(Value::Nil)
+            }
+            _ => Err(self.error(format!("Unknown list method '{}'", method))),
+        }
+    }
+
+    /// A stable sort where `before(a, b)` tells whether `a` goes before `b`. Unlike
+    /// `slice::sort_by` it copes with a callback that fails or is not a total order.
+    fn merge_sort(&mut self, mut elements: Vec<Value>, before: &Value) -> Result<Vec<Value>, ParseError> {
+        if elements.len() < 2 {
+            return Ok(elements);
+        }
+        let right = elements.split_off(elements.len() / 2);
+        let mut left = self.merge_sort(elements, before)?.into_iter().peekable();
+        let mut right = self.merge_sort(right, before)?.into_iter().peekable();
+
+        let mut sorted = Vec::with_capacity(left.len() + right.len());
+        while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+            // Equal elements keep their order: the right one only moves first when it must.
+            let next = if self.call_predicate(before, "sort_by", vec![r.clone(), l.clone()])? { right.next() } else { left.next() };
+            sorted.extend(next);
+        }
+        sorted.extend(left);
+        sorted.extend(right);
+        Ok(sorted)
+    }
+
+    /// Calls a function value whose arguments are on the stack; its captures follow them.
+    fn call_closure(&mut self, closure: &Closure, argc: usize) -> Result<(), ParseError> {
+        self.stack.extend(closure.captures.iter().cloned());
+        self.call(Rc::clone(&closure.function), argc + closure.captures.len(), None)
+    }
+
+    fn method(&self, type_name: &str, name: &str) -> Result<Rc<Function>, ParseError> {
+        self.methods.get(type_name)
+            .and_then(|methods| methods.get(name))
+            .map(|index| Rc::clone(&self.functions[*index as usize]))
+            .ok_or_else(|| {
+                let kind = if self.enum_defs.contains_key(type_name) { "Enum" } else { "Struct" };
+                self.error(format!("{} '{}' has no method '{}'", kind, type_name, name))
+            })
+    }
+
+    /// Calls a built-in, a host function or a script function looked up by name.
+    fn call_native(&mut self, name: &str, argc: usize) -> Result<(), ParseError> {
+        if native_functions::is_builtin(name) {
+            let args = self.pop_many(argc);
+            let result = match native_functions::dispatch(name, args) {
+                Some(result) => result.map_err(|error| self.error(error.message()))?,
+                None => Value::Nil,
+            };
+            self.stack.push(result);
+            return Ok(());
+        }
+        let native = self.native_fns.borrow().get(name).cloned();
+        if let Some(native) = native {
+            let args = self.pop_many(argc);
+            let result = native(args).map_err(|message| self.error(message))?;
+            self.stack.push(result);
+            return Ok(());
+        }
+        match self.function(name) {
+            Some(function) => self.call(Rc::clone(function), argc, None),
+            None => Err(self.error(format!("Undefined function '{}'", name))),
+        }
+    }
+}
+
+/// The type whose methods a value can call: a struct or an enum.
+fn instance_type(value: &Value) -> Option<&String> {
+    match value {
+        Value::StructInstance { type_name, .. } => Some(type_name),
+        Value::Enum(value) => Some(&value.type_name),
+        _ => None,
+    }
+}
+
+/// A field holding a function, called like a method when the struct has no
+/// method of that name: `button.on_click()`.
+fn field_function(methods: &HashMap<String, HashMap<String, u32>>, receiver: &Value, type_name: &str, name: &str) -> Option<Rc<Closure>> {
+    if methods.get(type_name).is_some_and(|methods| methods.contains_key(name)) {
+        return None;
+    }
+    match receiver {
+        Value::StructInstance { fields, .. } => fields.iter().find_map(|(field, value)| match value {
+            Value::Function(closure) if field == name => Some(Rc::clone(closure)),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// List methods that take a function, run by `Vm::callback_method`.
+fn takes_callback(method: &str) -> bool {
+    matches!(method, "map" | "filter" | "reduce" | "any" | "all" | "find" | "sort_by")
+}
+
+pub(crate) fn key_count(path: &[Access]) -> usize {
+    path.iter().filter(|access| **access == Access::Index).count()
+}
+
+/// Location of the instruction a frame executed last.
+fn frame_span(frame: &CallFrame) -> Span {
+    frame.function.chunk.spans[frame.ip.saturating_sub(1)].clone()
+}
+
+fn list_index(key: &Value, len: usize) -> Result<usize, String> {
+    match key {
+        // Negative indices count from the end: -1 is the last element
+        Value::Integer(n) => n.checked_add(if *n < 0 { len as i64 } else { 0 })
+            .and_then(|at| usize::try_from(at).ok())
+            .filter(|at| *at < len)
+            .ok_or_else(|| format!("Index out of bounds! Len: {}, Index: {}", len, n)),
+        _ => Err("Index must be an Integer!".to_string()),
+    }
+}
+
+/// The range `start..end` of a slice over `len` items; bounds may be negative.
+fn slice_range(start: Option<&Value>, end: Option<&Value>, len: usize) -> Result<std::ops::Range<usize>, String> {
+    let bound = |value: Option<&Value>| match value {
+        None => Ok(None),
+        Some(Value::Integer(n)) => Ok(Some(*n)),
+        Some(_) => Err("Slice bounds must be Integers!".to_string()),
+    };
+    let (start, end) = (bound(start)?, bound(end)?);
+    let resolve = |n: i64| if n < 0 { n.saturating_add(len as i64) } else { n };
+    let (from, to) = (start.map_or(0, resolve), end.map_or(len as i64, resolve));
+    if 0 <= from && from <= to && to <= len as i64 {
+        return Ok(from as usize..to as usize);
+    }
+    let show = |bound: Option<i64>| bound.map(|n| n.to_string()).unwrap_or_default();
+    Err(format!("Slice {}:{} is out of bounds for length {}", show(start), show(end), len))
+}
+
+/// `container[start:end]` for lists and strings.
+fn slice_value(container: &Value, start: Option<&Value>, end: Option<&Value>) -> Result<Value, String> {
+    match container {
+        Value::List(elements) => Ok(Value::List(elements[slice_range(start, end, elements.len())?].to_vec())),
+        Value::String(text) => {
+            let range = slice_range(start, end, text.chars().count())?;
+            Ok(Value::String(text.chars().skip(range.start).take(range.len()).collect()))
+        }
+        _ => Err("Type mismatch. Expected a list or a string to slice.".to_string()),
+    }
+}
+
+/// Position of `key` in a map's entries.
+fn map_entry(entries: &[(Value, Value)], key: &Value) -> Result<usize, String> {
+    if !key.is_key() {
+        return Err(format!("Map keys must be int, string or bool! Found: {}", Type::of(key)));
+    }
+    entries.iter().position(|(entry, _)| entry == key)
+        .ok_or_else(|| match key {
+            Value::String(s) => format!("Key \"{}\" not found in map", s),
+            other => format!("Key {} not found in map", other),
+        })
+}
+
+/// Sets the value under `key`, adding the key if the map does not have it yet.
+fn map_insert(entries: &mut Vec<(Value, Value)>, key: Value, value: Value) -> Result<(), String> {
+    match map_entry(entries, &key) {
+        Ok(at) => entries[at].1 = value,
+        Err(_) if key.is_key() => entries.push((key, value)),
+        Err(message) => return Err(message),
+    }
+    Ok(())
+}
+
+/// `container[key]`; indexing a string gives its character at `key`.
+fn index_value(container: &Value, key: &Value) -> Result<Value, String> {
+    match container {
+        Value::List(elements) => Ok(elements[list_index(key, elements.len())?].clone()),
+        Value::Map(entries) => Ok(entries[map_entry(entries, key)?].1.clone()),
+        Value::String(text) => {
+            let at = list_index(key, text.chars().count())?;
+            Ok(Value::String(text.chars().nth(at).map(String::from).unwrap_or_default()))
+        }
+        Value::Nil => Err("Cannot index into nil".to_string()),
+        _ => Err("Type mismatch. Expected a list, a map or a string to index.".to_string()),
+    }
+}
+
+fn navigate<'v>(mut value: &'v Value, path: &[Access], keys: &[Value]) -> Result<&'v Value, String> {
+    let mut keys = keys.iter();
+    for access in path {
+        value = match (access, value) {
+            (Access::Index, Value::List(elements)) => {
+                let key = keys.next().expect("missing index key");
+                &elements[list_index(key, elements.len())?]
+            }
+            (Access::Index, Value::Map(entries)) => {
+                let key = keys.next().expect("missing index key");
+                &entries[map_entry(entries, key)?].1
+            }
+            (Access::Index, Value::Nil) => return Err("Cannot index into nil".to_string()),
+            (Access::Index, _) => return Err("Target is not a list or a map!".to_string()),
+            (Access::Field(name), Value::Nil) => return Err(format!("Cannot read field '{}' of nil", name)),
+            (Access::Field(name), Value::StructInstance { fields, .. }) => fields.iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("Field '{}' not found", name))?,
+            (Access::Field(_), _) => return Err("Field access on non-struct value".to_string()),
+        };
+    }
+    Ok(value)
+}
+
+fn navigate_mut<'v>(mut value: &'v mut Value, path: &[Access], keys: &[Value]) -> Result<&'v mut Value, String> {
+    let mut keys = keys.iter();
+    for access in path {
+        value = match (access, value) {
+            (Access::Index, Value::List(elements)) => {
+                let key = keys.next().expect("missing index key");
+                let at = list_index(key, elements.len())?;
+                &mut elements[at]
+            }
+            (Access::Index, Value::Map(entries)) => {
+                let key = keys.next().expect("missing index key");
+                let at = map_entry(entries, key)?;
+                &mut entries[at].1
+            }
+            (Access::Index, Value::Nil) => return Err("Cannot index into nil".to_string()),
+            (Access::Index, _) => return Err("Target is not a list or a map!".to_string()),
+            (Access::Field(name), Value::Nil) => return Err(format!("Cannot change field '{}' of nil", name)),
+            (Access::Field(name), Value::StructInstance { fields, .. }) => fields.iter_mut()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("Field '{}' not found", name))?,
+            (Access::Field(_), _) => return Err("Field access on non-struct value".to_string()),
+        };
+    }
+    Ok(value)
+}
+
+/// Writes `value` at the end of a place. Assigning to a missing map key adds it.
+fn store(root: &mut Value, path: &[Access], keys: &[Value], value: Value) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        *root = value;
+        return Ok(());
+    };
+    let parent_keys = key_count(parents);
+    let parent = navigate_mut(root, parents, &keys[..parent_keys])?;
+    match (last, parent) {
+        (Access::Index, Value::Map(entries)) => map_insert(entries, keys[parent_keys].clone(), value),
+        (_, parent) => {
+            *navigate_mut(parent, std::slice::from_ref(last), &keys[parent_keys..])? = value;
+            Ok(())
+        }
+    }
+}
+
+/// Methods built into lists and maps.
+fn builtin_method(receiver: &mut Value, method: &str, args: Vec<Value>) -> Result<Value, String> {
+    match receiver {
+        Value::List(elements) => list_method(elements, method, args),
+        Value::Map(entries) => map_method(entries, method, args),
+        Value::String(text) => string_method(text, method, args),
+        Value::Nil => Err(format!("Cannot call method '{}' on nil", method)),
+        other => Err(format!("{} is not a list, a map or a string, cannot call method '{}'", Type::of(other), method)),
+    }
+}
+
+fn list_method(elements: &mut Vec<Value>, method: &str, args: Vec<Value>) -> Result<Value, String> {
+    match method {
+        "push" => {
+            elements.push(args.into_iter().next().unwrap_or(Value::Nil));
+            Ok(Value::Nil)
+        }
+        "pop" => elements.pop().ok_or_else(|| "Cannot pop from an empty list".to_string()),
+        "len" => Ok(Value::Integer(elements.len() as i64)),
+        "insert" => {
+            let at = int_arg(method, &args, 0)?;
+            let position = at.checked_add(if at < 0 { elements.len() as i64 } else { 0 })
+                .and_then(|position| usize::try_from(position).ok())
+                .filter(|position| *position <= elements.len())
+                .ok_or_else(|| format!("Insert position {} is out of bounds for length {}", at, elements.len()))?;
+            elements.insert(position, args.into_iter().nth(1).unwrap_or(Value::Nil));
+            Ok(Value::Nil)
+        }
+        "remove" => {
+            let at = list_index(args.first().unwrap_or(&Value::Nil), elements.len())?;
+            Ok(elements.remove(at))
+        }
+        "contains" => {
+            let value = args.first().unwrap_or(&Value::Nil);
+            Ok(Value::Boolean(elements.iter().any(|element| equals(element, value))))
+        }
+        "index_of" => {
+            let value = args.first().unwrap_or(&Value::Nil);
+            let found = elements.iter().position(|element| equals(element, value));
+            Ok(Value::Integer(found.map_or(-1, |at| at as i64)))
+        }
+        "reverse" => {
+            elements.reverse();
+            Ok(Value::Nil)
+        }
+        "sort" => {
+            sort_values(elements)?;
+            Ok(Value::Nil)
+        }
+        "slice" => {
+            let range = slice_range(args.first(), args.get(1), elements.len())?;
+            Ok(Value::List(elements[range].to_vec()))
+        }
+        "extend" => match args.into_iter().next() {
+            Some(Value::List(other)) => {
+                elements.extend(other);
+                Ok(Value::Nil)
+            }
+            other => Err(format!("'extend' expects a list argument, found {}", arg_type(other.as_ref()))),
+        },
+        "clear" => {
+            elements.clear();
+            Ok(Value::Nil)
+        }
+        "first" => elements.first().cloned().ok_or_else(|| "Cannot take the first element of an empty list".to_string()),
+        "last" => elements.last().cloned().ok_or_else(|| "Cannot take the last element of an empty list".to_string()),
+        "join" => {
+            let separator = string_arg(method, &args, 0)?;
+            let parts: Vec<String> = elements.iter().map(Value::to_string).collect();
+            Ok(Value::String(parts.join(separator)))
+        }
+        "enumerate" => Ok(Value::List(elements.iter().enumerate()
+            .map(|(at, element)| Value::List(vec![Value::Integer(at as i64), element.clone()]))
+            .collect())),
+        "zip" => match args.first() {
+            Some(Value::List(other)) => Ok(Value::List(elements.iter().zip(other)
+                .map(|(left, right)| Value::List(vec![left.clone(), right.clone()]))
+                .collect())),
+            other => Err(format!("'zip' expects a list argument, found {}", arg_type(other))),
+        },
+        _ => Err(format!("Unknown list method '{}'", method)),
+    }
+}
+
+fn map_method(entries: &mut Vec<(Value, Value)>, method: &str, args: Vec<Value>) -> Result<Value, String> {
+    let key = args.into_iter().next().unwrap_or(Value::Nil);
+    match method {
+        "keys" => Ok(Value::List(entries.iter().map(|(key, _)| key.clone()).collect())),
+        "values" => Ok(Value::List(entries.iter().map(|(_, value)| value.clone()).collect())),
+        "has" => Ok(Value::Boolean(entries.iter().any(|(entry, _)| *entry == key))),
+        "remove" => {
+            let at = map_entry(entries, &key)?;
+            Ok(entries.remove(at).1)
+        }
+        "len" => Ok(Value::Integer(entries.len() as i64)),
+        _ => Err(format!("Unknown map method '{}'", method)),
+    }
+}
+
+/// Sorts ints and floats by value, strings by their characters and `false` before `true`.
+fn sort_values(elements: &mut [Value]) -> Result<(), String> {
+    let mut failure = None;
+    elements.sort_by(|a, b| match (a, b) {
+        (Value::Integer(l), Value::Integer(r)) => l.cmp(r),
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
+        _ => match (to_float(a), to_float(b)) {
+            (Some(l), Some(r)) => l.total_cmp(&r),
+            _ => {
+                failure.get_or_insert_with(|| format!("Cannot sort a list holding {} and {}", Type::of(a), Type::of(b)));
+                Ordering::Equal
+            }
+        },
+    });
+    failure.map_or(Ok(()), Err)
+}
+
+/// Methods on strings. Lengths and positions count characters, not bytes.
+fn string_method(text: &str, method: &str, args: Vec<Value>) -> Result<Value, String> {
+    let value = match method {
+        "len" => Value::Integer(text.chars().count() as i64),
+        "upper" => Value::String(text.to_uppercase()),
+        "lower" => Value::String(text.to_lowercase()),
+        "trim" => Value::String(text.trim().to_string()),
+        "split" => {
+            let separator = string_arg(method, &args, 0)?;
+            if separator.is_empty() {
+                return Err("Cannot split on an empty separator, use chars() instead".to_string());
+            }
+            Value::List(text.split(separator).map(|part| Value::String(part.to_string())).collect())
+        }
+        "contains" => Value::Boolean(text.contains(string_arg(method, &args, 0)?)),
+        "starts_with" => Value::Boolean(text.starts_with(string_arg(method, &args, 0)?)),
+        "ends_with" => Value::Boolean(text.ends_with(string_arg(method, &args, 0)?)),
+        "replace" => Value::String(text.replace(string_arg(method, &args, 0)?, string_arg(method, &args, 1)?)),
+        "find" => {
+            let found = text.find(string_arg(method, &args, 0)?);
+            Value::Integer(found.map_or(-1, |byte| text[..byte].chars().count() as i64))
+        }
+        "substring" => {
+            let (start, end) = (int_arg(method, &args, 0)?, int_arg(method, &args, 1)?);
+            let len = text.chars().count() as i64;
+            if start < 0 || start > end || end > len {
+                return Err(format!("Substring range {}..{} is out of bounds for length {}", start, end, len));
+            }
+            Value::String(text.chars().skip(start as usize).take((end - start) as usize).collect())
+        }
+        "chars" => Value::List(text.chars().map(|c| Value::String(c.to_string())).collect()),
+        "repeat" => {
+            let count = int_arg(method, &args, 0)?;
+            let count = usize::try_from(count).map_err(|_| format!("Repeat count cannot be negative! Found: {}", count))?;
+            match text.len().checked_mul(count) {
+                Some(len) if len <= MAX_REPEAT_LEN => Value::String(text.repeat(count)),
+                _ => return Err("Repeat result too large".to_string()),
+            }
+        }
+        _ => return Err(format!("Unknown string method '{}'", method)),
+    };
+    Ok(value)
+}
+
+/// The type of an argument for an error message, or `nothing` when it is missing.
+fn arg_type(arg: Option<&Value>) -> String {
+    arg.map_or_else(|| "nothing".to_string(), |value| Type::of(value).to_string())
+}
+
+fn string_arg<'v>(method: &str, args: &'v [Value], at: usize) -> Result<&'v str, String> {
+    match args.get(at) {
+        Some(Value::String(text)) => Ok(text),
+        other => Err(format!("'{}' expects a string argument, found {}", method, arg_type(other))),
+    }
+}
+
+fn int_arg(method: &str, args: &[Value], at: usize) -> Result<i64, String> {
+    match args.get(at) {
+        Some(Value::Integer(n)) => Ok(*n),
+        other => Err(format!("'{}' expects an int argument, found {}", method, arg_type(other))),
+    }
+}
+
+fn arithmetic(op: Op, left: Value, right: Value, overflow: Overflow) -> Result<Value, String> {
+    if left == Value::Nil || right == Value::Nil {
+        return Err(format!("Cannot use nil in arithmetic or comparisons (found {} and {})", left, right));
+    }
+    let (left, right) = promote(left, right);
+    // Two ints compare exactly; going through f64 would round large ones
+    let compared = match (&left, &right) {
+        (Value::Integer(l), Value::Integer(r)) => compare(op, l, r),
+        (Value::Float(l), Value::Float(r)) => compare(op, l, r),
+        _ => None,
+    };
+    if let Some(result) = compared {
+        return Ok(Value::Boolean(result));
+    }
+
+    match (left, op, right) {
+        (Value::Integer(l), Op::Add, Value::Integer(r)) => {
+            overflow.resolve(l.checked_add(r), || l.wrapping_add(r), || l.saturating_add(r), "+")
+        }
+        (Value::Float(l), Op::Add, Value::Float(r)) => Ok(Value::Float(l + r)),
+        (Value::Integer(l), Op::Subtract, Value::Integer(r)) => {
+            overflow.resolve(l.checked_sub(r), || l.wrapping_sub(r), || l.saturating_sub(r), "-")
+        }
+        (Value::Float(l), Op::Subtract, Value::Float(r)) => Ok(Value::Float(l - r)),
+        (Value::Integer(l), Op::Multiply, Value::Integer(r)) => {
+            overflow.resolve(l.checked_mul(r), || l.wrapping_mul(r), || l.saturating_mul(r), "*")
+        }
+        (Value::Float(l), Op::Multiply, Value::Float(r)) => Ok(Value::Float(l * r)),
+        (Value::Integer(_), Op::Divide | Op::FloorDivide | Op::Modulo, Value::Integer(0)) => Err("Division by zero".to_string()),
+        // Only i64::MIN / -1 overflows
+        (Value::Integer(l), Op::Divide, Value::Integer(r)) => {
+            overflow.resolve(l.checked_div(r), || l.wrapping_div(r), || i64::MAX, "/")
+        }
+        (Value::Float(l), Op::Divide, Value::Float(r)) => Ok(Value::Float(l / r)),
+        (Value::String(l), Op::Add, Value::String(r)) => Ok(Value::String(l + &r)),
+        (Value::Integer(l), Op::FloorDivide, Value::Integer(r)) => {
+            overflow.resolve(floor_div(l, r), || l.wrapping_div(r), || i64::MAX, "//")
+        }
+        (Value::Float(l), Op::FloorDivide, Value::Float(r)) => Ok(Value::Float((l / r).floor())),
+        (Value::Integer(l), Op::Modulo, Value::Integer(r)) => {
+            // Only i64::MIN % -1 overflows, and its remainder is 0
+            Ok(Value::Integer(floor_div(l, r).map_or(0, |q| l - q * r)))
+        }
+        (Value::Float(l), Op::Modulo, Value::Float(r)) => Ok(Value::Float(l - r * (l / r).floor())),
+        (Value::Integer(l), Op::Power, Value::Integer(r)) => {
+            let exponent = u32::try_from(r).map_err(|_| format!("Integer exponent must be between 0 and {}, found {}", u32::MAX, r))?;
+            overflow.resolve(l.checked_pow(exponent), || l.wrapping_pow(exponent), || l.saturating_pow(exponent), "**")
+        }
+        (Value::Float(l), Op::Power, Value::Float(r)) => Ok(Value::Float(l.powf(r))),
+        (Value::Integer(l), Op::BitAnd, Value::Integer(r)) => Ok(Value::Integer(l & r)),
+        (Value::Integer(l), Op::BitOr, Value::Integer(r)) => Ok(Value::Integer(l | r)),
+        (Value::Integer(l), Op::BitXor, Value::Integer(r)) => Ok(Value::Integer(l ^ r)),
+        (Value::Integer(l), Op::ShiftLeft | Op::ShiftRight, Value::Integer(r)) => {
+            let amount = u32::try_from(r).ok().filter(|amount| *amount < 64)
+                .ok_or_else(|| format!("Shift amount must be between 0 and 63, found {}", r))?;
+            Ok(Value::Integer(if op == Op::ShiftLeft { l << amount } else { l >> amount }))
+        }
+        (left, op, right) => Err(operand_error(op, &left, &right)),
+    }
+}
+
+/// The error for operands that `op` does not work on, naming what it expects.
+fn operand_error(op: Op, left: &Value, right: &Value) -> String {
+    let (symbol, expected) = match op {
+        Op::Add => ("+", "two numbers or two strings"),
+        Op::Subtract => ("-", "numbers"),
+        Op::Multiply => ("*", "numbers"),
+        Op::Divide => ("/", "numbers"),
+        Op::FloorDivide => ("//", "numbers"),
+        Op::Modulo => ("%", "numbers"),
+        Op::Power => ("**", "numbers"),
+        Op::Greater => (">", "numbers"),
+        Op::Less => ("<", "numbers"),
+        Op::GreaterEqual => (">=", "numbers"),
+        Op::LessEqual => ("<=", "numbers"),
+        Op::BitAnd => ("&", "ints"),
+        Op::BitOr => ("|", "ints"),
+        Op::BitXor => ("^", "ints"),
+        Op::ShiftLeft => ("<<", "ints"),
+        Op::ShiftRight => (">>", "ints"),
+        other => unreachable!("{:?} is not a binary operator", other),
+    };
+    format!("'{}' expects {}, found {} and {}", symbol, expected, Type::of(left), Type::of(right))
+}
+
+/// `l op r` for an ordering operator, `None` for any other operator.
+fn compare<T: PartialOrd>(op: Op, l: &T, r: &T) -> Option<bool> {
+    match op {
+        Op::Greater => Some(l > r),
+        Op::Less => Some(l < r),
+        Op::GreaterEqual => Some(l >= r),
+        Op::LessEqual => Some(l <= r),
+        _ => None,
+    }
+}
+
+/// Integer division rounded towards negative infinity; `None` on overflow.
+fn floor_div(l: i64, r: i64) -> Option<i64> {
+    let q = l.checked_div(r)?;
+    Some(if l % r != 0 && (l < 0) != (r < 0) { q - 1 } else { q })
+}
+
+/// An int meeting a float becomes a float, so `speed * 2` works for a float `speed`.
+fn promote(left: Value, right: Value) -> (Value, Value) {
+    match (left, right) {
+        (Value::Integer(l), Value::Float(r)) => (Value::Float(l as f64), Value::Float(r)),
+        (Value::Float(l), Value::Integer(r)) => (Value::Float(l), Value::Float(r as f64)),
+        pair => pair,
+    }
+}
+
+/// `==` across ints and floats: `1 == 1.0` holds.
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Integer(i), Value::Float(f)) | (Value::Float(f), Value::Integer(i)) => int_equals_float(*i, *f),
+        _ => left == right,
+    }
+}
+
+/// Compares exactly: converting the int to `f64` would round large ones, so
+/// the float must be a whole number within `i64` range equal to the int.
+fn int_equals_float(i: i64, f: f64) -> bool {
+    f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 && f as i64 == i
+}
+
+fn to_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(n) => Some(*n as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn is_same_kind(old_value: &Value, new_value: &Value) -> bool {
+    match (old_value, new_value) {
+        (Value::StructInstance { type_name: old_type, .. }, Value::StructInstance { type_name: new_type, .. }) => {
+            old_type == new_type
+        }
+        (Value::Enum(old_value), Value::Enum(new_value)) => old_value.type_name == new_value.type_name,
+        // Optional variables switch between nil and a value
+        (Value::Nil, _) | (_, Value::Nil) => true,
+        _ => std::mem::discriminant(old_value) == std::mem::discriminant(new_value),
+    }
+}
+
+fn print_value(value: &Value) -> Result<(), String> {
+    match value {
+        Value::String(s) => print!("{} ", s),
+        Value::Integer(n) => print!("{} ", n),
+        Value::Float(f) => print!("{} ", f),
+        Value::Boolean(b) => print!("{} ", b),
+        Value::List(elements) => {
+            for (i, element) in elements.iter().enumerate() {
+                print_value(element)?;
+                if i < elements.len() - 1 {
+                    print!(", ");
+                }
+            }
+        }
+        Value::Map(entries) => {
+            print!("{{ ");
+            for (i, (key, value)) in entries.iter().enumerate() {
+                print!("{}: ", key);
+                print_value(value)?;
+                if i < entries.len() - 1 {
+                    print!(", ");
+                }
+            }
+            print!("}}");
+        }
+        Value::StructInstance { type_name, fields } => {
+            print!("{} {{ ", type_name);
+            for (i, (field_name, field_val)) in fields.iter().enumerate() {
+                print!("{}: ", field_name);
+                print_value(field_val)?;
+                if i < fields.len() - 1 {
+                    print!(", ");
+                }
+            }
+            print!("}}");
+        }
+        Value::Enum(_) | Value::Function(_) | Value::NativeFunction(_) => print!("{} ", value),
+        Value::Nil => print!("nil "),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Overflow, WolfEngine, WolfError, Value};
+
+    fn ints(values: &[i64]) -> Option<Vec<Value>> {
+        Some(values.iter().map(|n| Value::Integer(*n)).collect())
+    }
+
+    fn strings(values: &[&str]) -> Option<Vec<Value>> {
+        Some(values.iter().map(|s| Value::String(s.to_string())).collect())
+    }
+
+    #[test]
+    fn test_struct() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Point
+                x: int
+                y: int
+            end
+
+            let p: Point = Point(10, 20)
+            print p
+        "#).unwrap();
+    }
+
+    #[test]
+    fn test_impl() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Point
+                x: int
+                y: int
+            end
+
+            impl Point
+                fn get_x()
+                    return self.x
+                end
+            end
+
+            let p: Point = Point(10, 20)
+            let result: int = p.get_x()
+            print result
+        "#).unwrap();
+    }
+
+    #[test]
+    fn methods_write_self_back() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Counter
+                n: int
+            end
+
+            impl Counter
+                fn bump(by: int)
+                    self.n = self.n + by
+                end
+            end
+
+            let counter: Counter = Counter(10)
+            for int i = 0 range 3
+                counter.bump(i)
+            end
+            let total: int = counter.n
+        "#).unwrap();
+        assert_eq!(engine.get_int("total"), Some(13));
+    }
+
+    #[test]
+    fn loops_and_recursion() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn fib(n: int) -> int
+                if n < 2
+                    return n
+                end
+                return fib(n - 1) + fib(n - 2)
+            end
+
+            let sum: int = 0
+            let i: int = 0
+            while i < 10
+                sum = sum + fib(i)
+                i = i + 1
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_int("sum"), Some(88));
+    }
+
+    #[test]
+    fn runaway_recursion_is_an_error() {
+        let mut engine = WolfEngine::new();
+        let result = engine.run(r#"
+            fn down(n: int) -> int
+                return down(n + 1)
+            end
+            let x: int = down(0)
+        "#);
+        assert!(result.unwrap_err().to_string().contains("Stack overflow"));
+        // The engine stays usable after the failed run.
+        engine.run("let y: int = 1").unwrap();
+        assert_eq!(engine.get_value("y"), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn runaway_recursion_through_callbacks_is_an_error() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn down(n: int) -> list<int>
+                return [n].map(fn(x: int) -> int return down(x + 1).len() end)
+            end
+            let caught: string = ""
+            try
+                down(0)
+            catch message
+                caught = message
+            end
+        "#).unwrap();
+        assert!(engine.get_str("caught").unwrap().contains("Stack overflow"));
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let odd_sum: int = 0
+            for int i = 0 range 100
+                if i == 10
+                    break
+                end
+                if i == 2 * (i / 2)
+                    continue
+                end
+                odd_sum = odd_sum + i
+            end
+
+            let pairs: int = 0
+            let n: int = 0
+            outer: while n < 5
+                n = n + 1
+                for int j = 0 range 5
+                    if j == n
+                        continue outer
+                    end
+                    if n == 4
+                        break outer
+                    end
+                    pairs = pairs + 1
+                end
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_int("odd_sum"), Some(25));
+        assert_eq!(engine.get_int("pairs"), Some(6));
+        assert_eq!(engine.get_int("n"), Some(4));
+
+        let error = engine.run("break").unwrap_err().to_string();
+        assert!(error.contains("'break' outside of a loop"));
+        let error = engine.run("while true\ncontinue inner\nend").unwrap_err().to_string();
+        assert!(error.contains("No enclosing loop is labelled 'inner'"));
+    }
+
+    #[test]
+    fn maps() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let stock: map<string, int> = { "arrows": 20, "potions": 3 }
+            stock["gold"] = 15
+            stock["arrows"] = stock["arrows"] - 5
+            let potions: int = stock.remove("potions")
+            let has_gold: bool = stock.has("gold")
+            let has_potions: bool = stock.has("potions")
+            let count: int = stock.len()
+            let names: list<string> = stock.keys()
+            let amounts: list<int> = stock.values()
+
+            let flags: map<int, list<bool>> = {}
+            flags[7] = [false, false]
+            flags[7][1] = true
+        "#).unwrap();
+        assert_eq!(engine.get_int("potions"), Some(3));
+        assert_eq!(engine.get_bool("has_gold"), Some(true));
+        assert_eq!(engine.get_bool("has_potions"), Some(false));
+        assert_eq!(engine.get_int("count"), Some(2));
+        assert_eq!(engine.get_list("names"), Some(vec![Value::String("arrows".to_string()), Value::String("gold".to_string())]));
+        assert_eq!(engine.get_list("amounts"), Some(vec![Value::Integer(15), Value::Integer(15)]));
+        assert_eq!(engine.get_map("flags"), Some(vec![(Value::Integer(7), Value::List(vec![Value::Boolean(false), Value::Boolean(true)]))]));
+
+        let error = engine.run("print stock[\"rope\"]").unwrap_err().to_string();
+        assert!(error.contains("Key \"rope\" not found in map"));
+    }
+
+    #[test]
+    fn maps_compare_by_entries() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let a: map<string, int> = { "x": 1, "y": 2 }
+            let b: map<string, int> = { "y": 2, "x": 1 }
+            let same: bool = a == b
+        "#).unwrap();
+        assert_eq!(engine.get_bool("same"), Some(true));
+    }
+
+    #[test]
+    fn operators_and_precedence() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let modulo: list<int> = [7 % 3, -7 % 3, 7 % -3]
+            let floor: list<int> = [7 // 2, -7 // 2]
+            let power: int = 2 ** 3 ** 2
+            let negated: int = -2 ** 2
+            let bits: list<int> = [6 & 3, 6 | 3, 6 ^ 3, ~5, 1 << 4, 256 >> 2]
+            let mixed: int = 1 + 2 * 3 - 10 % 4
+            let masked: bool = 5 & 1 == 1
+            let zero: bool = power - 512 == 0
+
+            let grid: list<list<int>> = [[1, 2]]
+            grid[0][1] += 40
+            let bumped: int = grid[0][1] + 1
+            let n: int = 10
+            n -= 3
+            n *= 2
+            n %= 5
+
+            struct Counter
+                hits: int
+            end
+            let c: Counter = Counter(1)
+            c.hits += 4
+        "#).unwrap();
+        assert_eq!(engine.get_list("modulo"), ints(&[1, 2, -2]));
+        assert_eq!(engine.get_list("floor"), ints(&[3, -4]));
+        assert_eq!(engine.get_int("power"), Some(512));
+        assert_eq!(engine.get_int("negated"), Some(-4));
+        assert_eq!(engine.get_list("bits"), ints(&[2, 7, 5, -6, 16, 64]));
+        assert_eq!(engine.get_int("mixed"), Some(5));
+        assert_eq!(engine.get_bool("masked"), Some(true));
+        assert_eq!(engine.get_bool("zero"), Some(true));
+        assert_eq!(engine.get_int("bumped"), Some(43));
+        assert_eq!(engine.get_int("n"), Some(4));
+
+        let error = engine.run("print 1 % 0").unwrap_err().to_string();
+        assert!(error.contains("Division by zero"));
+        let error = engine.run("print 1 << 64").unwrap_err().to_string();
+        assert!(error.contains("Shift amount must be between 0 and 63, found 64"));
+    }
+
+    #[test]
+    fn division_by_zero_is_a_runtime_error() {
+        let mut engine = WolfEngine::new();
+        let error = engine.run_source("physics.wolf", "let speed: int = 10\nlet time: int = 0\nprint speed / time\n").unwrap_err();
+        assert!(matches!(error, WolfError::Runtime { .. }));
+        assert!(error.to_string().starts_with("Runtime Error: Division by zero\n --> physics.wolf:3:13"));
+
+        // Floats follow IEEE 754 instead
+        engine.run("let inf: float = 1.0 / 0.0").unwrap();
+        assert_eq!(engine.get_float("inf"), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn integer_overflow_modes() {
+        let script = r#"
+            let big: int = 9223372036854775807
+            let sum: int = big + 1
+            let product: int = big * -2
+            let negated: int = -(-big - 1)
+        "#;
+
+        let mut engine = WolfEngine::new();
+        let error = engine.run(script).unwrap_err().to_string();
+        assert!(error.contains("Integer overflow in '+'"));
+
+        let mut engine = WolfEngine::new();
+        engine.set_overflow(Overflow::Wrap);
+        engine.run(script).unwrap();
+        assert_eq!(engine.get_int("sum"), Some(i64::MIN));
+        assert_eq!(engine.get_int("product"), Some(2));
+        assert_eq!(engine.get_int("negated"), Some(i64::MIN));
+
+        let mut engine = WolfEngine::new();
+        engine.set_overflow(Overflow::Saturate);
+        engine.run(script).unwrap();
+        assert_eq!(engine.get_int("sum"), Some(i64::MAX));
+        assert_eq!(engine.get_int("product"), Some(i64::MIN));
+        assert_eq!(engine.get_int("negated"), Some(i64::MAX));
+    }
+
+    #[test]
+    fn numeric_promotion_and_casts() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let speed: float = 2.5
+            let steps: int = 3
+            let distance: float = speed * steps + 1
+            let halves: list<float> = [7 / 2.0, 7 // 2.0, 2 ** 0.5 * 0]
+            let same: bool = 1 == 1.0
+            let whole: int = distance as int
+            let ratio: float = steps as float / 2
+            let parsed: int = int(" 42 ") + int(-3.9)
+            let label: string = string(3.5) + "m " + steps as string
+        "#).unwrap();
+        assert_eq!(engine.get_float("distance"), Some(8.5));
+        assert_eq!(engine.get_list("halves"), Some(vec![Value::Float(3.5), Value::Float(3.0), Value::Float(0.0)]));
+        assert_eq!(engine.get_bool("same"), Some(true));
+        assert_eq!(engine.get_int("whole"), Some(8));
+        assert_eq!(engine.get_float("ratio"), Some(1.5));
+        assert_eq!(engine.get_int("parsed"), Some(39));
+        assert_eq!(engine.get_str("label"), Some("3.5m 3".to_string()));
+
+        let error = engine.run_source("input.wolf", "let n: int = int(\"forty\")\n").unwrap_err();
+        assert!(matches!(error, WolfError::Runtime { .. }));
+        assert!(error.to_string().starts_with("Runtime Error: Cannot convert \"forty\" to int\n --> input.wolf:1:14"));
+        let error = engine.run("print (10.0 ** 30) as int").unwrap_err().to_string();
+        assert!(error.contains("Cannot convert 1000000000000000000000000000000 to int"));
+    }
+
+    #[test]
+    fn large_ints_compare_exactly() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let above: bool = 9007199254740993 > 9007199254740992
+            let max: int = 9223372036854775807
+            let below: bool = max - 1 < max
+            let at_most: bool = max <= max - 1
+            let mixed: bool = 2 < 2.5
+            let equal: list<bool> = [2 == 2.0, 2.5 == 2, 9007199254740993 == 9007199254740992.0, max == 9223372036854775807.0]
+        "#).unwrap();
+        assert_eq!(engine.get_bool("above"), Some(true));
+        assert_eq!(engine.get_bool("below"), Some(true));
+        assert_eq!(engine.get_bool("at_most"), Some(false));
+        assert_eq!(engine.get_bool("mixed"), Some(true));
+        assert_eq!(engine.get_list("equal"), Some(vec![
+            Value::Boolean(true), Value::Boolean(false), Value::Boolean(false), Value::Boolean(false),
+        ]));
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Player
+                name: string
+                hp: int
+            end
+            let player: Player = Player("Ulf", 7)
+            let line: string = "{player.name} has {player.hp * 2} HP {"and {[1, 2]}"}\n"
+            let dialogue: string = """
+                "Halt!" said {player.name}.
+                  Nobody moved.
+                """
+        "#).unwrap();
+        assert_eq!(engine.get_str("line"), Some("Ulf has 14 HP and [1, 2]\n".to_string()));
+        assert_eq!(engine.get_str("dialogue"), Some("\"Halt!\" said Ulf.\n  Nobody moved.".to_string()));
+
+        // Host values are only known at runtime
+        engine.push_fn("half", |_| Value::Float(1.5));
+        let error = engine.run(r#"print "{~half()}""#).unwrap_err().to_string();
+        assert!(error.contains("'~' expects int, found float"));
+        let error = engine.run(r#"print "{half() + "s"}""#).unwrap_err().to_string();
+        assert!(error.contains("'+' expects two numbers or two strings, found float and string"));
+    }
+
+    #[test]
+    fn string_methods() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let line: string = "  Take Öl Sword "
+            let clean: string = line.trim()
+            let lowered: string = clean.lower()
+            let words: list<string> = lowered.split(" ")
+            let facts: list<bool> = [clean.contains("Öl"), clean.starts_with("Take"), clean.ends_with("x")]
+            let found: list<int> = [clean.len(), clean.find("Sword"), clean.find("axe")]
+            let parts: list<string> = [clean.upper(), clean.replace(" ", "_"), clean.substring(5, 7), clean[5], words[1][0]]
+            let letters: list<string> = lowered.chars()
+            let bar: string = "=-"
+            let joined: string = words.join(", ") + bar.repeat(2)
+        "#).unwrap();
+        assert_eq!(engine.get_list("words"), strings(&["take", "öl", "sword"]));
+        assert_eq!(engine.get_list("facts"), Some(vec![Value::Boolean(true), Value::Boolean(true), Value::Boolean(false)]));
+        assert_eq!(engine.get_list("found"), Some(vec![Value::Integer(13), Value::Integer(8), Value::Integer(-1)]));
+        assert_eq!(engine.get_list("parts"), strings(&["TAKE ÖL SWORD", "Take_Öl_Sword", "Öl", "Ö", "ö"]));
+        assert_eq!(engine.get_list("letters").map(|letters| letters.len()), Some(13));
+        assert_eq!(engine.get_str("joined"), Some("take, öl, sword=-=-".to_string()));
+
+        let error = engine.run("print clean.substring(3, 99)").unwrap_err().to_string();
+        assert!(error.contains("Substring range 3..99 is out of bounds for length 13"));
+        let error = engine.run("print clean[13]").unwrap_err().to_string();
+        assert!(error.contains("Index out of bounds! Len: 13, Index: 13"));
+        let error = engine.run("print \"abc\".repeat(9223372036854775807)").unwrap_err().to_string();
+        assert!(error.contains("Repeat result too large"));
+        engine.run(r#"
+            let caught: string = ""
+            try
+                print bar.repeat(1000000000)
+            catch err
+                caught = err
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_str("caught"), Some("Repeat result too large".to_string()));
+    }
+
+    #[test]
+    fn list_methods_slices_and_negative_indices() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let xs: list<int> = [5, 3, 9, 1]
+            let ends: list<int> = [xs[-1], xs[-4], xs.first(), xs.last()]
+            let middle: list<int> = xs[1:3]
+            let tail: list<int> = xs[-2:]
+            let head: list<int> = xs[:-1]
+            xs.sort()
+            let sorted: list<int> = xs[:]
+            xs.reverse()
+            xs.insert(0, 42)
+            xs.insert(-1, 7)
+            let removed: int = xs.remove(-1)
+            xs.extend([8, 8])
+            xs[-1] = 0
+            let found: list<int> = [xs.index_of(3), xs.index_of(100)]
+            let has: bool = xs.contains(9)
+            let part: list<int> = xs.slice(1, -1)
+            let word: string = "wölfe"
+            let letters: list<string> = [word[-1], word[1:3], word[:-2]]
+            let names: list<string> = ["b", "a", "C"]
+            names.sort()
+            let mixed: list<float> = [2.5, -1.0, 0.5]
+            mixed.sort()
+            let emptied: list<int> = [1, 2]
+            emptied.clear()
+        "#).unwrap();
+        assert_eq!(engine.get_list("ends"), ints(&[1, 5, 5, 1]));
+        assert_eq!(engine.get_list("middle"), ints(&[3, 9]));
+        assert_eq!(engine.get_list("tail"), ints(&[9, 1]));
+        assert_eq!(engine.get_list("head"), ints(&[5, 3, 9]));
+        assert_eq!(engine.get_list("sorted"), ints(&[1, 3, 5, 9]));
+        assert_eq!(engine.get_int("removed"), Some(1));
+        assert_eq!(engine.get_list("xs"), ints(&[42, 9, 5, 3, 7, 8, 0]));
+        assert_eq!(engine.get_list("found"), ints(&[3, -1]));
+        assert_eq!(engine.get_bool("has"), Some(true));
+        assert_eq!(engine.get_list("part"), ints(&[9, 5, 3, 7, 8]));
+        assert_eq!(engine.get_list("letters"), strings(&["e", "öl", "wöl"]));
+        assert_eq!(engine.get_list("names"), strings(&["C", "a", "b"]));
+        assert_eq!(engine.get_list("mixed"), Some(vec![Value::Float(-1.0), Value::Float(0.5), Value::Float(2.5)]));
+        assert_eq!(engine.get_list("emptied"), Some(vec![]));
+
+        let error = engine.run("print xs[-8]").unwrap_err().to_string();
+        assert!(error.contains("Index out of bounds! Len: 7, Index: -8"));
+        let error = engine.run("print xs[5:]\nprint xs[3:1]").unwrap_err().to_string();
+        assert!(error.contains("Slice 3:1 is out of bounds for length 7"));
+        let error = engine.run("print emptied.first()").unwrap_err().to_string();
+        assert!(error.contains("Cannot take the first element of an empty list"));
+    }
+
+    #[test]
+    fn place_expressions_and_chained_calls() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Counter
+                count: int
+            end
+            impl Counter
+                fn bump() -> int
+                    self.count += 1
+                    return self.count
+                end
+            end
+            struct Bag
+                owner: Counter
+                items: list<string>
+            end
+            impl Bag
+                fn take(at: int) -> string
+                    return self.items[at]
+                end
+                fn copy() -> Bag
+                    return self
+                end
+            end
+            fn new_bag() -> Bag
+                return Bag(Counter(0), ["axe", "rope"])
+            end
+
+            let counters: list<Counter> = [Counter(0), Counter(10)]
+            counters[1].bump()
+            let bumped: int = counters[1].bump()
+            let bags: list<Bag> = [new_bag()]
+            bags[0].owner.count = 5
+            bags[0].owner.bump()
+            bags[0].items[1] = "torch"
+            bags[0].items.push("map")
+            bags[0].owner.count *= 2
+            let owner: int = bags[0].owner.count
+            let items: list<string> = bags[0].items
+            let taken: list<string> = [new_bag().take(1), bags[0].take(-1), bags[0].copy().take(0).upper()]
+            let nested: map<string, list<int>> = {"a": [1, 2]}
+            nested["a"].push(4)
+            let literals: list<string> = ["wolf".upper(), "abc"[-1]]
+            let sizes: list<int> = [[3, 1, 2][1:].len(), nested["a"].len(), new_bag().owner.count]
+        "#).unwrap();
+        assert_eq!(engine.get_int("bumped"), Some(12));
+        assert_eq!(engine.get_int("owner"), Some(12));
+        assert_eq!(engine.get_list("items"), strings(&["axe", "torch", "map"]));
+        assert_eq!(engine.get_list("taken"), strings(&["rope", "map", "AXE"]));
+        assert_eq!(engine.get_map("nested"), Some(vec![(Value::String("a".to_string()), Value::List(vec![
+            Value::Integer(1), Value::Integer(2), Value::Integer(4),
+        ]))]));
+        assert_eq!(engine.get_list("literals"), strings(&["WOLF", "c"]));
+        assert_eq!(engine.get_list("sizes"), ints(&[2, 3, 0]));
+
+        let error = engine.run("new_bag().owner = Counter(1)").unwrap_err().to_string();
+        assert!(error.contains("Cannot assign to a field of a temporary value"));
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_the_place_once() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Slot
+                hp: int
+            end
+            let calls: int = 0
+            fn next() -> int
+                calls += 1
+                return calls - 1
+            end
+            let counts: list<int> = [10, 20, 30]
+            counts[next()] += 5
+            let grid: list<list<int>> = [[1, 2, 3], [4, 5, 6]]
+            grid[next()][next()] *= 10
+            let slots: list<Slot> = [Slot(1), Slot(2), Slot(3), Slot(4)]
+            slots[next()].hp -= 1
+            let words: map<string, string> = {"a": "x"}
+            words["a"] += "y"
+        "#).unwrap();
+        assert_eq!(engine.get_int("calls"), Some(4));
+        assert_eq!(engine.get_list("counts"), ints(&[15, 20, 30]));
+        assert_eq!(engine.get_list("grid"), Some(vec![
+            Value::List(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]),
+            Value::List(vec![Value::Integer(4), Value::Integer(5), Value::Integer(60)]),
+        ]));
+        assert_eq!(engine.get_value("slots").map(|slots| slots.to_string()), Some("[Slot { hp: 1 }, Slot { hp: 2 }, Slot { hp: 3 }, Slot { hp: 3 }]".to_string()));
+        assert_eq!(engine.get_map("words"), Some(vec![(Value::String("a".to_string()), Value::String("xy".to_string()))]));
+    }
+
+    #[test]
+    fn function_values_and_closures() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            fn make_adder(n: int) -> fn(int) -> int
+                return fn(x: int) -> int return x + n end
+            end
+            fn twice(f: fn(int) -> int, x: int) -> int
+                return f(f(x))
+            end
+            fn inc(x: int) -> int
+                return x + 1
+            end
+            fn nested(base: int) -> int
+                let curry: fn(int) -> fn(int) -> int = fn(a: int) -> fn(int) -> int
+                    return fn(b: int) -> int return base + a + b end
+                end
+                return curry(10)(1)
+            end
+            struct Button
+                label: string
+                on_click: fn(string) -> string
+            end
+
+            let add5: fn(int) -> int = make_adder(5)
+            let calls: list<int> = [add5(1), twice(add5, 0), twice(inc, 0), make_adder(2)(3), nested(100)]
+
+            let handlers: list<fn(int) -> int> = [inc, add5, fn(x: int) -> int return x * 10 end]
+            let handled: list<int> = []
+            for handler in handlers
+                handled.push(handler(2))
+            end
+
+            let squares: list<fn() -> int> = []
+            for i in range 0, 3
+                squares.push(fn() -> int return i * i end)
+            end
+            let captured: list<int> = [squares[0](), squares[1](), squares[2]()]
+
+            let ok: Button = Button("ok", fn(who: string) -> string return "clicked by {who}" end)
+            let clicked: string = ok.on_click("me")
+            let shown: string = "{inc} {add5}"
+        "#).unwrap();
+        assert_eq!(engine.get_list("calls"), ints(&[6, 10, 2, 5, 111]));
+        assert_eq!(engine.get_list("handled"), ints(&[3, 7, 20]));
+        assert_eq!(engine.get_list("captured"), ints(&[0, 1, 4]));
+        assert_eq!(engine.get_str("clicked"), Some("clicked by me".to_string()));
+        assert_eq!(engine.get_str("shown"), Some("<fn inc> <lambda>".to_string()));
+
+        // Captures are copies: changing one inside the lambda does not leak out.
+        engine.run(r#"
+            fn counter() -> int
+                let count: int = 0
+                let bump: fn() -> int = fn() -> int
+                    count = count + 1
+                    return count
+                end
+                bump()
+                return count * 10 + bump()
+            end
+            let counted: int = counter()
+        "#).unwrap();
+        assert_eq!(engine.get_int("counted"), Some(1));
+    }
+
+    #[test]
+    fn higher_order_list_methods() {
+        let mut engine = WolfEngine::new();
+        engine.push_fn("triple", |args| match args.first() {
+            Some(Value::Integer(n)) => Value::Integer(n * 3),
+            _ => Value::Nil,
+        });
+        engine.run(r#"
+            struct Unit
+                name: string
+                hp: int
+            end
+            fn is_even(n: int) -> bool
+                return n % 2 == 0
+            end
+
+            let xs: list<int> = [5, 3, 8, 1, 4]
+            let doubled: list<int> = xs.map(fn(x: int) -> int return x * 2 end)
+            let tripled: list<int> = xs.map(triple)
+            let even: list<int> = xs.filter(is_even)
+            let total: int = xs.reduce(0, fn(sum: int, x: int) -> int return sum + x end)
+            let checks: list<bool> = [
+                xs.any(fn(x: int) -> bool return x > 7 end),
+                xs.all(fn(x: int) -> bool return x > 1 end),
+                [].all(is_even)
+            ]
+            let found: int = xs.find(fn(x: int) -> bool return x < 4 end) ?? -1
+            let labels: list<string> = xs.filter(is_even).map(string)
+
+            let units: list<Unit> = [Unit("a", 3), Unit("b", 1), Unit("c", 3), Unit("d", 2)]
+            units.sort_by(fn(l: Unit, r: Unit) -> bool return l.hp < r.hp end)
+            let order: string = units.map(fn(u: Unit) -> string return u.name end).join("")
+            xs.sort_by(fn(a: int, b: int) -> bool return a > b end)
+
+            let pairs: string = ""
+            for pair in ["x", "y"].enumerate()
+                pairs += "{pair[0]}{pair[1]} "
+            end
+            let zipped: string = "{[1, 2, 3].zip(["a", "b"])}"
+        "#).unwrap();
+        assert_eq!(engine.get_list("doubled"), ints(&[10, 6, 16, 2, 8]));
+        assert_eq!(engine.get_list("tripled"), ints(&[15, 9, 24, 3, 12]));
+        assert_eq!(engine.get_list("even"), ints(&[8, 4]));
+        assert_eq!(engine.get_int("total"), Some(21));
+        assert_eq!(engine.get_list("checks"), Some(vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true)]));
+        assert_eq!(engine.get_int("found"), Some(3));
+        assert_eq!(engine.get_list("labels"), strings(&["8", "4"]));
+        assert_eq!(engine.get_str("order"), Some("bdac".to_string()));
+        assert_eq!(engine.get_list("xs"), ints(&[8, 5, 4, 3, 1]));
+        assert_eq!(engine.get_str("pairs"), Some("0x 1y ".to_string()));
+        assert_eq!(engine.get_str("zipped"), Some("[[1, a], [2, b]]".to_string()));
+
+        let error = engine.run("let odd: list<int> = xs.filter(triple)").unwrap_err().to_string();
+        assert!(error.contains("The function passed to 'filter' must return bool, found int"));
+    }
+
+    #[test]
+    fn nil_and_options() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            struct Unit
+                name: string
+                hp: int
+                pet: option<Unit>
+            end
+            impl Unit
+                fn heal(amount: int)
+                    self.hp += amount
+                end
+            end
+
+            let hero: option<Unit> = Unit("ana", 10, Unit("rex", 3, nil))
+            let nobody: option<Unit> = nil
+            hero?.heal(5)
+            nobody?.heal(5)
+            let hp: int = hero?.hp ?? 0
+            let missing: int = nobody?.hp ?? -1
+            let pet: string = hero?.pet?.name ?? "none"
+            let no_pet: string = nobody?.pet?.name ?? "none"
+            let shown: string = "{nobody?.name}"
+
+            let big: option<int> = [1, 2, 3].find(fn(x: int) -> bool return x > 5 end)
+            let fallback: int = big ?? nil ?? 7
+            let narrowed: int = 0
+            if hero != nil
+                narrowed = hero.hp * 2
+                hero = nil
+            end
+            let cleared: bool = hero == nil
+            let slots: list<option<int>> = [nil, 4]
+            let filled: int = slots[1] ?? 0
+        "#).unwrap();
+        assert_eq!(engine.get_int("hp"), Some(15));
+        assert_eq!(engine.get_int("missing"), Some(-1));
+        assert_eq!(engine.get_str("pet"), Some("rex".to_string()));
+        assert_eq!(engine.get_str("no_pet"), Some("none".to_string()));
+        assert_eq!(engine.get_str("shown"), Some("nil".to_string()));
+        assert_eq!(engine.get_int("fallback"), Some(7));
+        assert_eq!(engine.get_int("narrowed"), Some(30));
+        assert_eq!(engine.get_bool("cleared"), Some(true));
+        assert_eq!(engine.get_int("filled"), Some(4));
+
+        let error = engine.run("let empty: list<int> = []\nempty.pop()").unwrap_err().to_string();
+        assert!(error.contains("Cannot pop from an empty list"));
+        let error = engine.run("fn nothing()\nend\nprint nothing() + 1").unwrap_err().to_string();
+        assert!(error.contains("Cannot use nil in arithmetic or comparisons (found nil and 1)"));
+        engine.push_fn("lookup", |_| Value::Nil);
+        let error = engine.run("print lookup().name").unwrap_err().to_string();
+        assert!(error.contains("Cannot read field 'name' of nil"));
+    }
+
+    #[test]
+    fn try_catch_recovers_from_errors() {
+        let mut engine = WolfEngine::new();
+        engine.push_fallible_fn("open", |args| match args.first() {
+            Some(Value::String(path)) if path == "save.txt" => Ok(Value::String("level 3".to_string())),
+            Some(path) => Err(format!("No such file: {}", path)),
+            None => Err("open() needs a path".to_string()),
+        });
+        engine.run(r#"
+            fn parse(text: string) -> int
+                try
+                    return int(text)
+                catch
+                    return -1
+                end
+            end
+
+            fn check(n: int) -> int
+                if n < 0
+                    error("negative: {n}")
+                end
+                return n
+            end
+
+            let parsed: int = parse("12") + parse("abc")
+            let loaded: string = ""
+            try
+                loaded = open("save.txt") + open("missing.txt")
+            catch err
+                loaded = err
+            end
+
+            let raised: string = ""
+            try
+                check(-3)
+                raised = "unreached"
+            catch err
+                raised = err
+            end
+
+            let total: int = 0
+            for i in range 0, 5
+                try
+                    if i == 2
+                        continue
+                    end
+                    total += check(i)
+                catch
+                    total = -100
+                end
+            end
+
+            let rethrown: string = ""
+            try
+                try
+                    error("inner")
+                catch err
+                    error("outer after {err}")
+                end
+            catch err
+                rethrown = err
+            end
+
+            let from_callback: string = ""
+            try
+                [1, 2].map(fn(x: int) -> int return check(0 - x) end)
+            catch err
+                from_callback = err
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_int("parsed"), Some(11));
+        assert_eq!(engine.get_str("loaded"), Some("No such file: missing.txt".to_string()));
+        assert_eq!(engine.get_str("raised"), Some("negative: -3".to_string()));
+        assert_eq!(engine.get_int("total"), Some(8));
+        assert_eq!(engine.get_str("rethrown"), Some("outer after inner".to_string()));
+        assert_eq!(engine.get_str("from_callback"), Some("negative: -1".to_string()));
+
+        // Blocks left by return, break or an earlier error no longer catch anything
+        let error = engine.run(r#"
+            for i in range 0, 3
+                try
+                    break
+                catch
+                end
+            end
+            parse("1")
+            error("left uncaught")
+        "#).unwrap_err().to_string();
+        assert!(error.contains("Runtime Error: left uncaught"));
+        let error = engine.run("print open(\"nope\")").unwrap_err().to_string();
+        assert!(error.contains("No such file: nope"));
+    }
+
+    #[test]
+    fn enums_and_match() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            enum State
+                Idle
+                Walking(speed: float)
+                Attacking(target: string, damage: int)
+            end
+
+            impl State
+                fn describe() -> string
+                    match self
+                        case Idle
+                            return "idle"
+                        case Walking(speed)
+                            return "walking at {speed}"
+                        case State.Attacking(target, _)
+                            return "attacking {target}"
+                    end
+                end
+            end
+
+            let states: list<State> = [State.Idle, State.Walking(1.5), State.Attacking("wolf", 3)]
+            let described: list<string> = states.map(fn(s: State) -> string return s.describe() end)
+            let shown: string = "{states[1]} {states[0]}"
+            let same: bool = State.Attacking("wolf", 3) == states[2]
+
+            let damage: int = 0
+            for s in states
+                match s
+                    case Attacking(_, amount)
+                        damage += amount
+                    case _
+                end
+            end
+
+            let codes: list<string> = []
+            for n in [1, -2, 7]
+                match n
+                    case 1
+                        codes.push("one")
+                    case -2
+                        codes.push("minus two")
+                    case _
+                        codes.push("other")
+                end
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_list("described"), strings(&["idle", "walking at 1.5", "attacking wolf"]));
+        assert_eq!(engine.get_str("shown"), Some("State.Walking(1.5) State.Idle".to_string()));
+        assert_eq!(engine.get_bool("same"), Some(true));
+        assert_eq!(engine.get_int("damage"), Some(3));
+        assert_eq!(engine.get_list("codes"), strings(&["one", "minus two", "other"]));
+
+        // Values from the host are not typed, so their match is only checked when it runs
+        engine.push_fn("echo", |args| args.first().cloned().unwrap_or(Value::Nil));
+        let error = engine.run(r#"
+            match echo(State.Walking(2.5))
+                case State.Idle
+                    print "idle"
+            end
+        "#).unwrap_err().to_string();
+        assert!(error.contains("No case matches State.Walking(2.5)"));
+    }
+
+    #[test]
+    fn for_each_and_stepped_ranges() {
+        let mut engine = WolfEngine::new();
+        engine.run(r#"
+            let total: int = 0
+            let weights: list<int> = [3, 4, 5]
+            for w in weights
+                weights.push(w)
+                total = total + w
+            end
+
+            let positions: int = 0
+            for i, w in weights
+                positions = positions + i
+            end
+
+            let letters: string = ""
+            for ch in "wolf"
+                letters = ch + letters
+            end
+
+            let stock: map<string, int> = { "arrows": 20, "gold": 3 }
+            let summary: string = ""
+            for item, count in stock
+                if count > 5
+                    summary = summary + item
+                end
+            end
+            let keys: int = 0
+            for item in stock
+                keys = keys + 1
+            end
+
+            let evens: int = 0
+            for int i = 0 range 10, 2
+                evens = evens + i
+            end
+            let countdown: list<int> = []
+            for i in range 3, 0, -1
+                countdown.push(i)
+            end
+        "#).unwrap();
+        assert_eq!(engine.get_int("total"), Some(12));
+        assert_eq!(engine.get_int("positions"), Some(15));
+        assert_eq!(engine.get_str("letters"), Some("flow".to_string()));
+        assert_eq!(engine.get_str("summary"), Some("arrows".to_string()));
+        assert_eq!(engine.get_int("keys"), Some(2));
+        assert_eq!(engine.get_int("evens"), Some(20));
+        assert_eq!(engine.get_list("countdown"), Some(vec![Value::Integer(3), Value::Integer(2), Value::Integer(1)]));
+
+        let error = engine.run("for i in range 0, 3, 0\nend").unwrap_err().to_string();
+        assert!(error.contains("Range step cannot be zero"));
+    }
+
+    #[test]
+    fn failed_imports_are_not_cached() {
+        let path = std::env::temp_dir().join("wolf_retried_module.wolf");
+        std::fs::write(&path, "fn one() -> int\n    return missing\nend\n").unwrap();
+        let source = format!("import \"{}\" as m\nlet n: int = m.one()\n", path.to_str().unwrap());
+
+        let mut engine = WolfEngine::new();
+        assert!(engine.run(&source).is_err());
+
+        std::fs::write(&path, "fn one() -> int\n    return 1\nend\n").unwrap();
+        engine.run(&source).unwrap();
+        assert_eq!(engine.get_int("n"), Some(1));
+    }
+
+    #[test]
+    fn traces_through_methods_and_modules() {
+        let path = std::env::temp_dir().join("wolf_trace_module.wolf");
+        std::fs::write(&path, "\
+struct Bag
+    items: list<int>
+end
+
+impl Bag
+    fn take(at: int) -> int
+        let items: list<int> = self.items
+        return items[at]
+    end
+end
+
+fn pick(at: int) -> int
+    let bag: Bag = Bag([1, 2])
+    return bag.take(at)
+end
+").unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let mut engine = WolfEngine::new();
+        let source = format!("import \"{}\" as loot\nprint loot.pick(0)\nprint loot.pick(5)\n", path);
+        let error = engine.run_source("main.wolf", &source).unwrap_err();
+
+        let WolfError::Runtime { trace, .. } = &error else {
+            panic!("expected a runtime error, got {:?}", error);
+        };
+        let frames: Vec<(&str, Option<&str>, String)> = trace.iter()
+            .map(|frame| (frame.function.as_str(), frame.module.as_deref(), format!("{}:{}", frame.span.file.name, frame.span.line)))
+            .collect();
+        assert_eq!(frames, [
+            ("loot::Bag.take", Some("loot"), format!("{}:8", path)),
+            ("loot::pick", Some("loot"), format!("{}:14", path)),
+            ("<script>", None, "main.wolf:3".to_string()),
+        ]);
+        assert!(error.to_string().contains(&format!("    in loot::pick at {}:14:16", path)));
+    }
+}